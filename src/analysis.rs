@@ -0,0 +1,112 @@
+//! Compares the sets of paths that programs match
+//!
+//! Each program is turned into an automaton that mirrors the matcher (see [`automaton`]), and
+//! pairs of them are explored together, breadth first, so any example path found is as short as
+//! possible.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    path::PathBuf,
+};
+
+use crate::compiler::Program;
+
+use automaton::{Alphabet, Dfa, Nfa, Shape, Symbol};
+
+mod automaton;
+#[cfg(test)]
+mod tests;
+
+/// Find a path that `a` matches but `b` doesn't
+///
+/// There is no such path exactly when `a` is a subset of `b`.
+pub fn subset_counterexample(a: &Program, b: &Program) -> Option<PathBuf> {
+    search(a, b, |a, b| a && !b)
+}
+
+/// Whether every path that `a` matches is also matched by `b`
+pub fn is_subset(a: &Program, b: &Program) -> bool {
+    subset_counterexample(a, b).is_none()
+}
+
+/// Find a path that exactly one of `a` and `b` matches
+pub fn equivalence_counterexample(a: &Program, b: &Program) -> Option<PathBuf> {
+    search(a, b, |a, b| a != b)
+}
+
+/// Whether `a` and `b` match exactly the same paths
+pub fn is_equivalent(a: &Program, b: &Program) -> bool {
+    equivalence_counterexample(a, b).is_none()
+}
+
+/// Find a path that both `a` and `b` match
+pub fn overlap_example(a: &Program, b: &Program) -> Option<PathBuf> {
+    search(a, b, |a, b| a && b)
+}
+
+/// Whether any path is matched by both `a` and `b`
+pub fn overlaps(a: &Program, b: &Program) -> bool {
+    overlap_example(a, b).is_some()
+}
+
+/// A state of the product automaton: the shape of the path so far, and the state of each program
+type Node = (Shape, usize, usize);
+
+/// Search the product of the automata for `a` and `b` for the shortest path whose results from
+/// each of them satisfy `want`
+fn search(a: &Program, b: &Program, want: impl Fn(bool, bool) -> bool) -> Option<PathBuf> {
+    let alphabet = Alphabet::new(&[a, b]);
+    let symbols = alphabet.symbols();
+    let mut dfa_a = Dfa::new(Nfa::new(a, &alphabet));
+    let mut dfa_b = Dfa::new(Nfa::new(b, &alphabet));
+
+    // Prune anything that can't lead to a wanted result, once one of the automata is dead
+    let possible = |dead: bool| {
+        if dead {
+            &[false][..]
+        } else {
+            &[false, true][..]
+        }
+    };
+    let viable = |dfa_a: &Dfa, dfa_b: &Dfa, (_, id_a, id_b): Node| {
+        possible(dfa_a.is_dead(id_a)).iter().any(|result_a| {
+            possible(dfa_b.is_dead(id_b))
+                .iter()
+                .any(|result_b| want(*result_a, *result_b))
+        })
+    };
+
+    let start = (Shape::Start, Dfa::START, Dfa::START);
+    // How each node was first reached, for rebuilding the path
+    let mut parents: HashMap<Node, Option<(Node, Symbol)>> = HashMap::from([(start, None)]);
+    let mut queue = VecDeque::from([start]);
+    while let Some(node) = queue.pop_front() {
+        let (shape, id_a, id_b) = node;
+        if shape.is_complete() && want(dfa_a.is_accepting(id_a), dfa_b.is_accepting(id_b)) {
+            let mut word = vec![];
+            let mut current = node;
+            while let Some(Some((parent, symbol))) = parents.get(&current) {
+                word.push(*symbol);
+                current = *parent;
+            }
+            word.reverse();
+            return Some(alphabet.path(&word));
+        }
+
+        for symbol in &symbols {
+            let Some(next_shape) = shape.step(*symbol, &alphabet) else {
+                continue;
+            };
+            let next = (
+                next_shape,
+                dfa_a.step(id_a, *symbol),
+                dfa_b.step(id_b, *symbol),
+            );
+            if !parents.contains_key(&next) && viable(&dfa_a, &dfa_b, next) {
+                parents.insert(next, Some((node, *symbol)));
+                queue.push_back(next);
+            }
+        }
+    }
+    None
+}
@@ -0,0 +1,546 @@
+//! Models a program as a finite automaton over the paths it can match
+//!
+//! Paths are read as a sequence of [`Symbol`]s: each normal component is its characters followed
+//! by [`Symbol::End`], and every other kind of component is a single symbol. Characters are
+//! grouped into intervals that no program can tell apart, so that the alphabet stays small.
+//!
+//! The states of the automaton mirror the states of the matcher's threads: the instruction, what
+//! it knows about the current component, and the value of each counter. Counters saturate at the
+//! largest value they are compared against, which keeps the number of states finite.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::{is_separator, PathBuf},
+};
+
+use crate::{
+    compiler::{Instruction, Program},
+    filesystem::path_from_bytes,
+    parser::CharacterClass,
+};
+
+/// Something read from a path
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) enum Symbol {
+    /// A character from one of the alphabet's intervals
+    Char(usize),
+    /// A byte that isn't part of a UTF-8 character, which only literals can mention
+    Byte(u8),
+    /// The end of a normal component
+    End,
+    Prefix(usize),
+    RootDir,
+    CurDir,
+    ParentDir,
+}
+
+/// The symbols needed to tell apart every path that some set of programs can distinguish
+#[derive(Debug)]
+pub(crate) struct Alphabet {
+    /// The first character of each interval, sorted
+    starts: Vec<u32>,
+    /// A character from each interval to use in example paths, or `None` if the interval can't
+    /// appear in a normal component
+    representatives: Vec<Option<char>>,
+    bytes: Vec<u8>,
+    prefixes: Vec<Box<str>>,
+    /// The interval holding `.`, which can't make up a whole normal component on its own
+    dot: usize,
+}
+
+impl Alphabet {
+    pub(crate) fn new(programs: &[&Program]) -> Alphabet {
+        // Surrogates aren't characters, so they get an interval that is never used
+        let mut boundaries = BTreeSet::from([0, '.' as u32, '.' as u32 + 1, 0xD800, 0xE000]);
+        let single = |boundaries: &mut BTreeSet<u32>, ch: char| {
+            boundaries.insert(ch as u32);
+            boundaries.insert(ch as u32 + 1);
+        };
+        let mut bytes = BTreeSet::new();
+        let mut prefixes = BTreeSet::new();
+        for instruction in programs.iter().flat_map(|program| &program.instructions) {
+            match instruction {
+                Instruction::LiteralString(literal) | Instruction::SkipToLiteral(literal) => {
+                    for chunk in literal.utf8_chunks() {
+                        for ch in chunk.valid().chars() {
+                            single(&mut boundaries, ch);
+                        }
+                        bytes.extend(chunk.invalid());
+                    }
+                }
+                Instruction::Characters(classes) => {
+                    for class in classes.iter() {
+                        let (start, end) = match *class {
+                            CharacterClass::Single(ch) => (ch, ch),
+                            CharacterClass::Range(start, end) => (start, end),
+                        };
+                        boundaries.insert(start as u32);
+                        boundaries.insert(end as u32 + 1);
+                    }
+                }
+                Instruction::Prefix(prefix) => {
+                    prefixes.insert(prefix.clone());
+                }
+                _ => (),
+            }
+        }
+        // Characters that can't be in a normal component get intervals of their own
+        for ch in ['\0', '/', std::path::MAIN_SEPARATOR] {
+            single(&mut boundaries, ch);
+        }
+        boundaries.retain(|boundary| *boundary <= char::MAX as u32);
+
+        let starts: Vec<u32> = boundaries.into_iter().collect();
+        let representatives = starts
+            .iter()
+            .enumerate()
+            .map(|(index, start)| {
+                let end = starts
+                    .get(index + 1)
+                    .map_or(char::MAX as u32, |next| next - 1);
+                representative(*start, end)
+            })
+            .collect();
+        let dot = starts.partition_point(|start| *start <= '.' as u32) - 1;
+        Alphabet {
+            starts,
+            representatives,
+            bytes: bytes.into_iter().collect(),
+            prefixes: prefixes.into_iter().collect(),
+            dot,
+        }
+    }
+
+    /// Every symbol that can appear in a path
+    pub(crate) fn symbols(&self) -> Vec<Symbol> {
+        let chars = (0..self.starts.len())
+            .filter(|index| self.representatives[*index].is_some())
+            .map(Symbol::Char);
+        let bytes = self.bytes.iter().map(|byte| Symbol::Byte(*byte));
+        let prefixes = (0..self.prefixes.len()).map(Symbol::Prefix);
+        chars
+            .chain(bytes)
+            .chain([
+                Symbol::End,
+                Symbol::RootDir,
+                Symbol::CurDir,
+                Symbol::ParentDir,
+            ])
+            .chain(prefixes)
+            .collect()
+    }
+
+    fn char_symbol(&self, ch: char) -> Option<Symbol> {
+        let index = self.starts.partition_point(|start| *start <= ch as u32) - 1;
+        self.representatives[index].map(|_| Symbol::Char(index))
+    }
+
+    /// The symbols a literal consists of, or `None` if no path could contain it
+    fn literal_symbols(&self, literal: &[u8]) -> Option<Box<[Symbol]>> {
+        let mut symbols = vec![];
+        for chunk in literal.utf8_chunks() {
+            for ch in chunk.valid().chars() {
+                symbols.push(self.char_symbol(ch)?);
+            }
+            symbols.extend(chunk.invalid().iter().map(|byte| Symbol::Byte(*byte)));
+        }
+        Some(symbols.into())
+    }
+
+    /// Turn a sequence of symbols back into an example path
+    pub(crate) fn path(&self, word: &[Symbol]) -> PathBuf {
+        let mut path = PathBuf::new();
+        let mut component = vec![];
+        for symbol in word {
+            match symbol {
+                Symbol::Char(index) => {
+                    let mut buf = [0; 4];
+                    let ch = self.representatives[*index].unwrap_or('?');
+                    component.extend_from_slice(ch.encode_utf8(&mut buf).as_bytes());
+                }
+                Symbol::Byte(byte) => component.push(*byte),
+                Symbol::End => path.push(path_from_bytes(std::mem::take(&mut component))),
+                Symbol::Prefix(index) => path.push(&*self.prefixes[*index]),
+                Symbol::RootDir => path.push(std::path::MAIN_SEPARATOR_STR),
+                Symbol::CurDir => path.push("."),
+                Symbol::ParentDir => path.push(".."),
+            }
+        }
+        path
+    }
+}
+
+/// Pick a readable character between `start` and `end`, if any of them can be in a component
+fn representative(start: u32, end: u32) -> Option<char> {
+    let start_char = char::from_u32(start)?;
+    if start_char == '\0' || is_separator(start_char) {
+        return None;
+    }
+    let preferred = ('a'..='z').chain('0'..='9').chain('A'..='Z');
+    preferred
+        .filter(|ch| (start..=end).contains(&(*ch as u32)))
+        .chain((start..=end.min(start + 0x100)).filter_map(char::from_u32))
+        .find(|ch| !ch.is_control())
+        .or(Some(start_char))
+}
+
+/// Where a path is in its list of components, to rule out symbol sequences that aren't paths
+///
+/// This follows the normalization done by [`std::path::Path::components`]: normal components
+/// are never empty, `.` or `..`, and `.` can only be the first component.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Shape {
+    Start,
+    AfterPrefix,
+    AfterRoot,
+    Boundary,
+    /// In a normal component that is `.` so far
+    Dot,
+    /// In a normal component that is `..` so far
+    DotDot,
+    Component,
+}
+
+impl Shape {
+    pub(crate) fn step(self, symbol: Symbol, alphabet: &Alphabet) -> Option<Shape> {
+        use Shape::*;
+        let at_boundary = matches!(self, Start | AfterPrefix | AfterRoot | Boundary);
+        match symbol {
+            Symbol::Prefix(_) if self == Start => Some(AfterPrefix),
+            Symbol::RootDir if matches!(self, Start | AfterPrefix) => Some(AfterRoot),
+            Symbol::CurDir if self == Start => Some(Boundary),
+            Symbol::ParentDir if at_boundary => Some(Boundary),
+            Symbol::Char(index) if index == alphabet.dot => match self {
+                _ if at_boundary => Some(Dot),
+                Dot => Some(DotDot),
+                _ => Some(Component),
+            },
+            Symbol::Char(_) | Symbol::Byte(_) => Some(Component),
+            Symbol::End if self == Component => Some(Boundary),
+            _ => None,
+        }
+    }
+
+    /// Whether a whole path has been read
+    pub(crate) fn is_complete(self) -> bool {
+        matches!(
+            self,
+            Shape::Start | Shape::AfterPrefix | Shape::AfterRoot | Shape::Boundary
+        )
+    }
+}
+
+/// What a thread knows about the current component
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Mode {
+    /// No component is loaded. If `pending`, a separator has promised there is another one.
+    Between { pending: bool },
+    /// A normal component is loaded. If `fresh`, none of it has been consumed, so it isn't empty.
+    Inside { fresh: bool },
+    /// The component has been consumed up to its end, but is still loaded
+    Drained,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) struct State {
+    pc: usize,
+    mode: Mode,
+    /// How many symbols of a literal have been consumed. For [`Instruction::SkipToLiteral`], 0
+    /// means characters are still being skipped, and one more than the count otherwise.
+    offset: usize,
+    counters: Box<[u32]>,
+}
+
+pub(crate) type StateSet = BTreeSet<State>;
+
+/// The automaton for a single program
+#[derive(Debug)]
+pub(crate) struct Nfa<'a> {
+    program: &'a Program,
+    alphabet: &'a Alphabet,
+    /// Counters stop increasing at this value, as nothing compares them against anything larger
+    caps: Vec<u32>,
+    /// The symbols of the literal at each instruction
+    literals: Vec<Option<Box<[Symbol]>>>,
+}
+
+impl<'a> Nfa<'a> {
+    pub(crate) fn new(program: &'a Program, alphabet: &'a Alphabet) -> Nfa<'a> {
+        let mut caps = vec![0; program.counters as usize];
+        for instruction in &program.instructions {
+            if let Instruction::BranchIfLessThan(_, counter_id, value) = instruction {
+                let cap = &mut caps[counter_id.0 as usize];
+                *cap = (*cap).max(*value);
+            }
+        }
+        let literals = program
+            .instructions
+            .iter()
+            .map(|instruction| match instruction {
+                Instruction::LiteralString(literal) | Instruction::SkipToLiteral(literal) => {
+                    alphabet.literal_symbols(literal)
+                }
+                _ => None,
+            })
+            .collect();
+        Nfa {
+            program,
+            alphabet,
+            caps,
+            literals,
+        }
+    }
+
+    pub(crate) fn start(&self) -> StateSet {
+        self.closure([State {
+            pc: 0,
+            mode: Mode::Between { pending: false },
+            offset: 0,
+            counters: vec![0; self.caps.len()].into(),
+        }])
+    }
+
+    /// Whether a path that ends here matches
+    pub(crate) fn is_accepting(&self, states: &StateSet) -> bool {
+        states.iter().any(|state| {
+            matches!(
+                self.program.instructions.get(state.pc),
+                Some(Instruction::Complete)
+            ) && matches!(state.mode, Mode::Between { pending: false } | Mode::Drained)
+        })
+    }
+
+    pub(crate) fn step(&self, states: &StateSet, symbol: Symbol) -> StateSet {
+        self.closure(
+            states
+                .iter()
+                .filter_map(|state| self.consume(state, symbol)),
+        )
+    }
+
+    /// Follow every transition that doesn't consume a symbol
+    fn closure(&self, states: impl IntoIterator<Item = State>) -> StateSet {
+        let mut out = StateSet::new();
+        let mut stack: Vec<State> = states.into_iter().collect();
+        while let Some(state) = stack.pop() {
+            if out.contains(&state) {
+                continue;
+            }
+            stack.extend(self.epsilon(&state));
+            out.insert(state);
+        }
+        out
+    }
+
+    fn epsilon(&self, state: &State) -> Vec<State> {
+        let Some(instruction) = self.program.instructions.get(state.pc) else {
+            return vec![];
+        };
+        let goto = |pc: usize, mode: Mode| State {
+            pc,
+            mode,
+            offset: 0,
+            counters: state.counters.clone(),
+        };
+        let next = |mode: Mode| goto(state.pc + 1, mode);
+        match instruction {
+            Instruction::Jump(target) => vec![goto(target.0, state.mode)],
+            Instruction::Alternative(target) => {
+                vec![next(state.mode), goto(target.0, state.mode)]
+            }
+            Instruction::Increment(counter_id) => {
+                let mut incremented = next(state.mode);
+                let index = counter_id.0 as usize;
+                incremented.counters[index] = (state.counters[index] + 1).min(self.caps[index]);
+                vec![incremented]
+            }
+            Instruction::BranchIfLessThan(target, counter_id, value) => {
+                if state.counters[counter_id.0 as usize] < *value {
+                    vec![goto(target.0, state.mode)]
+                } else {
+                    vec![next(state.mode)]
+                }
+            }
+            Instruction::Separator => match state.mode {
+                Mode::Between { .. } | Mode::Drained => vec![next(Mode::Between { pending: true })],
+                // Separators with nothing consumed since the last one collapse
+                Mode::Inside { fresh: true } => vec![next(state.mode)],
+                Mode::Inside { fresh: false } => vec![],
+            },
+            Instruction::LiteralString(_)
+            | Instruction::AnyCharacter
+            | Instruction::AnyString
+            | Instruction::Characters(_)
+            | Instruction::SkipToLiteral(_) => match state.mode {
+                // Load the next component
+                Mode::Between { .. } => vec![goto(state.pc, Mode::Inside { fresh: true })],
+                // Only things that can match nothing succeed on an empty component
+                Mode::Drained => match instruction {
+                    Instruction::LiteralString(literal) | Instruction::SkipToLiteral(literal)
+                        if literal.is_empty() =>
+                    {
+                        vec![next(Mode::Drained)]
+                    }
+                    Instruction::AnyString => vec![next(Mode::Drained)],
+                    _ => vec![],
+                },
+                Mode::Inside { .. } => match (instruction, &self.literals[state.pc]) {
+                    (Instruction::SkipToLiteral(_), Some(symbols)) => {
+                        if state.offset == symbols.len() + 1 {
+                            vec![next(Mode::Inside { fresh: false })]
+                        } else if state.offset == 0 {
+                            // Stop skipping and start on the literal
+                            vec![State {
+                                offset: 1,
+                                ..state.clone()
+                            }]
+                        } else {
+                            vec![]
+                        }
+                    }
+                    (_, Some(symbols)) if state.offset == symbols.len() => {
+                        vec![next(Mode::Inside { fresh: false })]
+                    }
+                    _ => vec![],
+                },
+            },
+            _ => vec![],
+        }
+    }
+
+    fn consume(&self, state: &State, symbol: Symbol) -> Option<State> {
+        let instruction = self.program.instructions.get(state.pc)?;
+        let goto = |pc: usize, mode: Mode, offset: usize| State {
+            pc,
+            mode,
+            offset,
+            counters: state.counters.clone(),
+        };
+        let is_special = matches!(
+            instruction,
+            Instruction::Prefix(_)
+                | Instruction::RootDir
+                | Instruction::CurDir
+                | Instruction::ParentDir
+        );
+        match (state.mode, symbol) {
+            // Instructions that need the component to be used up can end it
+            (Mode::Inside { fresh: false }, Symbol::End)
+                if is_special
+                    || matches!(instruction, Instruction::Separator | Instruction::Complete) =>
+            {
+                Some(goto(state.pc, Mode::Drained, 0))
+            }
+            (Mode::Between { .. } | Mode::Drained, _) if is_special => {
+                let matched = match (instruction, symbol) {
+                    (Instruction::Prefix(prefix), Symbol::Prefix(index)) => {
+                        *prefix == self.alphabet.prefixes[index]
+                    }
+                    (Instruction::RootDir, Symbol::RootDir)
+                    | (Instruction::CurDir, Symbol::CurDir)
+                    | (Instruction::ParentDir, Symbol::ParentDir) => true,
+                    _ => false,
+                };
+                let mode = match state.mode {
+                    Mode::Drained => Mode::Drained,
+                    _ => Mode::Between { pending: false },
+                };
+                matched.then(|| goto(state.pc + 1, mode, 0))
+            }
+            (Mode::Inside { .. }, _) => {
+                let is_char = matches!(symbol, Symbol::Char(_) | Symbol::Byte(_));
+                let consumed = Mode::Inside { fresh: false };
+                match instruction {
+                    Instruction::LiteralString(_) => {
+                        let symbols = self.literals[state.pc].as_ref()?;
+                        (symbols.get(state.offset) == Some(&symbol))
+                            .then(|| goto(state.pc, consumed, state.offset + 1))
+                    }
+                    Instruction::SkipToLiteral(_) if state.offset == 0 => {
+                        is_char.then(|| goto(state.pc, consumed, 0))
+                    }
+                    Instruction::SkipToLiteral(_) => {
+                        let symbols = self.literals[state.pc].as_ref()?;
+                        (symbols.get(state.offset - 1) == Some(&symbol))
+                            .then(|| goto(state.pc, consumed, state.offset + 1))
+                    }
+                    Instruction::AnyCharacter if is_char => Some(goto(state.pc + 1, consumed, 0)),
+                    Instruction::Characters(classes) => {
+                        let Symbol::Char(index) = symbol else {
+                            return None;
+                        };
+                        let ch = self.alphabet.representatives[index]?;
+                        classes
+                            .iter()
+                            .any(|class| class.contains(ch))
+                            .then(|| goto(state.pc + 1, consumed, 0))
+                    }
+                    Instruction::AnyString if is_char => Some(goto(state.pc, consumed, 0)),
+                    Instruction::AnyString if symbol == Symbol::End => {
+                        Some(goto(state.pc + 1, Mode::Drained, 0))
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Interns the state sets reached by an automaton, and caches the transitions between them
+#[derive(Debug)]
+pub(crate) struct Dfa<'a> {
+    nfa: Nfa<'a>,
+    sets: Vec<StateSet>,
+    accepting: Vec<bool>,
+    ids: HashMap<StateSet, usize>,
+    transitions: HashMap<(usize, Symbol), usize>,
+}
+
+impl<'a> Dfa<'a> {
+    pub(crate) fn new(nfa: Nfa<'a>) -> Dfa<'a> {
+        let mut dfa = Dfa {
+            nfa,
+            sets: vec![],
+            accepting: vec![],
+            ids: HashMap::new(),
+            transitions: HashMap::new(),
+        };
+        let start = dfa.nfa.start();
+        dfa.intern(start);
+        dfa
+    }
+
+    /// The id of the start state
+    pub(crate) const START: usize = 0;
+
+    fn intern(&mut self, set: StateSet) -> usize {
+        if let Some(id) = self.ids.get(&set) {
+            return *id;
+        }
+        let id = self.sets.len();
+        self.accepting.push(self.nfa.is_accepting(&set));
+        self.sets.push(set.clone());
+        self.ids.insert(set, id);
+        id
+    }
+
+    pub(crate) fn step(&mut self, id: usize, symbol: Symbol) -> usize {
+        if let Some(next) = self.transitions.get(&(id, symbol)) {
+            return *next;
+        }
+        let set = self.nfa.step(&self.sets[id], symbol);
+        let next = self.intern(set);
+        self.transitions.insert((id, symbol), next);
+        next
+    }
+
+    pub(crate) fn is_accepting(&self, id: usize) -> bool {
+        self.accepting[id]
+    }
+
+    /// Whether no path can be accepted from here, because no thread is left
+    pub(crate) fn is_dead(&self, id: usize) -> bool {
+        self.sets[id].is_empty()
+    }
+}
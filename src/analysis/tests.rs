@@ -0,0 +1,138 @@
+use std::path::Path;
+
+use crate::{
+    analysis::{
+        equivalence_counterexample, is_equivalent, is_subset, overlap_example, overlaps,
+        subset_counterexample,
+    },
+    compiler::{compile, Program},
+    matcher::path_matches,
+    parser::parse,
+};
+
+fn program(pattern: &str) -> Program {
+    compile(&parse(pattern)).unwrap()
+}
+
+fn matches(program: &Program, path: &Path) -> bool {
+    path_matches(path, program).valid_as_complete_match
+}
+
+/// Check the subset relation both ways, and that any counterexample really is one
+fn subset(a: &str, b: &str) -> bool {
+    let (a, b) = (program(a), program(b));
+    match subset_counterexample(&a, &b) {
+        Some(path) => {
+            assert!(matches(&a, &path), "{}", path.display());
+            assert!(!matches(&b, &path), "{}", path.display());
+            false
+        }
+        None => true,
+    }
+}
+
+#[test]
+fn recurse_covers_nested_dirs() {
+    assert!(subset("src/lib/*.rs", "src/**/*.rs"));
+    assert!(!subset("src/**/*.rs", "src/lib/*.rs"));
+    assert!(subset("src/*.rs", "src/**/*.rs"));
+    assert!(!subset("src/**/*.rs", "src/*/*.rs"));
+}
+
+#[test]
+fn counterexample_is_shortest() {
+    let (a, b) = (program("src/**/*.rs"), program("src/lib/*.rs"));
+    assert_eq!(
+        subset_counterexample(&a, &b).as_deref(),
+        Some(Path::new("src/.rs"))
+    );
+}
+
+#[test]
+fn alternatives_and_classes() {
+    assert!(subset("*.{rs,md}", "*.?[sd]*"));
+    assert!(!subset("*.{rs,md}", "*.rs"));
+    assert!(subset("[ab]x", "{a,b}x"));
+    assert!(subset("{a,b}x", "[ab]x"));
+    assert!(subset("[b-c]", "[a-z]"));
+    assert!(!subset("[a-z]", "[b-c]"));
+}
+
+#[test]
+fn repeats_count() {
+    assert!(subset("<a:2,3>", "<a:1,4>"));
+    assert!(!subset("<a:1,4>", "<a:2,3>"));
+    assert!(subset("<a/:2>b", "a/**/b"));
+    assert!(!subset("a/**/b", "<a/:2>b"));
+}
+
+#[test]
+fn wildcard_does_not_match_dot_components() {
+    // `.` and `..` are never normal components, so `*` can't match them
+    assert!(subset("*", "{*,.}"));
+    assert!(subset("a/*", "a/?*"));
+}
+
+#[test]
+fn equivalence() {
+    let check = |a: &str, b: &str| {
+        let (a, b) = (program(a), program(b));
+        if let Some(path) = equivalence_counterexample(&a, &b) {
+            assert_ne!(matches(&a, &path), matches(&b, &path));
+        }
+        is_equivalent(&a, &b)
+    };
+    assert!(check("{a,b}/c", "{b,a}/c"));
+    assert!(check("a*b*", "a*b*"));
+    assert!(check("{a,b}*/**/c", "{b*,a*}/**/c"));
+    assert!(check("a/<*/:0,2>b", "a/{,*/,*/*/}b"));
+    assert!(!check("**", "**/*"));
+    assert!(!check("/a", "a"));
+    assert!(is_subset(&program("a"), &program("a")));
+}
+
+#[test]
+fn agrees_with_matcher_on_samples() {
+    let patterns = [
+        "*", "**/*", "a/*", "*.rs", "a/**/b", "{a,b}/*", "[a-c]*", "<?:1,2>", "../*", "/a/*", ".",
+    ];
+    let paths = [
+        "", "a", "b", "a/b", "a/x/b", "x.rs", "ab", "..", "../a", "/a/b", ".", "a/.rs",
+    ];
+    let programs: Vec<_> = patterns.iter().map(|pattern| program(pattern)).collect();
+    for a in &programs {
+        for b in &programs {
+            if is_subset(a, b) {
+                for path in paths.iter().map(Path::new) {
+                    assert!(
+                        !matches(a, path) || matches(b, path),
+                        "{}\n{}\n{:?}",
+                        a,
+                        b,
+                        path
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn overlapping_patterns() {
+    let overlap = |a: &str, b: &str| {
+        let (a, b) = (program(a), program(b));
+        let example = overlap_example(&a, &b);
+        if let Some(path) = &example {
+            assert!(matches(&a, path) && matches(&b, path), "{}", path.display());
+        }
+        example
+    };
+    assert_eq!(
+        overlap("src/**/*.rs", "**/tests.rs").as_deref(),
+        Some(Path::new("src/tests.rs"))
+    );
+    assert_eq!(overlap("*.rs", "*.md"), None);
+    assert_eq!(overlap("docs/*", "src/*"), None);
+    assert!(overlap("[a-m]*", "[k-z]*").is_some());
+    assert!(!overlaps(&program("<a:2>"), &program("<a:3>")));
+}
@@ -0,0 +1,133 @@
+//! Indexes tar and zip archives so that they can be globbed without extracting them
+
+use std::{
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use anyhow::Context;
+use flate2::read::GzDecoder;
+
+use crate::filesystem::{FileSystem, MemoryFileSystem};
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// Guess the format of an archive from its first few bytes
+    pub fn detect(magic: &[u8]) -> ArchiveFormat {
+        if magic.starts_with(b"PK\x03\x04") || magic.starts_with(b"PK\x05\x06") {
+            ArchiveFormat::Zip
+        } else if magic.starts_with(b"\x1f\x8b") {
+            ArchiveFormat::TarGz
+        } else {
+            ArchiveFormat::Tar
+        }
+    }
+}
+
+/// Build an in-memory directory index from the listing of the archive at `path`
+///
+/// Paths in the index are the paths stored in the archive, so they are usually relative.
+pub fn index(path: impl AsRef<Path>) -> anyhow::Result<MemoryFileSystem> {
+    let path = path.as_ref();
+    let mut file = File::open(path).with_context(|| path.display().to_string())?;
+
+    let mut magic = [0; 4];
+    let magic_len = read_up_to(&mut file, &mut magic)?;
+    file.seek(SeekFrom::Start(0))?;
+
+    let result = match ArchiveFormat::detect(&magic[..magic_len]) {
+        ArchiveFormat::Tar => index_tar(BufReader::new(file)),
+        ArchiveFormat::TarGz => index_tar(GzDecoder::new(BufReader::new(file))),
+        ArchiveFormat::Zip => index_zip(BufReader::new(file)),
+    };
+    result.with_context(|| path.display().to_string())
+}
+
+fn read_up_to(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match reader.read(&mut buf[len..])? {
+            0 => break,
+            n => len += n,
+        }
+    }
+    Ok(len)
+}
+
+/// Build an in-memory directory index from a tar stream
+pub fn index_tar(reader: impl Read) -> anyhow::Result<MemoryFileSystem> {
+    let mut fs = MemoryFileSystem::new();
+    // The root is there even if the archive is empty, so globbing it finds nothing
+    fs.add_dir("");
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let path = entry.path()?;
+        match entry.header().entry_type() {
+            tar::EntryType::Directory => {
+                fs.add_dir(path);
+            }
+            tar::EntryType::Symlink => {
+                let target = entry.link_name()?.unwrap_or_default();
+                fs.add_symlink(path, target);
+            }
+            // A hard link is another name for a file earlier in the archive, and has no data of
+            // its own
+            tar::EntryType::Link => {
+                let len = entry
+                    .link_name()?
+                    .and_then(|target| fs.metadata(&target).ok())
+                    .map_or(0, |metadata| metadata.len);
+                fs.add_file(path, len);
+            }
+            tar::EntryType::Regular
+            | tar::EntryType::Continuous
+            | tar::EntryType::GNUSparse
+            | tar::EntryType::Char
+            | tar::EntryType::Block
+            | tar::EntryType::Fifo => {
+                fs.add_file(path, entry.size());
+            }
+            // Everything else describes other entries rather than being one, like the pax global
+            // header that `git archive` starts with, or pax and GNU long name headers
+            _ => (),
+        }
+    }
+    Ok(fs)
+}
+
+/// Build an in-memory directory index from the central directory of a zip file
+pub fn index_zip(reader: impl Read + Seek) -> anyhow::Result<MemoryFileSystem> {
+    let mut fs = MemoryFileSystem::new();
+    // The root is there even if the archive is empty, so globbing it finds nothing
+    fs.add_dir("");
+    let mut archive = zip::ZipArchive::new(reader)?;
+    for index in 0..archive.len() {
+        let is_symlink = archive.by_index_raw(index)?.is_symlink();
+        if is_symlink {
+            // The link target is stored as the content of the entry
+            let mut entry = archive.by_index(index)?;
+            let mut target = String::new();
+            entry.read_to_string(&mut target)?;
+            fs.add_symlink(entry.name(), target);
+        } else {
+            let entry = archive.by_index_raw(index)?;
+            if entry.is_dir() {
+                fs.add_dir(entry.name());
+            } else {
+                fs.add_file(entry.name(), entry.size());
+            }
+        }
+    }
+    Ok(fs)
+}
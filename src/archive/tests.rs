@@ -0,0 +1,102 @@
+use std::{
+    io::{Cursor, Write},
+    path::Path,
+    sync::Arc,
+};
+
+use crate::{
+    archive::{index_tar, index_zip, ArchiveFormat},
+    compiler::compile,
+    filesystem::FileSystem,
+    globber::glob_in,
+    parser::parse,
+};
+
+#[test]
+fn detect_formats() {
+    assert_eq!(ArchiveFormat::detect(b"PK\x03\x04"), ArchiveFormat::Zip);
+    assert_eq!(
+        ArchiveFormat::detect(b"\x1f\x8b\x08\x00"),
+        ArchiveFormat::TarGz
+    );
+    assert_eq!(ArchiveFormat::detect(b"pkg/"), ArchiveFormat::Tar);
+}
+
+#[test]
+fn tar_listing() {
+    let mut builder = tar::Builder::new(vec![]);
+    // Like `git archive`, start with a pax global header holding the commit id
+    let comment = b"52 comment=0123456789abcdef0123456789abcdef01234567\n";
+    let mut header = tar::Header::new_ustar();
+    header.set_entry_type(tar::EntryType::XGlobalHeader);
+    header.set_size(comment.len() as u64);
+    builder
+        .append_data(&mut header, "pax_global_header", &comment[..])
+        .unwrap();
+    let mut header = tar::Header::new_gnu();
+    header.set_size(3);
+    header.set_mode(0o644);
+    builder
+        .append_data(&mut header, "./pkg/lib/a.so", &b"abc"[..])
+        .unwrap();
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Symlink);
+    header.set_size(0);
+    builder
+        .append_link(&mut header, "pkg/link.so", "lib/a.so")
+        .unwrap();
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Link);
+    header.set_size(0);
+    builder
+        .append_link(&mut header, "pkg/hard.so", "pkg/lib/a.so")
+        .unwrap();
+    let bytes = builder.into_inner().unwrap();
+
+    let fs = index_tar(&bytes[..]).unwrap();
+    assert!(fs.metadata(Path::new("pkg/lib")).unwrap().is_dir());
+    assert_eq!(fs.metadata(Path::new("pkg/lib/a.so")).unwrap().len, 3);
+    assert!(fs
+        .symlink_metadata(Path::new("pkg/link.so"))
+        .unwrap()
+        .is_symlink());
+    assert!(fs.metadata(Path::new("pkg/link.so")).unwrap().is_file());
+    assert_eq!(fs.metadata(Path::new("pkg/hard.so")).unwrap().len, 3);
+    let names: Vec<_> = fs.read_dir(Path::new("")).unwrap().collect();
+    assert_eq!(names.len(), 1, "{:?}", names);
+}
+
+#[test]
+fn zip_listing() {
+    let mut writer = zip::ZipWriter::new(Cursor::new(vec![]));
+    let options = zip::write::SimpleFileOptions::default();
+    writer.add_directory("pkg/bin/", options).unwrap();
+    writer.start_file("pkg/lib/a.so", options).unwrap();
+    writer.write_all(b"abcd").unwrap();
+    let bytes = writer.finish().unwrap().into_inner();
+
+    let fs = index_zip(Cursor::new(bytes)).unwrap();
+    assert!(fs.metadata(Path::new("pkg/bin")).unwrap().is_dir());
+    assert_eq!(fs.metadata(Path::new("pkg/lib/a.so")).unwrap().len, 4);
+    let names: Vec<_> = fs.read_dir(Path::new("pkg")).unwrap().collect();
+    assert_eq!(names.len(), 2);
+}
+
+#[test]
+fn empty_archives_have_a_root() {
+    let program = Arc::new(compile(&parse("**/*")).unwrap());
+    let empty_zip = zip::ZipWriter::new(Cursor::new(vec![]))
+        .finish()
+        .unwrap()
+        .into_inner();
+    for fs in [
+        index_tar(&[0; 1024][..]).unwrap(),
+        index_zip(Cursor::new(empty_zip)).unwrap(),
+    ] {
+        assert!(fs.metadata(Path::new("")).unwrap().is_dir());
+        let paths = glob_in(Arc::new(fs), "", program.clone())
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        assert!(paths.is_empty());
+    }
+}
@@ -0,0 +1,287 @@
+//! Reads programs back from the text that [`Program`]'s `Display` writes, so they can be written
+//! by hand
+//!
+//! Each line is an instruction, optionally labelled with its offset like `[  3]:`. Offsets can be
+//! written as `[  3]` or just `3`. Lines starting with `#` are comments, except for the header
+//! that `Display` writes, which sets the number of counters and the absolute prefix. Without a
+//! header, the program has as many counters as it uses. Programs are checked with
+//! [`verifier::verify`] before they are returned.
+//!
+//! Literals are printed lossily, so a literal that isn't valid UTF-8 won't read back the same.
+
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail};
+
+use crate::{
+    compiler::{CounterId, Instruction, Program, ProgramOffset},
+    parser::CharacterClass,
+    verifier,
+};
+
+#[cfg(test)]
+mod tests;
+
+pub fn assemble(source: &str) -> anyhow::Result<Program> {
+    let mut program = Program::default();
+    let mut header_counters = None;
+    // One more than the highest counter used, for when there's no header
+    let mut used_counters = 0;
+    for (index, line) in source.lines().enumerate() {
+        let number = index + 1;
+        let line = line.trim();
+        let result = if let Some(header) = line.strip_prefix("# counters=") {
+            parse_header(header).map(|(counters, absolute_prefix)| {
+                header_counters = Some(counters);
+                program.absolute_prefix = absolute_prefix;
+            })
+        } else if line.is_empty() || line.starts_with('#') {
+            Ok(())
+        } else {
+            let offset = program.instructions.len();
+            parse_instruction(line, offset).and_then(|instruction| {
+                if let Instruction::Increment(counter_id)
+                | Instruction::BranchIfLessThan(_, counter_id, _) = instruction
+                {
+                    let Some(count) = counter_id.0.checked_add(1) else {
+                        bail!(
+                            "counter {} is out of range, there can only be {} counters",
+                            counter_id,
+                            u16::MAX
+                        );
+                    };
+                    used_counters = used_counters.max(count);
+                }
+                program.instructions.push(instruction);
+                Ok(())
+            })
+        };
+        result.map_err(|err| anyhow!("line {}: {}", number, err))?;
+    }
+
+    program.counters = header_counters.unwrap_or(used_counters);
+    verifier::verify(&program)?;
+    Ok(program)
+}
+
+/// Parse the rest of a `# counters=N, absolute_prefix=...` line
+fn parse_header(header: &str) -> anyhow::Result<(u16, Option<PathBuf>)> {
+    let mut cursor = Cursor(header);
+    let counters = cursor.number()?;
+    cursor.expect(",")?;
+    cursor.expect("absolute_prefix=")?;
+    let absolute_prefix = if cursor.eat("None") {
+        None
+    } else {
+        cursor.expect("Some(")?;
+        let prefix = cursor.string()?;
+        cursor.expect(")")?;
+        Some(PathBuf::from(prefix))
+    };
+    cursor.end()?;
+    Ok((counters, absolute_prefix))
+}
+
+fn parse_instruction(line: &str, offset: usize) -> anyhow::Result<Instruction> {
+    let mut cursor = Cursor(line);
+    if cursor.0.starts_with('[') {
+        let label = cursor.offset()?;
+        cursor.expect(":")?;
+        if label.0 != offset {
+            bail!("labelled {}, but it is {}", label, ProgramOffset(offset));
+        }
+    }
+    let instruction = match cursor.word() {
+        "separator" => Instruction::Separator,
+        "prefix" => Instruction::Prefix(cursor.string()?.into()),
+        "root-dir" => Instruction::RootDir,
+        "cur-dir" => Instruction::CurDir,
+        "parent-dir" => Instruction::ParentDir,
+        "literal-string" => Instruction::LiteralString(cursor.string()?.into_bytes().into()),
+        "any-character" => Instruction::AnyCharacter,
+        "any-string" => Instruction::AnyString,
+        "characters" => Instruction::Characters(cursor.classes()?.into()),
+        "skip-to-literal" => Instruction::SkipToLiteral(cursor.string()?.into_bytes().into()),
+        "jump" => Instruction::Jump(cursor.offset()?),
+        "alternative" => Instruction::Alternative(cursor.offset()?),
+        "increment" => Instruction::Increment(cursor.counter()?),
+        "branch-if" => {
+            let target = cursor.offset()?;
+            cursor.expect(",")?;
+            let counter_id = cursor.counter()?;
+            cursor.expect("<")?;
+            Instruction::BranchIfLessThan(target, counter_id, cursor.number()?)
+        }
+        "complete" => Instruction::Complete,
+        "" => bail!("expected an instruction"),
+        other => bail!("unknown instruction {:?}", other),
+    };
+    cursor.end()?;
+    Ok(instruction)
+}
+
+/// The rest of a line that hasn't been parsed yet
+struct Cursor<'a>(&'a str);
+
+impl Cursor<'_> {
+    fn skip_whitespace(&mut self) {
+        self.0 = self.0.trim_start();
+    }
+
+    /// Consume `token` if it's next
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        match self.0.strip_prefix(token) {
+            Some(rest) => {
+                self.0 = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> anyhow::Result<()> {
+        if !self.eat(token) {
+            bail!("expected {:?}, found {:?}", token, self.0);
+        }
+        Ok(())
+    }
+
+    fn end(&mut self) -> anyhow::Result<()> {
+        self.skip_whitespace();
+        if !self.0.is_empty() {
+            bail!("unexpected {:?}", self.0);
+        }
+        Ok(())
+    }
+
+    fn word(&mut self) -> &str {
+        self.skip_whitespace();
+        let end = self
+            .0
+            .find(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '-'))
+            .unwrap_or(self.0.len());
+        let (word, rest) = self.0.split_at(end);
+        self.0 = rest;
+        word
+    }
+
+    fn number<T: std::str::FromStr>(&mut self) -> anyhow::Result<T> {
+        self.skip_whitespace();
+        let end = self
+            .0
+            .find(|ch: char| !ch.is_ascii_digit())
+            .unwrap_or(self.0.len());
+        let (digits, rest) = self.0.split_at(end);
+        let number = digits
+            .parse()
+            .map_err(|_| anyhow!("expected a number, found {:?}", self.0))?;
+        self.0 = rest;
+        Ok(number)
+    }
+
+    /// An offset, either bracketed like `[  3]` or bare
+    fn offset(&mut self) -> anyhow::Result<ProgramOffset> {
+        let offset = if self.eat("[") {
+            let offset = self.number()?;
+            self.expect("]")?;
+            offset
+        } else {
+            self.number()?
+        };
+        Ok(ProgramOffset(offset))
+    }
+
+    fn counter(&mut self) -> anyhow::Result<CounterId> {
+        self.expect("#")?;
+        Ok(CounterId(self.number()?))
+    }
+
+    /// A string literal, as written by `Debug`
+    fn string(&mut self) -> anyhow::Result<String> {
+        self.expect("\"")?;
+        let mut out = String::new();
+        loop {
+            match self.next_char()? {
+                '"' => return Ok(out),
+                '\\' => out.push(self.escape()?),
+                ch => out.push(ch),
+            }
+        }
+    }
+
+    /// A character literal, as written by `Debug`
+    fn char(&mut self) -> anyhow::Result<char> {
+        self.expect("'")?;
+        let ch = match self.next_char()? {
+            '\\' => self.escape()?,
+            ch => ch,
+        };
+        if self.next_char()? != '\'' {
+            bail!("expected the end of a character literal");
+        }
+        Ok(ch)
+    }
+
+    /// A list of character classes, as written by `Debug`
+    fn classes(&mut self) -> anyhow::Result<Vec<CharacterClass>> {
+        self.expect("[")?;
+        let mut classes = vec![];
+        while !self.eat("]") {
+            if !classes.is_empty() {
+                self.expect(",")?;
+            }
+            let class = match self.word() {
+                "Single" => {
+                    self.expect("(")?;
+                    CharacterClass::Single(self.char()?)
+                }
+                "Range" => {
+                    self.expect("(")?;
+                    let start = self.char()?;
+                    self.expect(",")?;
+                    CharacterClass::Range(start, self.char()?)
+                }
+                other => bail!("unknown character class {:?}", other),
+            };
+            self.expect(")")?;
+            classes.push(class);
+        }
+        Ok(classes)
+    }
+
+    fn next_char(&mut self) -> anyhow::Result<char> {
+        let mut chars = self.0.chars();
+        let ch = chars
+            .next()
+            .ok_or_else(|| anyhow!("unexpected end of line"))?;
+        self.0 = chars.as_str();
+        Ok(ch)
+    }
+
+    /// The rest of an escape sequence, after the backslash
+    fn escape(&mut self) -> anyhow::Result<char> {
+        Ok(match self.next_char()? {
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            '0' => '\0',
+            'u' => {
+                let rest = self
+                    .0
+                    .strip_prefix('{')
+                    .ok_or_else(|| anyhow!("expected {{ after \\u"))?;
+                let (hex, rest) = rest
+                    .split_once('}')
+                    .ok_or_else(|| anyhow!("unterminated \\u escape"))?;
+                self.0 = rest;
+                u32::from_str_radix(hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| anyhow!("invalid character escape \\u{{{}}}", hex))?
+            }
+            ch @ ('\\' | '"' | '\'') => ch,
+            other => bail!("unknown escape \\{}", other),
+        })
+    }
+}
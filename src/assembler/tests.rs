@@ -0,0 +1,121 @@
+use std::path::Path;
+
+use crate::{
+    assembler::assemble,
+    compiler::{compile, compile_with, CompileOptions, Instruction, Program, ProgramOffset},
+    matcher::path_matches,
+    parser::parse,
+};
+
+/// Check that a program reads back the same as it was written, apart from its spans
+fn round_trip(program: &Program) {
+    let text = program.to_string();
+    let assembled = assemble(&text).unwrap_or_else(|err| panic!("{}\n{}", err, text));
+    assert_eq!(assembled.instructions, program.instructions, "{}", text);
+    assert_eq!(assembled.counters, program.counters, "{}", text);
+    assert_eq!(
+        assembled.absolute_prefix, program.absolute_prefix,
+        "{}",
+        text
+    );
+}
+
+#[test]
+fn compiled_programs_round_trip() {
+    for pattern in [
+        "src/**/*.rs",
+        "/usr/lib/{a,b}/../x",
+        "./<[0-9a-f]:2,4>.txt",
+        "say \"hi\"\\ there",
+        "tab\there/[\u{301}-\u{302}'\\\\]",
+        "<<a:2>b:1,3>",
+    ] {
+        round_trip(&compile(&parse(pattern)).unwrap());
+        let options = CompileOptions {
+            case_insensitive: true,
+            optimize: true,
+        };
+        round_trip(&compile_with(&parse(pattern), &options).unwrap());
+    }
+}
+
+#[test]
+fn hand_written_programs() {
+    let program = assemble(
+        "
+        # Either directory, then anything ending in .rs
+        alternative 3
+        literal-string \"src\"
+        jump 4
+        literal-string \"tests\"
+        separator
+        skip-to-literal \".rs\"
+        complete
+        ",
+    )
+    .unwrap();
+    assert_eq!(program.counters, 0);
+    assert_eq!(program.instructions[2], Instruction::Jump(ProgramOffset(4)));
+    for (path, expected) in [
+        ("src/main.rs", true),
+        ("tests/a.rs.rs", true),
+        ("docs/a.rs", false),
+        ("src/main.rs/x", false),
+    ] {
+        assert_eq!(
+            path_matches(Path::new(path), &program).valid_as_complete_match,
+            expected,
+            "{}",
+            path
+        );
+    }
+}
+
+#[test]
+fn counters_default_to_those_used() {
+    let program = assemble(
+        "
+        [  0]: increment #1
+        [  1]: any-character
+        [  2]: branch-if [  0], #1 < 3
+        [  3]: complete
+        ",
+    )
+    .unwrap();
+    assert_eq!(program.counters, 2);
+    assert!(path_matches(Path::new("abc"), &program).valid_as_complete_match);
+    assert!(!path_matches(Path::new("ab"), &program).valid_as_complete_match);
+}
+
+#[test]
+fn invalid_programs_are_rejected() {
+    for (text, message) in [
+        ("", "the program has no instructions"),
+        (
+            "jump 2\ncomplete",
+            "[  0]: target [  2] is past the end of the program",
+        ),
+        (
+            "# counters=1, absolute_prefix=None\nincrement #1\ncomplete",
+            "[  0]: counter #1 doesn't exist, there are only 1",
+        ),
+        (
+            "increment #65535\ncomplete",
+            "line 1: counter #65535 is out of range, there can only be 65535 counters",
+        ),
+        (
+            "complete\n[  0]: complete",
+            "line 2: labelled [  0], but it is [  1]",
+        ),
+        ("literal-string \"a", "line 1: unexpected end of line"),
+        ("separator 3", "line 1: unexpected \"3\""),
+        ("fork 3", "line 1: unknown instruction \"fork\""),
+        (
+            "characters [Single('a'), Many('b')]",
+            "line 1: unknown character class \"Many\"",
+        ),
+    ] {
+        let err = assemble(text).unwrap_err();
+        assert_eq!(err.to_string(), message, "{}", text);
+    }
+}
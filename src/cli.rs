@@ -0,0 +1,6 @@
+//! Subcommands that are more than a few lines long
+
+pub mod exec;
+pub mod filter;
+pub mod output;
+pub mod repl;
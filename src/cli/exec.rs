@@ -0,0 +1,282 @@
+//! Runs a command for each match, or for batches of matches, without going through a shell
+
+use std::{
+    borrow::Cow,
+    ffi::{OsStr, OsString},
+    path::Path,
+    process::Command,
+    sync::Mutex,
+};
+
+use anyhow::{anyhow, bail};
+
+#[cfg(test)]
+mod tests;
+
+/// Leave plenty of room under the usual `ARG_MAX` for the environment
+const MAX_BATCH_ARG_BYTES: usize = 128 * 1024;
+
+/// The exit status to use when the command couldn't be run at all, like a shell does
+const EXIT_CANNOT_RUN: i32 = 127;
+
+/// A placeholder in a command template, replaced by part of a matching path
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Placeholder {
+    /// `{}`: the whole path
+    Path,
+    /// `{/}`: the file name
+    Basename,
+    /// `{//}`: the parent directory
+    Parent,
+    /// `{.}`: the path without its extension
+    NoExtension,
+    /// `{/.}`: the file name without its extension
+    BasenameNoExtension,
+}
+
+impl Placeholder {
+    /// Longest first, so that `{/}` doesn't get found inside `{//}`
+    const ALL: [(&'static str, Placeholder); 5] = [
+        ("{//}", Placeholder::Parent),
+        ("{/.}", Placeholder::BasenameNoExtension),
+        ("{/}", Placeholder::Basename),
+        ("{.}", Placeholder::NoExtension),
+        ("{}", Placeholder::Path),
+    ];
+
+    fn expand<'a>(&self, path: &'a Path) -> Cow<'a, OsStr> {
+        match self {
+            Placeholder::Path => path.as_os_str().into(),
+            Placeholder::Basename => path.file_name().unwrap_or(path.as_os_str()).into(),
+            Placeholder::Parent => match path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent.as_os_str().into(),
+                _ => OsStr::new(".").into(),
+            },
+            Placeholder::NoExtension => OsString::from(path.with_extension("")).into(),
+            Placeholder::BasenameNoExtension => path.file_stem().unwrap_or(path.as_os_str()).into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Piece {
+    Literal(String),
+    Placeholder(Placeholder),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TemplateArg {
+    /// An argument with no placeholders, which could be anything
+    Plain(OsString),
+    Pieces(Vec<Piece>),
+}
+
+/// A command line with placeholders for each matching path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandTemplate {
+    program: OsString,
+    args: Vec<TemplateArg>,
+}
+
+impl CommandTemplate {
+    /// If no argument has a placeholder, the path is added as an extra argument at the end
+    pub fn parse(command: impl IntoIterator<Item = OsString>) -> anyhow::Result<CommandTemplate> {
+        let mut command = command.into_iter();
+        let program = command
+            .next()
+            .ok_or_else(|| anyhow!("missing command to run"))?;
+        let mut args = command
+            .map(|arg| {
+                let has_placeholder = Placeholder::ALL.iter().any(|(token, _)| {
+                    arg.as_encoded_bytes()
+                        .windows(token.len())
+                        .any(|window| window == token.as_bytes())
+                });
+                if !has_placeholder {
+                    Ok(TemplateArg::Plain(arg))
+                } else if let Some(arg) = arg.to_str() {
+                    Ok(TemplateArg::Pieces(parse_pieces(arg)))
+                } else {
+                    bail!("placeholders can only be used in arguments that are valid UTF-8")
+                }
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        if !args.iter().any(|arg| matches!(arg, TemplateArg::Pieces(_))) {
+            args.push(TemplateArg::Pieces(vec![Piece::Placeholder(
+                Placeholder::Path,
+            )]));
+        }
+        Ok(CommandTemplate { program, args })
+    }
+
+    fn expand_arg(pieces: &[Piece], path: &Path) -> OsString {
+        let mut out = OsString::new();
+        for piece in pieces {
+            match piece {
+                Piece::Literal(string) => out.push(string),
+                Piece::Placeholder(placeholder) => out.push(placeholder.expand(path)),
+            }
+        }
+        out
+    }
+
+    /// The arguments to run the command for a single path
+    pub fn args_for(&self, path: &Path) -> Vec<OsString> {
+        self.args
+            .iter()
+            .map(|arg| match arg {
+                TemplateArg::Plain(arg) => arg.clone(),
+                TemplateArg::Pieces(pieces) => Self::expand_arg(pieces, path),
+            })
+            .collect()
+    }
+
+    /// Check that the command can run for many paths at once. Only one argument can have
+    /// placeholders, as there's no way to line up the paths across several repeated arguments.
+    pub fn check_batch(&self) -> anyhow::Result<()> {
+        let with_placeholders = self
+            .args
+            .iter()
+            .filter(|arg| matches!(arg, TemplateArg::Pieces(_)))
+            .count();
+        if with_placeholders > 1 {
+            bail!("only one argument can have placeholders in batch mode");
+        }
+        Ok(())
+    }
+
+    /// The arguments to run the command once for many paths, where the argument with a
+    /// placeholder is repeated for every path
+    pub fn args_for_batch(&self, paths: &[impl AsRef<Path>]) -> Vec<OsString> {
+        let mut out = vec![];
+        for arg in &self.args {
+            match arg {
+                TemplateArg::Plain(arg) => out.push(arg.clone()),
+                TemplateArg::Pieces(pieces) => out.extend(
+                    paths
+                        .iter()
+                        .map(|path| Self::expand_arg(pieces, path.as_ref())),
+                ),
+            }
+        }
+        out
+    }
+
+    fn run(&self, args: Vec<OsString>) -> Result<(), i32> {
+        match Command::new(&self.program).args(args).status() {
+            Ok(status) if status.success() => Ok(()),
+            Ok(status) => Err(status.code().unwrap_or(1)),
+            Err(err) => {
+                eprintln!("{}: {}", self.program.to_string_lossy(), err);
+                Err(EXIT_CANNOT_RUN)
+            }
+        }
+    }
+
+    /// How many bytes an argument takes up in the argument list passed to the kernel
+    fn arg_cost(arg: &OsStr) -> usize {
+        arg.len() + 1 + std::mem::size_of::<usize>()
+    }
+}
+
+fn parse_pieces(mut arg: &str) -> Vec<Piece> {
+    let mut pieces = vec![];
+    while !arg.is_empty() {
+        let next = Placeholder::ALL
+            .iter()
+            .filter_map(|(token, placeholder)| {
+                arg.find(token).map(|index| (index, *token, *placeholder))
+            })
+            // Earliest, then longest
+            .min_by_key(|(index, token, _)| (*index, usize::MAX - token.len()));
+        match next {
+            Some((index, token, placeholder)) => {
+                if index > 0 {
+                    pieces.push(Piece::Literal(arg[..index].into()));
+                }
+                pieces.push(Piece::Placeholder(placeholder));
+                arg = &arg[index + token.len()..];
+            }
+            None => {
+                pieces.push(Piece::Literal(arg.into()));
+                break;
+            }
+        }
+    }
+    pieces
+}
+
+/// Run the command once per path, with up to `jobs` running at once
+///
+/// Returns the exit status of the first invocation that failed, if any did.
+pub fn exec_each(
+    template: &CommandTemplate,
+    paths: impl Iterator<Item = impl AsRef<Path>> + Send,
+    jobs: usize,
+) -> Option<i32> {
+    let paths = Mutex::new(paths);
+    let failure = Mutex::new(None);
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.max(1) {
+            scope.spawn(|| loop {
+                let Some(path) = paths.lock().unwrap().next() else {
+                    break;
+                };
+                if let Err(code) = template.run(template.args_for(path.as_ref())) {
+                    failure.lock().unwrap().get_or_insert(code);
+                }
+            });
+        }
+    });
+    failure.into_inner().unwrap()
+}
+
+/// Run the command with as many paths at once as fit in the argument list
+///
+/// Returns the exit status of the first invocation that failed, if any did, or an error if the
+/// template can't be used for batches.
+pub fn exec_batch(
+    template: &CommandTemplate,
+    paths: impl Iterator<Item = impl AsRef<Path>>,
+) -> anyhow::Result<Option<i32>> {
+    template.check_batch()?;
+
+    // The arguments that are there no matter how many paths are in the batch
+    let base_cost: usize = std::iter::once(&template.program)
+        .chain(template.args.iter().filter_map(|arg| match arg {
+            TemplateArg::Plain(arg) => Some(arg),
+            TemplateArg::Pieces(_) => None,
+        }))
+        .map(|arg| CommandTemplate::arg_cost(arg))
+        .sum();
+
+    let mut failure = None;
+    let mut batch = vec![];
+    let mut batch_cost = base_cost;
+    let mut run_batch = |batch: &mut Vec<_>| {
+        if !batch.is_empty() {
+            if let Err(code) = template.run(template.args_for_batch(batch)) {
+                failure.get_or_insert(code);
+            }
+            batch.clear();
+        }
+    };
+    for path in paths {
+        let path = path.as_ref().to_owned();
+        let cost: usize = template
+            .args_for(&path)
+            .iter()
+            .zip(&template.args)
+            .filter(|(_, arg)| matches!(arg, TemplateArg::Pieces(_)))
+            .map(|(arg, _)| CommandTemplate::arg_cost(arg))
+            .sum();
+        if batch_cost + cost > MAX_BATCH_ARG_BYTES {
+            run_batch(&mut batch);
+            batch_cost = base_cost;
+        }
+        batch.push(path);
+        batch_cost += cost;
+    }
+    run_batch(&mut batch);
+    Ok(failure)
+}
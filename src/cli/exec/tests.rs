@@ -0,0 +1,89 @@
+use std::{ffi::OsString, path::Path};
+
+use super::CommandTemplate;
+
+fn template(command: &[&str]) -> CommandTemplate {
+    CommandTemplate::parse(command.iter().map(OsString::from)).unwrap()
+}
+
+fn strings(args: Vec<OsString>) -> Vec<String> {
+    args.into_iter()
+        .map(|arg| arg.into_string().unwrap())
+        .collect()
+}
+
+#[test]
+fn path_appended_without_placeholder() {
+    let template = template(&["wc", "-l"]);
+    let args = template.args_for(Path::new("src/main.rs"));
+    assert_eq!(strings(args), ["-l", "src/main.rs"]);
+}
+
+#[test]
+fn placeholders() {
+    let template = template(&["echo", "{}", "{/}", "{//}", "{.}", "{/.}", "{.}.bak"]);
+    let args = template.args_for(Path::new("src/cli/exec.rs"));
+    assert_eq!(
+        strings(args),
+        [
+            "src/cli/exec.rs",
+            "exec.rs",
+            "src/cli",
+            "src/cli/exec",
+            "exec",
+            "src/cli/exec.bak"
+        ]
+    );
+}
+
+#[test]
+fn parent_of_top_level_file() {
+    let template = template(&["echo", "{//}"]);
+    assert_eq!(strings(template.args_for(Path::new("a.rs"))), ["."]);
+}
+
+#[test]
+fn placeholders_repeat_in_batches() {
+    let template = template(&["cp", "-t", "out", "{}"]);
+    let args = template.args_for_batch(&["a", "b"]);
+    assert_eq!(strings(args), ["-t", "out", "a", "b"]);
+}
+
+#[test]
+fn batches_take_one_placeholder_argument() {
+    assert!(template(&["cp", "-t", "out", "{}"]).check_batch().is_ok());
+    assert!(template(&["wc", "-l"]).check_batch().is_ok());
+    assert!(template(&["cp", "{}", "out/{/}"]).check_batch().is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn batch_with_several_placeholder_arguments_runs_nothing() {
+    use super::exec_batch;
+
+    let paths = ["a", "b"];
+    assert!(exec_batch(&template(&["false", "{}", "{/}"]), paths.iter()).is_err());
+}
+
+#[test]
+fn missing_command() {
+    assert!(CommandTemplate::parse(Vec::<OsString>::new()).is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn exit_status_propagates() {
+    use super::{exec_batch, exec_each};
+
+    let paths = ["a", "b", "c"];
+    assert_eq!(exec_each(&template(&["true"]), paths.iter(), 2), None);
+    assert_eq!(exec_each(&template(&["false"]), paths.iter(), 2), Some(1));
+    assert_eq!(
+        exec_batch(&template(&["false"]), paths.iter()).unwrap(),
+        Some(1)
+    );
+    assert_eq!(
+        exec_each(&template(&["/nonexistent/command"]), paths.iter(), 1),
+        Some(127)
+    );
+}
@@ -0,0 +1,113 @@
+//! Filters paths read from stdin, like `grep` does for lines
+
+use std::{
+    fmt,
+    io::{self, BufRead, Write},
+    path::Path,
+};
+
+use rayon::prelude::*;
+
+use glob_experiment::{compiler::Program, matcher::path_matches, prefilter::Prefilter};
+
+#[cfg(test)]
+mod tests;
+
+/// How many paths to match in parallel before writing out the matches
+const BATCH_SIZE: usize = 4096;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FilterOptions {
+    /// Paths are separated by NUL rather than newline, on both input and output
+    pub null: bool,
+    /// Output the paths that don't match instead
+    pub invert: bool,
+}
+
+/// Counts of what happened to the paths given to [`filter`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FilterStats {
+    pub read: usize,
+    pub written: usize,
+    /// Paths that the [`Prefilter`] ruled out, without running the program
+    pub rejected: usize,
+}
+
+impl fmt::Display for FilterStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let percent = |count: usize| 100.0 * count as f64 / self.read.max(1) as f64;
+        write!(
+            f,
+            "read {} paths, wrote {} ({:.1}%), prefilter rejected {} ({:.1}%)",
+            self.read,
+            self.written,
+            percent(self.written),
+            self.rejected,
+            percent(self.rejected)
+        )
+    }
+}
+
+/// Write the paths from `input` that match `program` to `output`, in the same order
+///
+/// Paths are checked with a [`Prefilter`] first, so most that can't match never reach the
+/// matcher.
+pub fn filter(
+    program: &Program,
+    options: FilterOptions,
+    input: impl BufRead,
+    mut output: impl Write,
+) -> io::Result<FilterStats> {
+    let prefilter = Prefilter::new(program);
+    let delimiter = if options.null { b'\0' } else { b'\n' };
+    // An empty record isn't a path, wherever it is, just like there's none after a trailing
+    // separator
+    let mut lines = input
+        .split(delimiter)
+        .filter(|line| !line.as_ref().is_ok_and(Vec::is_empty));
+    let mut stats = FilterStats::default();
+    loop {
+        let batch = lines
+            .by_ref()
+            .take(BATCH_SIZE)
+            .collect::<io::Result<Vec<_>>>()?;
+        if batch.is_empty() {
+            break;
+        }
+        // Whether each path matched, or None if the prefilter rejected it
+        let matched: Vec<Option<bool>> = batch
+            .par_iter()
+            .map(|line| {
+                let Some(path) = path_from_bytes(line) else {
+                    return Some(false);
+                };
+                prefilter
+                    .might_match(path)
+                    .then(|| path_matches(path, program).valid_as_complete_match)
+            })
+            .collect();
+        stats.read += batch.len();
+        for (line, matched) in batch.iter().zip(matched) {
+            stats.rejected += usize::from(matched.is_none());
+            if matched.unwrap_or(false) != options.invert {
+                output.write_all(line)?;
+                output.write_all(&[delimiter])?;
+                stats.written += 1;
+            }
+        }
+    }
+    output.flush()?;
+    Ok(stats)
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> Option<&Path> {
+    use std::os::unix::ffi::OsStrExt;
+    Some(Path::new(std::ffi::OsStr::from_bytes(bytes)))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> Option<&Path> {
+    // Paths on other platforms aren't arbitrary bytes, so they have to be valid UTF-8 here
+    std::str::from_utf8(bytes).ok().map(Path::new)
+}
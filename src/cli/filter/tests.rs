@@ -0,0 +1,93 @@
+use glob_experiment::{compiler::compile, parser::parse};
+
+use super::{filter, FilterOptions, FilterStats};
+
+fn run(pattern: &str, options: FilterOptions, input: &[u8]) -> (usize, Vec<u8>) {
+    let (stats, output) = run_with_stats(pattern, options, input);
+    (stats.written, output)
+}
+
+fn run_with_stats(pattern: &str, options: FilterOptions, input: &[u8]) -> (FilterStats, Vec<u8>) {
+    let program = compile(&parse(pattern)).unwrap();
+    let mut output = vec![];
+    let stats = filter(&program, options, input, &mut output).unwrap();
+    (stats, output)
+}
+
+#[test]
+fn keeps_matches_in_order() {
+    let input = "b.rs\nsrc/a.rs\nREADME.md\na.rs\n".repeat(5000);
+    let (written, output) = run("*.rs", FilterOptions::default(), input.as_bytes());
+    assert_eq!(written, 10000);
+    assert_eq!(output, "b.rs\na.rs\n".repeat(5000).as_bytes());
+}
+
+#[test]
+fn invert() {
+    let options = FilterOptions {
+        invert: true,
+        ..FilterOptions::default()
+    };
+    let (written, output) = run("*.rs", options, b"a.rs\nREADME.md\n");
+    assert_eq!(written, 1);
+    assert_eq!(output, b"README.md\n");
+}
+
+#[test]
+fn null_separated() {
+    let options = FilterOptions {
+        null: true,
+        ..FilterOptions::default()
+    };
+    let (written, output) = run("*.md", options, b"a\nb.md\0main.rs\0README.md\0");
+    assert_eq!(written, 2);
+    assert_eq!(output, b"a\nb.md\0README.md\0");
+}
+
+#[test]
+fn empty_records_are_skipped() {
+    let (stats, output) = run_with_stats("{,a}", FilterOptions::default(), b"a\n\nb\n");
+    assert_eq!(output, b"a\n");
+    assert_eq!(stats.read, 2);
+    let options = FilterOptions {
+        null: true,
+        invert: true,
+    };
+    let (written, output) = run("{,a}", options, b"\0a\0\0b\0");
+    assert_eq!(written, 1);
+    assert_eq!(output, b"b\0");
+}
+
+#[test]
+fn nothing_matched() {
+    let (written, output) = run("*.rs", FilterOptions::default(), b"README.md\n");
+    assert_eq!(written, 0);
+    assert!(output.is_empty());
+}
+
+#[test]
+fn prefilter_rejections_are_counted() {
+    let input = b"a_test.go\nmain.go\nREADME.md\nb_test.go/c\nsrc/x_test.go\n";
+    let (stats, output) = run_with_stats("**/*_test.go", FilterOptions::default(), input);
+    assert_eq!(output, b"a_test.go\nsrc/x_test.go\n");
+    assert_eq!(
+        stats,
+        FilterStats {
+            read: 5,
+            written: 2,
+            rejected: 3,
+        }
+    );
+    assert_eq!(
+        stats.to_string(),
+        "read 5 paths, wrote 2 (40.0%), prefilter rejected 3 (60.0%)"
+    );
+
+    let options = FilterOptions {
+        invert: true,
+        ..FilterOptions::default()
+    };
+    let (stats, output) = run_with_stats("**/*_test.go", options, input);
+    assert_eq!(output, b"main.go\nREADME.md\nb_test.go/c\n");
+    assert_eq!(stats.rejected, 3);
+}
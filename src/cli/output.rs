@@ -0,0 +1,156 @@
+//! Output formats for the results of the subcommands
+
+use std::{
+    io::{self, Write},
+    str::FromStr,
+};
+
+use anyhow::bail;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use glob_experiment::globber::GlobMatch;
+#[cfg(feature = "serde")]
+use glob_experiment::lossless::LosslessBytes;
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    /// One path per line, or the plain text form of anything else
+    #[default]
+    Lines,
+    /// Paths terminated by NUL, for filenames containing newlines
+    Null,
+    /// A single JSON document
+    #[cfg(feature = "serde")]
+    Json,
+    /// One JSON document per line
+    #[cfg(feature = "serde")]
+    Jsonl,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(string: &str) -> anyhow::Result<Format> {
+        Ok(match string {
+            "lines" => Format::Lines,
+            "null" => Format::Null,
+            #[cfg(feature = "serde")]
+            "json" => Format::Json,
+            #[cfg(feature = "serde")]
+            "jsonl" => Format::Jsonl,
+            #[cfg(not(feature = "serde"))]
+            "json" | "jsonl" => bail!("the {string} format needs the serde feature"),
+            _ => bail!("unknown format {string:?}, expected lines, null, json or jsonl"),
+        })
+    }
+}
+
+#[cfg(feature = "serde")]
+#[derive(Serialize)]
+struct JsonMatch<'a> {
+    path: LosslessBytes<'a>,
+    patterns: &'a [usize],
+}
+
+/// Writes glob matches out one at a time
+pub struct MatchWriter<W: Write> {
+    format: Format,
+    out: W,
+    /// Nothing has been written into the JSON array yet
+    #[cfg(feature = "serde")]
+    first: bool,
+}
+
+impl<W: Write> MatchWriter<W> {
+    pub fn new(format: Format, out: W) -> MatchWriter<W> {
+        MatchWriter {
+            format,
+            out,
+            #[cfg(feature = "serde")]
+            first: true,
+        }
+    }
+
+    pub fn write(&mut self, glob_match: &GlobMatch) -> io::Result<()> {
+        let path = glob_match.path.as_os_str().as_encoded_bytes();
+        #[cfg(feature = "serde")]
+        let json_match = JsonMatch {
+            path: LosslessBytes(path),
+            patterns: &glob_match.patterns,
+        };
+        match self.format {
+            Format::Lines => {
+                self.out.write_all(path)?;
+                self.out.write_all(b"\n")
+            }
+            Format::Null => {
+                self.out.write_all(path)?;
+                self.out.write_all(b"\0")
+            }
+            #[cfg(feature = "serde")]
+            Format::Json => {
+                self.out
+                    .write_all(if self.first { b"[\n" } else { b",\n" })?;
+                serde_json::to_writer(&mut self.out, &json_match)?;
+                self.first = false;
+                Ok(())
+            }
+            #[cfg(feature = "serde")]
+            Format::Jsonl => {
+                serde_json::to_writer(&mut self.out, &json_match)?;
+                self.out.write_all(b"\n")
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        #[cfg(feature = "serde")]
+        if self.format == Format::Json {
+            self.out
+                .write_all(if self.first { b"[]\n" } else { b"\n]\n" })?;
+        }
+        self.out.flush()
+    }
+}
+
+/// Write a value that only has a plain text form and a JSON form
+#[cfg(feature = "serde")]
+pub fn write_value<T: Serialize>(
+    format: Format,
+    value: &T,
+    text: impl FnOnce() -> String,
+    mut out: impl Write,
+) -> anyhow::Result<()> {
+    match format {
+        Format::Lines => write!(out, "{}", text())?,
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut out, value)?;
+            writeln!(out)?;
+        }
+        Format::Jsonl => {
+            serde_json::to_writer(&mut out, value)?;
+            writeln!(out)?;
+        }
+        Format::Null => bail!("the null format is only for lists of paths"),
+    }
+    Ok(())
+}
+
+/// Write a value that only has a plain text form, as there's no JSON without serde
+#[cfg(not(feature = "serde"))]
+pub fn write_value<T>(
+    format: Format,
+    _value: &T,
+    text: impl FnOnce() -> String,
+    mut out: impl Write,
+) -> anyhow::Result<()> {
+    match format {
+        Format::Lines => write!(out, "{}", text())?,
+        Format::Null => bail!("the null format is only for lists of paths"),
+    }
+    Ok(())
+}
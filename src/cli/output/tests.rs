@@ -0,0 +1,67 @@
+use std::path::PathBuf;
+
+use glob_experiment::globber::GlobMatch;
+
+use super::{Format, MatchWriter};
+
+fn write_all(format: Format, paths: &[impl Into<PathBuf> + Clone]) -> Vec<u8> {
+    let mut out = vec![];
+    let mut writer = MatchWriter::new(format, &mut out);
+    for path in paths {
+        let glob_match = GlobMatch {
+            path: path.clone().into(),
+            patterns: vec![0],
+        };
+        writer.write(&glob_match).unwrap();
+    }
+    writer.finish().unwrap();
+    out
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn json_empty_array() {
+    assert_eq!(write_all(Format::Json, &[] as &[&str]), b"[]\n");
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn json_array() {
+    let out = write_all(Format::Json, &["a", "b"]);
+    let value: serde_json::Value = serde_json::from_slice(&out).unwrap();
+    assert_eq!(
+        value,
+        serde_json::json!([
+            {"path": "a", "patterns": [0]},
+            {"path": "b", "patterns": [0]},
+        ])
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn jsonl_keeps_newlines_in_names() {
+    let out = write_all(Format::Jsonl, &["a\nb"]);
+    assert_eq!(out, b"{\"path\":\"a\\nb\",\"patterns\":[0]}\n");
+}
+
+#[cfg(all(unix, feature = "serde"))]
+#[test]
+fn jsonl_non_utf8_as_bytes() {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    let out = write_all(Format::Jsonl, &[OsStr::from_bytes(b"a\xff")]);
+    assert_eq!(out, b"{\"path\":{\"bytes\":[97,255]},\"patterns\":[0]}\n");
+}
+
+#[test]
+fn null_separated() {
+    assert_eq!(write_all(Format::Null, &["a\nb", "c"]), b"a\nb\0c\0");
+}
+
+#[cfg(not(feature = "serde"))]
+#[test]
+fn json_needs_serde() {
+    assert!("json".parse::<Format>().is_err());
+    assert!("jsonl".parse::<Format>().is_err());
+}
@@ -0,0 +1,243 @@
+//! An interactive loop for trying out patterns against a directory
+
+use std::{
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::anyhow;
+
+use glob_experiment::{
+    compiler::{compile_with, CompileOptions, Program},
+    explain,
+    filesystem::FileSystem,
+    globber::{glob_with, GlobOptions, Parallelism},
+    matcher::path_matches,
+    parser::{parse, AstNode},
+};
+
+#[cfg(test)]
+mod tests;
+
+const HELP: &str = "\
+Type a pattern to see its AST, program and matches, or one of these commands:
+  :test <path>     check whether a path matches the pattern
+  :explain <path>  show why a path does or doesn't match
+  :case            toggle case-insensitive matching
+  :hidden          toggle skipping hidden files
+  :limit <n>       show at most n matches
+  :help            show this message
+  :quit            exit
+";
+
+pub struct Repl<F> {
+    fs: Arc<F>,
+    relative_to: PathBuf,
+    /// The last pattern entered, and its program
+    current: Option<(String, Program)>,
+    compile_options: CompileOptions,
+    skip_hidden: bool,
+    limit: usize,
+}
+
+impl<F: FileSystem + 'static> Repl<F> {
+    pub fn new(fs: Arc<F>, relative_to: impl Into<PathBuf>) -> Repl<F> {
+        Repl {
+            fs,
+            relative_to: relative_to.into(),
+            current: None,
+            compile_options: CompileOptions::default(),
+            skip_hidden: false,
+            limit: 20,
+        }
+    }
+
+    /// Prompt for and handle lines from `input` until it ends or the user quits
+    pub fn run(&mut self, input: impl BufRead, mut out: impl Write) -> anyhow::Result<()> {
+        let mut lines = input.lines();
+        loop {
+            write!(out, "> ")?;
+            out.flush()?;
+            let Some(line) = lines.next().transpose()? else {
+                writeln!(out)?;
+                return Ok(());
+            };
+            match self.handle_line(&line, &mut out) {
+                Ok(true) => (),
+                Ok(false) => return Ok(()),
+                Err(err) => writeln!(out, "error: {}", err)?,
+            }
+        }
+    }
+
+    /// Handle one line of input, returning false if the user asked to quit
+    pub fn handle_line(&mut self, line: &str, out: &mut impl Write) -> anyhow::Result<bool> {
+        let line = line.trim();
+        let (command, argument) = match line.split_once(char::is_whitespace) {
+            Some((command, argument)) => (command, argument.trim()),
+            None => (line, ""),
+        };
+        match command {
+            "" => (),
+            ":quit" | ":q" => return Ok(false),
+            ":help" | ":h" => write!(out, "{}", HELP)?,
+            ":test" | ":t" => {
+                let (_, program) = self.current()?;
+                let result = path_matches(Path::new(argument), program);
+                writeln!(
+                    out,
+                    "{}",
+                    match (result.valid_as_complete_match, result.valid_as_prefix) {
+                        (true, true) => "matches, and longer paths could match too",
+                        (true, false) => "matches",
+                        (false, true) => "no match, but longer paths could match",
+                        (false, false) => "no match",
+                    }
+                )?;
+            }
+            ":explain" | ":e" => {
+                let (pattern, program) = self.current()?;
+                write!(
+                    out,
+                    "{}",
+                    explain::explain(pattern, Path::new(argument), program)
+                )?;
+            }
+            ":case" => {
+                self.compile_options.case_insensitive = !self.compile_options.case_insensitive;
+                self.recompile()?;
+                self.show(out)?;
+            }
+            ":hidden" => {
+                self.skip_hidden = !self.skip_hidden;
+                self.show(out)?;
+            }
+            ":limit" => {
+                self.limit = argument.parse()?;
+                self.show(out)?;
+            }
+            _ if command.starts_with(':') => {
+                return Err(anyhow!("unknown command {}, try :help", command));
+            }
+            _ => {
+                let program = compile_with(&parse(line), &self.compile_options)?;
+                self.current = Some((line.to_owned(), program));
+                self.show(out)?;
+            }
+        }
+        Ok(true)
+    }
+
+    fn current(&self) -> anyhow::Result<(&str, &Program)> {
+        self.current
+            .as_ref()
+            .map(|(pattern, program)| (pattern.as_str(), program))
+            .ok_or_else(|| anyhow!("enter a pattern first"))
+    }
+
+    fn recompile(&mut self) -> anyhow::Result<()> {
+        if let Some((pattern, program)) = &mut self.current {
+            *program = compile_with(&parse(&*pattern), &self.compile_options)?;
+        }
+        Ok(())
+    }
+
+    /// Show everything about the current pattern
+    fn show(&self, out: &mut impl Write) -> anyhow::Result<()> {
+        let Some((pattern, program)) = &self.current else {
+            writeln!(
+                out,
+                "case-insensitive: {}, skip hidden: {}, limit: {}",
+                self.compile_options.case_insensitive, self.skip_hidden, self.limit
+            )?;
+            return Ok(());
+        };
+
+        writeln!(out, "ast:")?;
+        write_nodes(out, &parse(pattern).nodes, 1)?;
+        writeln!(out, "program:")?;
+        write!(out, "{}", program)?;
+
+        let mut flags = vec![];
+        if self.compile_options.case_insensitive {
+            flags.push("case-insensitive");
+        }
+        if self.skip_hidden {
+            flags.push("skipping hidden files");
+        }
+        if flags.is_empty() {
+            writeln!(out, "matches:")?;
+        } else {
+            writeln!(out, "matches ({}):", flags.join(", "))?;
+        }
+
+        // Walk sequentially so that the matches shown don't change from run to run
+        let options = GlobOptions {
+            limit: Some(self.limit + 1),
+            parallelism: Parallelism::Sequential,
+            skip_hidden: self.skip_hidden,
+            ..GlobOptions::default()
+        };
+        let results = glob_with(
+            self.fs.clone(),
+            self.relative_to.clone(),
+            Arc::new(program.clone()),
+            options,
+        );
+        let mut found = 0;
+        for result in results {
+            if found == self.limit {
+                writeln!(out, "  ... more not shown, see :limit")?;
+                break;
+            }
+            match result {
+                Ok(path) => {
+                    found += 1;
+                    writeln!(out, "  {}", path.display())?;
+                }
+                Err(err) => writeln!(out, "  error: {}", err)?,
+            }
+        }
+        if found == 0 {
+            writeln!(out, "  (none)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Write an AST as an indented outline, which is easier to read than its debug representation
+fn write_nodes(out: &mut impl Write, nodes: &[AstNode], depth: usize) -> anyhow::Result<()> {
+    let indent = "  ".repeat(depth);
+    for node in nodes {
+        match node {
+            AstNode::Prefix(prefix) => writeln!(out, "{}prefix {:?}", indent, prefix)?,
+            AstNode::RootDir => writeln!(out, "{}root-dir", indent)?,
+            AstNode::CurDir => writeln!(out, "{}cur-dir", indent)?,
+            AstNode::ParentDir => writeln!(out, "{}parent-dir", indent)?,
+            AstNode::Separator => writeln!(out, "{}separator", indent)?,
+            AstNode::LiteralString(bytes) => writeln!(
+                out,
+                "{}literal {:?}",
+                indent,
+                String::from_utf8_lossy(bytes)
+            )?,
+            AstNode::AnyCharacter => writeln!(out, "{}any-character", indent)?,
+            AstNode::Characters(classes) => writeln!(out, "{}characters {:?}", indent, classes)?,
+            AstNode::Wildcard => writeln!(out, "{}wildcard", indent)?,
+            AstNode::Recurse => writeln!(out, "{}recurse", indent)?,
+            AstNode::Alternatives { choices } => {
+                writeln!(out, "{}alternatives", indent)?;
+                for choice in choices {
+                    writeln!(out, "{}  choice", indent)?;
+                    write_nodes(out, &choice.nodes, depth + 2)?;
+                }
+            }
+            AstNode::Repeat { min, max, pattern } => {
+                writeln!(out, "{}repeat {}..={}", indent, min, max)?;
+                write_nodes(out, &pattern.nodes, depth + 1)?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,57 @@
+use std::sync::Arc;
+
+use glob_experiment::filesystem::MemoryFileSystem;
+
+use super::Repl;
+
+fn run(script: &str) -> String {
+    let mut fs = MemoryFileSystem::new();
+    fs.add_file("/proj/README.md", 10)
+        .add_file("/proj/src/Main.rs", 10)
+        .add_file("/proj/.hidden.rs", 10);
+    let mut repl = Repl::new(Arc::new(fs), "/proj");
+    let mut out = vec![];
+    repl.run(script.as_bytes(), &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn shows_pattern_and_matches() {
+    let out = run("**/*.rs\n");
+    assert!(out.contains("ast:\n  recurse\n  separator\n  wildcard\n  literal \".rs\"\n"));
+    assert!(out.contains("program:\n# counters=0"));
+    assert!(
+        out.contains("matches:\n  .hidden.rs\n  src/Main.rs\n"),
+        "{}",
+        out
+    );
+}
+
+#[test]
+fn toggles_options() {
+    let out = run("src/main.rs\n:case\n:hidden\n*.rs\n");
+    assert!(out.contains("matches:\n  (none)\n"), "{}", out);
+    assert!(
+        out.contains("matches (case-insensitive):\n  src/Main.rs\n"),
+        "{}",
+        out
+    );
+    assert!(out.contains("matches (case-insensitive, skipping hidden files):\n  (none)\n"));
+}
+
+#[test]
+fn tests_paths() {
+    let out = run(":test a\n{a,b}<c:1,2>\n:test bcc\n:test bccc\n:quit\n:test b\n");
+    assert!(
+        out.starts_with("> error: enter a pattern first\n"),
+        "{}",
+        out
+    );
+    assert!(out.contains("> matches\n> no match\n> "), "{}", out);
+}
+
+#[test]
+fn limits_matches() {
+    let out = run(":limit 1\n**/*\n");
+    assert!(out.contains("matches:\n  .hidden.rs\n  ... more not shown, see :limit\n"));
+}
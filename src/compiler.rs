@@ -1,14 +1,25 @@
 //! Compiles a glob pattern into a simple set of instructions
 
-use std::path::{Component, PathBuf};
+use std::{
+    path::{Component, PathBuf},
+    sync::OnceLock,
+};
 
-use crate::parser::{AstNode, CharacterClass, Pattern};
+use crate::{
+    optimizer,
+    parser::{AstNode, CharacterClass, Pattern, Span},
+    verifier,
+};
+
+#[cfg(test)]
+mod tests;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ProgramOffset(pub usize);
 
 impl ProgramOffset {
-    const PLACEHOLDER: ProgramOffset = ProgramOffset(usize::MAX);
+    pub(crate) const PLACEHOLDER: ProgramOffset = ProgramOffset(usize::MAX);
 }
 
 impl std::ops::Add<usize> for ProgramOffset {
@@ -26,6 +37,7 @@ impl std::fmt::Display for ProgramOffset {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CounterId(pub u16);
 
 impl std::fmt::Display for CounterId {
@@ -35,16 +47,40 @@ impl std::fmt::Display for CounterId {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
 pub enum Instruction {
     Separator,
     Prefix(Box<str>),
     RootDir,
     CurDir,
     ParentDir,
-    LiteralString(Box<[u8]>),
+    LiteralString(
+        #[cfg_attr(
+            feature = "serde",
+            serde(
+                serialize_with = "crate::lossless::serialize",
+                deserialize_with = "crate::lossless::deserialize"
+            )
+        )]
+        Box<[u8]>,
+    ),
     AnyCharacter,
     AnyString,
     Characters(Box<[CharacterClass]>),
+    /// Skip any characters up to an occurrence of the literal, then consume it. This is what `*`
+    /// followed by a literal compiles to once optimized, trying every occurrence like the loop
+    /// would.
+    SkipToLiteral(
+        #[cfg_attr(
+            feature = "serde",
+            serde(
+                serialize_with = "crate::lossless::serialize",
+                deserialize_with = "crate::lossless::deserialize"
+            )
+        )]
+        Box<[u8]>,
+    ),
     Jump(ProgramOffset),
     Alternative(ProgramOffset),
     Increment(CounterId),
@@ -52,6 +88,28 @@ pub enum Instruction {
     Complete,
 }
 
+impl Instruction {
+    /// Shift any program offsets and counter ids, so the instruction can be copied into another
+    /// program. The shifted counter ids must fit in a `u16`.
+    pub fn relocate(&self, offset: usize, counter: u16) -> Instruction {
+        match self {
+            Instruction::Jump(index) => Instruction::Jump(*index + offset),
+            Instruction::Alternative(index) => Instruction::Alternative(*index + offset),
+            Instruction::Increment(counter_id) => {
+                Instruction::Increment(CounterId(counter_id.0 + counter))
+            }
+            Instruction::BranchIfLessThan(index, counter_id, value) => {
+                Instruction::BranchIfLessThan(
+                    *index + offset,
+                    CounterId(counter_id.0 + counter),
+                    *value,
+                )
+            }
+            _ => self.clone(),
+        }
+    }
+}
+
 impl std::fmt::Display for Instruction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         const WIDTH: usize = 20;
@@ -72,6 +130,10 @@ impl std::fmt::Display for Instruction {
             Instruction::Characters(character_classes) => {
                 write!(f, "{:<WIDTH$} {:?}", "characters", character_classes)
             }
+            Instruction::SkipToLiteral(bytes) => {
+                let as_string = String::from_utf8_lossy(bytes);
+                write!(f, "{:<WIDTH$} {:?}", "skip-to-literal", as_string)
+            }
             Instruction::Jump(index) => {
                 write!(f, "{:<WIDTH$} {:>05}", "jump", index)
             }
@@ -93,17 +155,61 @@ impl std::fmt::Display for Instruction {
     }
 }
 
+/// A compiled pattern. Programs that are deserialized are checked with [`verifier::verify`]
+/// first.
 #[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "UnverifiedProgram"))]
 pub struct Program {
     pub instructions: Vec<Instruction>,
     pub counters: u16,
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "crate::lossless::serialize_optional_path")
+    )]
     pub absolute_prefix: Option<PathBuf>,
+    /// The part of the pattern string that each instruction was compiled from, if known. This can
+    /// be shorter than `instructions`.
+    pub spans: Vec<Option<Span>>,
+}
+
+/// A program as it is deserialized, before it has been verified
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct UnverifiedProgram {
+    instructions: Vec<Instruction>,
+    counters: u16,
+    #[serde(deserialize_with = "crate::lossless::deserialize_optional_path")]
+    absolute_prefix: Option<PathBuf>,
+    #[serde(default)]
+    spans: Vec<Option<Span>>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<UnverifiedProgram> for Program {
+    type Error = anyhow::Error;
+
+    fn try_from(unverified: UnverifiedProgram) -> anyhow::Result<Program> {
+        let program = Program {
+            instructions: unverified.instructions,
+            counters: unverified.counters,
+            absolute_prefix: unverified.absolute_prefix,
+            spans: unverified.spans,
+        };
+        verifier::verify(&program)?;
+        Ok(program)
+    }
 }
 
 impl Program {
     fn here(&self) -> ProgramOffset {
         ProgramOffset(self.instructions.len())
     }
+
+    /// The part of the pattern string that the instruction at `pc` was compiled from
+    pub fn span(&self, pc: ProgramOffset) -> Option<Span> {
+        self.spans.get(pc.0).cloned().flatten()
+    }
 }
 
 impl std::fmt::Display for Program {
@@ -121,7 +227,31 @@ impl std::fmt::Display for Program {
     }
 }
 
-fn append_program(out: &mut Program, node: &AstNode) -> anyhow::Result<()> {
+fn append_nodes(
+    out: &mut Program,
+    pattern: &Pattern,
+    options: &CompileOptions,
+) -> anyhow::Result<()> {
+    for (index, node) in pattern.nodes.iter().enumerate() {
+        let start = out.instructions.len();
+        append_program(out, node, options)?;
+
+        // Nested nodes have already claimed their instructions, the rest belong to this node
+        out.spans.resize(out.instructions.len(), None);
+        if let Some(span) = pattern.spans.get(index) {
+            for slot in &mut out.spans[start..] {
+                slot.get_or_insert_with(|| span.clone());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn append_program(
+    out: &mut Program,
+    node: &AstNode,
+    options: &CompileOptions,
+) -> anyhow::Result<()> {
     match node {
         AstNode::Separator => {
             out.instructions.push(Instruction::Separator);
@@ -150,6 +280,10 @@ fn append_program(out: &mut Program, node: &AstNode) -> anyhow::Result<()> {
             out.instructions.push(Instruction::ParentDir);
             Ok(())
         }
+        AstNode::LiteralString(string) if options.case_insensitive => {
+            append_caseless_literal(out, string);
+            Ok(())
+        }
         AstNode::LiteralString(string) => {
             out.instructions
                 .push(Instruction::LiteralString(string.as_slice().into()));
@@ -159,6 +293,11 @@ fn append_program(out: &mut Program, node: &AstNode) -> anyhow::Result<()> {
             out.instructions.push(Instruction::AnyCharacter);
             Ok(())
         }
+        AstNode::Characters(character_classes) if options.case_insensitive => {
+            out.instructions
+                .push(Instruction::Characters(caseless_classes(character_classes)));
+            Ok(())
+        }
         AstNode::Characters(character_classes) => {
             out.instructions
                 .push(Instruction::Characters(character_classes.as_slice().into()));
@@ -166,8 +305,109 @@ fn append_program(out: &mut Program, node: &AstNode) -> anyhow::Result<()> {
         }
         AstNode::Wildcard => append_wildcard_gadget(out),
         AstNode::Recurse => append_recurse_gadget(out),
-        AstNode::Alternatives { choices } => append_alternatives(out, choices),
-        AstNode::Repeat { min, max, pattern } => append_repeat(out, *min, *max, pattern),
+        AstNode::Alternatives { choices } => append_alternatives(out, choices, options),
+        AstNode::Repeat { min, max, pattern } => append_repeat(out, *min, *max, pattern, options),
+    }
+}
+
+/// Append a literal that matches any case, as a character class for each letter that has more
+/// than one case, and literal strings for everything in between
+fn append_caseless_literal(out: &mut Program, string: &[u8]) {
+    let mut literal = vec![];
+    for chunk in string.utf8_chunks() {
+        for ch in chunk.valid().chars() {
+            let classes = caseless_classes(&[CharacterClass::Single(ch)]);
+            if classes.len() == 1 {
+                let mut buf = [0; 4];
+                literal.extend_from_slice(ch.encode_utf8(&mut buf).as_bytes());
+            } else {
+                if !literal.is_empty() {
+                    out.instructions.push(Instruction::LiteralString(
+                        std::mem::take(&mut literal).into(),
+                    ));
+                }
+                out.instructions.push(Instruction::Characters(classes));
+            }
+        }
+        literal.extend_from_slice(chunk.invalid());
+    }
+    if !literal.is_empty() {
+        out.instructions
+            .push(Instruction::LiteralString(literal.into()));
+    }
+}
+
+/// Add the other cases of each character to a set of character classes
+fn caseless_classes(classes: &[CharacterClass]) -> Box<[CharacterClass]> {
+    let mut out = classes.to_vec();
+    for class in classes {
+        match *class {
+            CharacterClass::Single(ch) => {
+                // Only simple case mappings can be matched a character at a time
+                for other in simple_case_mappings(ch) {
+                    if !out.contains(&CharacterClass::Single(other)) {
+                        out.push(CharacterClass::Single(other));
+                    }
+                }
+            }
+            CharacterClass::Range(start, end) => {
+                // Case doesn't map ranges onto ranges in general, so map each character that
+                // has other cases and gather the results back up into ranges
+                let mappings = case_mappings();
+                let first = mappings.partition_point(|(ch, _)| *ch < start);
+                let last = mappings.partition_point(|(ch, _)| *ch <= end);
+                let mut others: Vec<char> = mappings[first..last]
+                    .iter()
+                    .map(|(_, other)| *other)
+                    .filter(|other| !(start..=end).contains(other))
+                    .collect();
+                others.sort_unstable();
+                others.dedup();
+                let mut others = others.into_iter();
+                if let Some(first) = others.next() {
+                    let (mut low, mut high) = (first, first);
+                    for other in others {
+                        if u32::from(other) == u32::from(high) + 1 {
+                            high = other;
+                        } else {
+                            out.push(class_between(low, high));
+                            (low, high) = (other, other);
+                        }
+                    }
+                    out.push(class_between(low, high));
+                }
+            }
+        }
+    }
+    out.into()
+}
+
+/// Every character with other cases that it maps to simply, paired with each of them, in order of
+/// the character. Found once, so that folding a wide range doesn't have to look at every
+/// character in it.
+fn case_mappings() -> &'static [(char, char)] {
+    static MAPPINGS: OnceLock<Vec<(char, char)>> = OnceLock::new();
+    MAPPINGS.get_or_init(|| {
+        ('\0'..=char::MAX)
+            .flat_map(|ch| simple_case_mappings(ch).map(move |other| (ch, other)))
+            .collect()
+    })
+}
+
+/// The other cases of a character, if it only maps to single characters
+fn simple_case_mappings(ch: char) -> impl Iterator<Item = char> {
+    let (lower, upper) = (ch.to_lowercase(), ch.to_uppercase());
+    let simple = lower.len() == 1 && upper.len() == 1;
+    lower
+        .chain(upper)
+        .filter(move |other| simple && *other != ch)
+}
+
+fn class_between(low: char, high: char) -> CharacterClass {
+    if low == high {
+        CharacterClass::Single(low)
+    } else {
+        CharacterClass::Range(low, high)
     }
 }
 
@@ -192,7 +432,11 @@ fn append_recurse_gadget(out: &mut Program) -> anyhow::Result<()> {
     Ok(())
 }
 
-fn append_alternatives(out: &mut Program, choices: &[Pattern]) -> anyhow::Result<()> {
+fn append_alternatives(
+    out: &mut Program,
+    choices: &[Pattern],
+    options: &CompileOptions,
+) -> anyhow::Result<()> {
     // To compile alternatives, we first set up (choices.len() - 1) Alternative instructions
     let start = out.instructions.len();
     for _ in 0..choices.len().saturating_sub(1) {
@@ -207,9 +451,7 @@ fn append_alternatives(out: &mut Program, choices: &[Pattern]) -> anyhow::Result
             // instruction here
             out.instructions[start + (index - 1)] = Instruction::Alternative(choice_start);
         }
-        for node in &choice.nodes {
-            append_program(out, node)?;
-        }
+        append_nodes(out, choice, options)?;
         // We also put a jump to the end
         jumps.push(out.here());
         out.instructions
@@ -222,8 +464,14 @@ fn append_alternatives(out: &mut Program, choices: &[Pattern]) -> anyhow::Result
     Ok(())
 }
 
-fn append_repeat(out: &mut Program, min: u32, max: u32, pattern: &Pattern) -> anyhow::Result<()> {
-    if out.counters >= u16::MAX {
+fn append_repeat(
+    out: &mut Program,
+    min: u32,
+    max: u32,
+    pattern: &Pattern,
+    options: &CompileOptions,
+) -> anyhow::Result<()> {
+    if out.counters == u16::MAX {
         anyhow::bail!("Exceeded the number of repeats allowed in a glob pattern");
     }
 
@@ -235,9 +483,7 @@ fn append_repeat(out: &mut Program, min: u32, max: u32, pattern: &Pattern) -> an
     // This is the loop start - increase the counter
     out.instructions.push(Instruction::Increment(counter_id));
 
-    for node in &pattern.nodes {
-        append_program(out, node)?;
-    }
+    append_nodes(out, pattern, options)?;
 
     // If we have less than the minimum, another loop is required
     out.instructions
@@ -256,11 +502,32 @@ fn append_repeat(out: &mut Program, min: u32, max: u32, pattern: &Pattern) -> an
     Ok(())
 }
 
+/// Options that change what a compiled program matches
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompileOptions {
+    /// Match letters in either case. Only characters whose case maps to a single character are
+    /// affected.
+    pub case_insensitive: bool,
+    /// Run the program through [`optimizer::optimize`], which makes it smaller and faster without
+    /// changing what it matches
+    pub optimize: bool,
+}
+
 pub fn compile(pattern: &Pattern) -> anyhow::Result<Program> {
+    compile_with(pattern, &CompileOptions::default())
+}
+
+pub fn compile_with(pattern: &Pattern, options: &CompileOptions) -> anyhow::Result<Program> {
     let mut program = Program::default();
-    for node in &pattern.nodes {
-        append_program(&mut program, node)?;
-    }
+    append_nodes(&mut program, pattern, options)?;
     program.instructions.push(Instruction::Complete);
+    if options.optimize {
+        optimizer::optimize(&mut program);
+    }
+    if cfg!(debug_assertions) {
+        if let Err(err) = verifier::verify(&program) {
+            panic!("compiled an invalid program: {}\n{}", err, program);
+        }
+    }
     Ok(program)
 }
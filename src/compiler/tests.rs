@@ -0,0 +1,73 @@
+use std::path::Path;
+
+use crate::{
+    compiler::{compile, compile_with, CompileOptions},
+    matcher::path_matches,
+    parser::parse,
+};
+
+#[test]
+fn case_insensitive_matches_any_case() {
+    let options = CompileOptions {
+        case_insensitive: true,
+        ..CompileOptions::default()
+    };
+    // Letters become classes of both cases, and anything else stays literal
+    let instructions = |pattern: &str, options: &CompileOptions| {
+        compile_with(&parse(pattern), options).unwrap().instructions
+    };
+    assert_eq!(
+        instructions("SRC/[l-n]AIN.rs", &options),
+        instructions(
+            "[Ss][Rr][Cc]/[l-nL-N][Aa][Ii][Nn].[rR][sS]",
+            &Default::default()
+        )
+    );
+    assert_eq!(
+        instructions("<_1:2>", &options),
+        instructions("<_1:2>", &Default::default())
+    );
+}
+
+#[test]
+fn case_insensitive_ranges_fold_every_simple_mapping() {
+    let options = CompileOptions {
+        case_insensitive: true,
+        ..CompileOptions::default()
+    };
+    for (pattern, path, matches) in [
+        ("[а-я]", "Я", true),
+        ("[а-я]", "я", true),
+        ("[А-Я]", "ж", true),
+        ("[é-ê]", "É", true),
+        ("[é-ê]", "Ê", true),
+        ("[é-ê]", "Ë", false),
+        ("[X-c]", "x", true),
+        ("[X-c]", "C", true),
+        ("[X-c]", "_", true),
+        ("[X-c]", "d", false),
+        ("[X-c]", "D", false),
+        ("[a-c]", "B", true),
+        ("[a-c]", "d", false),
+    ] {
+        let program = compile_with(&parse(pattern), &options).unwrap();
+        assert_eq!(
+            path_matches(Path::new(path), &program).valid_as_complete_match,
+            matches,
+            "{} {}",
+            pattern,
+            path
+        );
+    }
+}
+
+#[test]
+fn case_insensitive_range_already_containing_its_cases() {
+    let options = CompileOptions {
+        case_insensitive: true,
+        ..CompileOptions::default()
+    };
+    let pattern = parse("[\u{0}-\u{10FFFF}]");
+    let program = compile_with(&pattern, &options).unwrap();
+    assert_eq!(program, compile(&pattern).unwrap());
+}
@@ -0,0 +1,166 @@
+//! Renders programs as Graphviz control-flow graphs
+//!
+//! Each instruction that does something is a node, and the jumps, alternatives and branches in
+//! between are folded into labelled edges. Nodes and edges have the part of the pattern they came
+//! from as their tooltip.
+
+use std::{ffi::OsStr, fmt::Write};
+
+use crate::compiler::{Instruction, Program, ProgramOffset};
+
+#[cfg(test)]
+mod tests;
+
+/// A way to get from one node to another, through any number of control instructions
+struct Edge {
+    to: usize,
+    labels: Vec<String>,
+    /// The control instructions on the way
+    via: Vec<usize>,
+}
+
+/// Render the program as a `digraph`, using `pattern` for the tooltips
+pub fn render(program: &Program, pattern: impl AsRef<OsStr>) -> String {
+    let pattern = pattern.as_ref().as_encoded_bytes();
+    let fragments = |pcs: &[usize]| {
+        let mut fragments: Vec<String> = vec![];
+        for pc in pcs {
+            if let Some(span) = program.span(ProgramOffset(*pc)) {
+                let fragment = String::from_utf8_lossy(&pattern[span]).into_owned();
+                if !fragments.contains(&fragment) {
+                    fragments.push(fragment);
+                }
+            }
+        }
+        fragments.join(" ")
+    };
+
+    let mut out = String::new();
+    out.push_str("digraph program {\n");
+    out.push_str("    node [shape=box, fontname=monospace];\n");
+    if let Some(prefix) = &program.absolute_prefix {
+        let label = format!("absolute prefix {:?}", prefix);
+        writeln!(out, "    label=\"{}\";", escape(&label)).unwrap();
+    }
+    out.push_str("    start [shape=point];\n");
+
+    let start = edges(program, None);
+    for edge in &start {
+        write_edge(&mut out, "start", edge, fragments);
+    }
+    // Find every node first, so they can be written in program order
+    let mut nodes: Vec<Option<Vec<Edge>>> = program.instructions.iter().map(|_| None).collect();
+    let mut stack: Vec<usize> = start.iter().map(|edge| edge.to).collect();
+    while let Some(pc) = stack.pop() {
+        if nodes[pc].is_none() {
+            let node_edges = edges(program, Some(pc));
+            stack.extend(node_edges.iter().map(|edge| edge.to));
+            nodes[pc] = Some(node_edges);
+        }
+    }
+    for (pc, node_edges) in nodes.iter().enumerate() {
+        let Some(node_edges) = node_edges else {
+            continue;
+        };
+        let instruction = &program.instructions[pc];
+        let mut attributes = format!("label=\"{}\"", escape(&label(pc, instruction)));
+        let tooltip = fragments(&[pc]);
+        if !tooltip.is_empty() {
+            write!(attributes, ", tooltip=\"{}\"", escape(&tooltip)).unwrap();
+        }
+        if *instruction == Instruction::Complete {
+            attributes.push_str(", peripheries=2");
+        }
+        writeln!(out, "    n{} [{}];", pc, attributes).unwrap();
+        for edge in node_edges {
+            write_edge(&mut out, &format!("n{}", pc), edge, fragments);
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn write_edge(out: &mut String, from: &str, edge: &Edge, fragments: impl Fn(&[usize]) -> String) {
+    let mut attributes = vec![];
+    if !edge.labels.is_empty() {
+        attributes.push(format!("label=\"{}\"", escape(&edge.labels.join(", "))));
+    }
+    let tooltip = fragments(&edge.via);
+    if !tooltip.is_empty() {
+        attributes.push(format!("tooltip=\"{}\"", escape(&tooltip)));
+    }
+    write!(out, "    {} -> n{}", from, edge.to).unwrap();
+    if !attributes.is_empty() {
+        write!(out, " [{}]", attributes.join(", ")).unwrap();
+    }
+    out.push_str(";\n");
+}
+
+/// The nodes that can run straight after the one at `from`, or the first ones if it's `None`
+fn edges(program: &Program, from: Option<usize>) -> Vec<Edge> {
+    let start = match from {
+        None => 0,
+        Some(pc) if program.instructions[pc] == Instruction::Complete => return vec![],
+        Some(pc) => pc + 1,
+    };
+    let mut edges = vec![];
+    // Followed in reverse, so the edges come out in the order the matcher tries them
+    let mut pending = vec![Edge {
+        to: start,
+        labels: vec![],
+        via: vec![],
+    }];
+    while let Some(mut edge) = pending.pop() {
+        let pc = edge.to;
+        // A loop made only of control instructions has to stop somewhere, so it stops at a node
+        if edge.via.contains(&pc) {
+            edges.push(edge);
+            continue;
+        }
+        let branches = match &program.instructions[pc] {
+            Instruction::Jump(target) => vec![(target.0, None)],
+            Instruction::Alternative(target) => {
+                vec![
+                    (target.0, Some("else".into())),
+                    (pc + 1, Some("try".into())),
+                ]
+            }
+            Instruction::BranchIfLessThan(target, counter_id, value) => vec![
+                (pc + 1, Some(format!("{} >= {}", counter_id, value))),
+                (target.0, Some(format!("{} < {}", counter_id, value))),
+            ],
+            _ => {
+                edges.push(edge);
+                continue;
+            }
+        };
+        edge.via.push(pc);
+        for (to, label) in branches {
+            let mut labels = edge.labels.clone();
+            labels.extend(label);
+            pending.push(Edge {
+                to,
+                labels,
+                via: edge.via.clone(),
+            });
+        }
+    }
+    edges
+}
+
+/// The instruction as it is displayed, without the padding
+fn label(pc: usize, instruction: &Instruction) -> String {
+    let text = instruction.to_string();
+    match text.split_once(' ') {
+        Some((name, operands)) => format!("{}: {} {}", pc, name, operands.trim_start()),
+        None => format!("{}: {}", pc, text),
+    }
+}
+
+/// Quote a string for use in a DOT attribute
+fn escape(string: &str) -> String {
+    string
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
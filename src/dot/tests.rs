@@ -0,0 +1,68 @@
+use crate::{
+    compiler::{compile, Instruction},
+    dot::render,
+    parser::parse,
+};
+
+#[test]
+fn control_instructions_become_edges() {
+    let pattern = "{a,b}/c";
+    let program = compile(&parse(pattern)).unwrap();
+    let dot = render(&program, pattern);
+    assert!(dot.starts_with("digraph program {\n"), "{}", dot);
+    assert!(dot.ends_with("}\n"), "{}", dot);
+    for (pc, instruction) in program.instructions.iter().enumerate() {
+        let is_control = matches!(
+            instruction,
+            Instruction::Jump(_) | Instruction::Alternative(_) | Instruction::BranchIfLessThan(..)
+        );
+        assert_eq!(
+            dot.contains(&format!("    n{} [", pc)),
+            !is_control,
+            "{}\n{}",
+            instruction,
+            dot
+        );
+    }
+    assert!(
+        dot.contains("    start -> n1 [label=\"try\", tooltip=\"{a,b}\"];\n"),
+        "{}",
+        dot
+    );
+    assert!(
+        dot.contains("    start -> n3 [label=\"else\", tooltip=\"{a,b}\"];\n"),
+        "{}",
+        dot
+    );
+    assert!(
+        dot.contains("    n1 [label=\"1: literal-string \\\"a\\\"\", tooltip=\"a\"];\n"),
+        "{}",
+        dot
+    );
+}
+
+#[test]
+fn repeats_are_labelled_with_their_counters() {
+    let pattern = "<a:2,3>";
+    let dot = render(&compile(&parse(pattern)).unwrap(), pattern);
+    assert!(dot.contains("label=\"#0 < 2\""), "{}", dot);
+    assert!(dot.contains("label=\"#0 >= 2, #0 < 3, try\""), "{}", dot);
+    assert!(dot.contains("peripheries=2"), "{}", dot);
+}
+
+#[test]
+fn tooltips_and_labels_are_escaped() {
+    let pattern = "/\"x\\\\";
+    let dot = render(&compile(&parse(pattern)).unwrap(), pattern);
+    assert!(
+        dot.contains(r#"    label="absolute prefix \"/\"";"#),
+        "{}",
+        dot
+    );
+    for line in [
+        r#"    n1 [label="1: literal-string \"\\\"x\"", tooltip="\"x"];"#,
+        r#"    n2 [label="2: literal-string \"\\\\\"", tooltip="\\"];"#,
+    ] {
+        assert!(dot.contains(line), "{}\n{}", line, dot);
+    }
+}
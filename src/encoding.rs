@@ -0,0 +1,260 @@
+//! A compact binary encoding of compiled programs, so they can be cached instead of compiled
+//! every time
+//!
+//! The encoding starts with a magic string and a version byte, followed by the number of
+//! counters, the absolute prefix and the instructions. Numbers are LEB128 varints, and strings
+//! are a length followed by their bytes. Spans aren't included, since they only make sense next
+//! to the pattern string.
+
+use anyhow::{anyhow, bail};
+
+use crate::{
+    compiler::{CounterId, Instruction, Program, ProgramOffset},
+    filesystem::path_from_bytes,
+    parser::CharacterClass,
+    verifier,
+};
+
+#[cfg(test)]
+mod tests;
+
+const MAGIC: &[u8] = b"GLOBVM";
+/// Bumped whenever the encoding changes, so old caches are rejected rather than misread
+const VERSION: u8 = 1;
+
+mod opcode {
+    pub const SEPARATOR: u8 = 0;
+    pub const PREFIX: u8 = 1;
+    pub const ROOT_DIR: u8 = 2;
+    pub const CUR_DIR: u8 = 3;
+    pub const PARENT_DIR: u8 = 4;
+    pub const LITERAL_STRING: u8 = 5;
+    pub const ANY_CHARACTER: u8 = 6;
+    pub const ANY_STRING: u8 = 7;
+    pub const CHARACTERS: u8 = 8;
+    pub const SKIP_TO_LITERAL: u8 = 9;
+    pub const JUMP: u8 = 10;
+    pub const ALTERNATIVE: u8 = 11;
+    pub const INCREMENT: u8 = 12;
+    pub const BRANCH_IF_LESS_THAN: u8 = 13;
+    pub const COMPLETE: u8 = 14;
+}
+
+impl Program {
+    /// Encode the program, apart from its spans
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.push(VERSION);
+        write_number(&mut out, self.counters.into());
+        match &self.absolute_prefix {
+            Some(prefix) => {
+                out.push(1);
+                write_bytes(&mut out, prefix.as_os_str().as_encoded_bytes());
+            }
+            None => out.push(0),
+        }
+        write_number(&mut out, self.instructions.len() as u64);
+        for instruction in &self.instructions {
+            write_instruction(&mut out, instruction);
+        }
+        out
+    }
+
+    /// Decode a program written by [`Program::to_bytes`], and check that it is valid
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Program> {
+        let mut reader = Reader(bytes);
+        if reader.take(MAGIC.len()).ok() != Some(MAGIC) {
+            bail!("not an encoded program");
+        }
+        let version = reader.byte()?;
+        if version != VERSION {
+            bail!(
+                "encoded with version {}, but only version {} is supported",
+                version,
+                VERSION
+            );
+        }
+        let counters = reader.number()?;
+        let absolute_prefix = match reader.byte()? {
+            0 => None,
+            1 => Some(path_from_bytes(reader.bytes()?.to_vec())),
+            other => bail!("invalid absolute prefix tag {}", other),
+        };
+        let len: usize = reader.number()?;
+        // Every instruction takes at least a byte, so don't trust a longer length
+        let mut instructions = Vec::with_capacity(len.min(reader.0.len()));
+        for _ in 0..len {
+            instructions.push(reader.instruction()?);
+        }
+        if !reader.0.is_empty() {
+            bail!("{} unexpected bytes after the program", reader.0.len());
+        }
+        let program = Program {
+            instructions,
+            counters,
+            absolute_prefix,
+            spans: vec![],
+        };
+        verifier::verify(&program)?;
+        Ok(program)
+    }
+}
+
+fn write_number(out: &mut Vec<u8>, mut number: u64) {
+    loop {
+        let byte = (number & 0x7f) as u8;
+        number >>= 7;
+        if number == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_number(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn write_char(out: &mut Vec<u8>, ch: char) {
+    write_number(out, ch.into());
+}
+
+fn write_instruction(out: &mut Vec<u8>, instruction: &Instruction) {
+    match instruction {
+        Instruction::Separator => out.push(opcode::SEPARATOR),
+        Instruction::Prefix(prefix) => {
+            out.push(opcode::PREFIX);
+            write_bytes(out, prefix.as_bytes());
+        }
+        Instruction::RootDir => out.push(opcode::ROOT_DIR),
+        Instruction::CurDir => out.push(opcode::CUR_DIR),
+        Instruction::ParentDir => out.push(opcode::PARENT_DIR),
+        Instruction::LiteralString(bytes) => {
+            out.push(opcode::LITERAL_STRING);
+            write_bytes(out, bytes);
+        }
+        Instruction::AnyCharacter => out.push(opcode::ANY_CHARACTER),
+        Instruction::AnyString => out.push(opcode::ANY_STRING),
+        Instruction::Characters(classes) => {
+            out.push(opcode::CHARACTERS);
+            write_number(out, classes.len() as u64);
+            for class in classes.iter() {
+                match *class {
+                    CharacterClass::Single(ch) => {
+                        out.push(0);
+                        write_char(out, ch);
+                    }
+                    CharacterClass::Range(start, end) => {
+                        out.push(1);
+                        write_char(out, start);
+                        write_char(out, end);
+                    }
+                }
+            }
+        }
+        Instruction::SkipToLiteral(bytes) => {
+            out.push(opcode::SKIP_TO_LITERAL);
+            write_bytes(out, bytes);
+        }
+        Instruction::Jump(target) => {
+            out.push(opcode::JUMP);
+            write_number(out, target.0 as u64);
+        }
+        Instruction::Alternative(target) => {
+            out.push(opcode::ALTERNATIVE);
+            write_number(out, target.0 as u64);
+        }
+        Instruction::Increment(counter_id) => {
+            out.push(opcode::INCREMENT);
+            write_number(out, counter_id.0.into());
+        }
+        Instruction::BranchIfLessThan(target, counter_id, value) => {
+            out.push(opcode::BRANCH_IF_LESS_THAN);
+            write_number(out, target.0 as u64);
+            write_number(out, counter_id.0.into());
+            write_number(out, (*value).into());
+        }
+        Instruction::Complete => out.push(opcode::COMPLETE),
+    }
+}
+
+/// The bytes that haven't been decoded yet
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        if self.0.len() < len {
+            bail!("unexpected end of the encoded program");
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> anyhow::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn number<T: TryFrom<u64>>(&mut self) -> anyhow::Result<T> {
+        let mut number: u64 = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            number |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return T::try_from(number).map_err(|_| anyhow!("number {} is too large", number));
+            }
+        }
+        bail!("number is too long")
+    }
+
+    fn bytes(&mut self) -> anyhow::Result<&'a [u8]> {
+        let len = self.number()?;
+        self.take(len)
+    }
+
+    fn char(&mut self) -> anyhow::Result<char> {
+        let number = self.number()?;
+        char::from_u32(number).ok_or_else(|| anyhow!("invalid character {:#x}", number))
+    }
+
+    fn instruction(&mut self) -> anyhow::Result<Instruction> {
+        Ok(match self.byte()? {
+            opcode::SEPARATOR => Instruction::Separator,
+            opcode::PREFIX => {
+                let prefix = std::str::from_utf8(self.bytes()?)?;
+                Instruction::Prefix(prefix.into())
+            }
+            opcode::ROOT_DIR => Instruction::RootDir,
+            opcode::CUR_DIR => Instruction::CurDir,
+            opcode::PARENT_DIR => Instruction::ParentDir,
+            opcode::LITERAL_STRING => Instruction::LiteralString(self.bytes()?.into()),
+            opcode::ANY_CHARACTER => Instruction::AnyCharacter,
+            opcode::ANY_STRING => Instruction::AnyString,
+            opcode::CHARACTERS => {
+                let len: usize = self.number()?;
+                let mut classes = Vec::with_capacity(len.min(self.0.len()));
+                for _ in 0..len {
+                    classes.push(match self.byte()? {
+                        0 => CharacterClass::Single(self.char()?),
+                        1 => CharacterClass::Range(self.char()?, self.char()?),
+                        other => bail!("invalid character class tag {}", other),
+                    });
+                }
+                Instruction::Characters(classes.into())
+            }
+            opcode::SKIP_TO_LITERAL => Instruction::SkipToLiteral(self.bytes()?.into()),
+            opcode::JUMP => Instruction::Jump(ProgramOffset(self.number()?)),
+            opcode::ALTERNATIVE => Instruction::Alternative(ProgramOffset(self.number()?)),
+            opcode::INCREMENT => Instruction::Increment(CounterId(self.number()?)),
+            opcode::BRANCH_IF_LESS_THAN => Instruction::BranchIfLessThan(
+                ProgramOffset(self.number()?),
+                CounterId(self.number()?),
+                self.number()?,
+            ),
+            opcode::COMPLETE => Instruction::Complete,
+            other => bail!("unknown opcode {}", other),
+        })
+    }
+}
@@ -0,0 +1,114 @@
+use crate::{
+    compiler::{compile, compile_with, CompileOptions, Instruction, Program, ProgramOffset},
+    parser::parse,
+};
+
+/// Check that a program decodes the same as it was encoded, apart from its spans
+fn round_trip(program: &Program) {
+    let decoded = Program::from_bytes(&program.to_bytes())
+        .unwrap_or_else(|err| panic!("{}\n{}", err, program));
+    assert_eq!(decoded.instructions, program.instructions, "{}", program);
+    assert_eq!(decoded.counters, program.counters, "{}", program);
+    assert_eq!(decoded.absolute_prefix, program.absolute_prefix);
+    assert!(decoded.spans.is_empty());
+}
+
+fn error(bytes: &[u8]) -> String {
+    Program::from_bytes(bytes).unwrap_err().to_string()
+}
+
+#[test]
+fn compiled_programs_round_trip() {
+    for pattern in [
+        "src/**/*.rs",
+        "/usr/lib/{a,b}/../x",
+        "./<[0-9a-f]:2,4>.txt",
+        "tab\there/[\u{301}-\u{10ffff}'\\\\]",
+        "<<a:2>b:1,3>",
+        "<*:200,300>",
+        "C:/<[0-9]:0>",
+    ] {
+        for (case_insensitive, optimize) in [(false, false), (true, false), (false, true)] {
+            let options = CompileOptions {
+                case_insensitive,
+                optimize,
+            };
+            round_trip(&compile_with(&parse(pattern), &options).unwrap());
+        }
+    }
+}
+
+#[test]
+fn literals_and_prefixes_do_not_have_to_be_utf8() {
+    let program = Program {
+        instructions: vec![
+            Instruction::LiteralString(b"a\xff"[..].into()),
+            Instruction::SkipToLiteral(b"\xc0"[..].into()),
+            Instruction::Complete,
+        ],
+        counters: 0,
+        absolute_prefix: Some(crate::filesystem::path_from_bytes(b"/x\xfe".to_vec())),
+        spans: vec![],
+    };
+    round_trip(&program);
+}
+
+#[test]
+fn bad_encodings_are_rejected() {
+    let bytes = compile(&parse("a/<b:2>")).unwrap().to_bytes();
+
+    assert_eq!(error(b"GLOB"), "not an encoded program");
+    let mut other_version = bytes.clone();
+    other_version[6] = 9;
+    assert_eq!(
+        error(&other_version),
+        "encoded with version 9, but only version 1 is supported"
+    );
+    assert_eq!(
+        error(&bytes[..bytes.len() - 1]),
+        "unexpected end of the encoded program"
+    );
+    let mut trailing = bytes.clone();
+    trailing.extend([0, 0]);
+    assert_eq!(error(&trailing), "2 unexpected bytes after the program");
+    let mut unknown = bytes.clone();
+    *unknown.last_mut().unwrap() = 99;
+    assert_eq!(error(&unknown), "unknown opcode 99");
+}
+
+#[test]
+fn decoded_programs_are_verified() {
+    let program = Program {
+        instructions: vec![Instruction::Jump(ProgramOffset(5)), Instruction::Complete],
+        ..Program::default()
+    };
+    assert_eq!(
+        error(&program.to_bytes()),
+        "[  0]: target [  5] is past the end of the program"
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_round_trips_and_verifies() {
+    let mut program = compile(&parse("/usr/**/<[a-z]:1,3>.rs")).unwrap();
+    program.instructions[0] = Instruction::LiteralString(b"\xff"[..].into());
+    let json = serde_json::to_string(&program).unwrap();
+    let decoded: Program = serde_json::from_str(&json).unwrap();
+    assert_eq!(decoded, program);
+
+    let invalid =
+        r#"{"instructions": [{"jump": 3}, "complete"], "counters": 0, "absolute_prefix": null}"#;
+    let err = serde_json::from_str::<Program>(invalid).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "[  0]: target [  3] is past the end of the program"
+    );
+    let without_spans = r#"{"instructions": [{"literal-string": {"bytes": [97]}}, "complete"], "counters": 0, "absolute_prefix": "/x"}"#;
+    let decoded: Program = serde_json::from_str(without_spans).unwrap();
+    assert_eq!(
+        decoded.instructions[0],
+        Instruction::LiteralString(b"a"[..].into())
+    );
+    assert_eq!(decoded.absolute_prefix, Some("/x".into()));
+}
@@ -0,0 +1,301 @@
+//! Lists every path matched by a pattern that only matches finitely many, like brace expansion in
+//! a shell
+
+use std::path::{is_separator, PathBuf, MAIN_SEPARATOR_STR};
+
+use anyhow::bail;
+
+use crate::{
+    filesystem::path_from_bytes,
+    parser::{AstNode, CharacterClass, Pattern, Span},
+};
+
+#[cfg(test)]
+mod tests;
+
+/// No path can be longer than this, so longer expansions aren't worth building
+const MAX_LENGTH: usize = 4096;
+
+/// Where an expansion has got to in the path, which decides what the next node adds to it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Position {
+    /// At the start of the path, or just after its root, where a separator adds nothing
+    Start,
+    /// Part way through a component
+    Inside,
+    /// Just after a `.` or `..` component, which needs a separator before anything else
+    Special,
+    /// Just after a separator, where another component has to follow
+    Separated,
+}
+
+/// A path being built up, and where it has got to
+#[derive(Debug, Clone)]
+struct Expansion {
+    bytes: Vec<u8>,
+    position: Position,
+}
+
+impl Expansion {
+    /// Add `bytes`, failing if that makes the path too long
+    fn push(&self, bytes: &[u8], position: Position) -> anyhow::Result<Expansion> {
+        if self.bytes.len() + bytes.len() > MAX_LENGTH {
+            bail!(
+                "The pattern expands to paths longer than {} bytes",
+                MAX_LENGTH
+            );
+        }
+        Ok(Expansion {
+            bytes: [&self.bytes[..], bytes].concat(),
+            position,
+        })
+    }
+
+    /// Whether the component being built is `.` or `..`, which the path would read as a current or
+    /// parent directory rather than the characters the pattern matched
+    fn in_dot_component(&self) -> bool {
+        self.position == Position::Inside
+            && matches!(
+                self.bytes.rsplit(|&byte| is_separator(byte.into())).next(),
+                Some(b"." | b"..")
+            )
+    }
+
+    /// Whether the path can end here
+    fn is_complete(&self) -> bool {
+        self.position != Position::Separated && !self.in_dot_component()
+    }
+}
+
+/// Whether the pattern matches a finite number of paths
+pub fn is_finite(pattern: &Pattern) -> bool {
+    first_infinite(pattern).is_none()
+}
+
+/// The span of the first node that can match infinitely many strings
+fn first_infinite(pattern: &Pattern) -> Option<Span> {
+    for (index, node) in pattern.nodes.iter().enumerate() {
+        let span = || pattern.spans.get(index).cloned().unwrap_or_default();
+        match node {
+            AstNode::AnyCharacter | AstNode::Wildcard | AstNode::Recurse => return Some(span()),
+            AstNode::Alternatives { choices } => {
+                if let Some(span) = choices.iter().find_map(first_infinite) {
+                    return Some(span);
+                }
+            }
+            AstNode::Repeat { pattern, .. } => {
+                if let Some(span) = first_infinite(pattern) {
+                    return Some(span);
+                }
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+/// The span of the first repeat inside a repeat that can run more than once
+///
+/// The inner repeat's counter carries on from one pass of the outer repeat to the next, so the
+/// passes don't each match the same strings.
+fn first_nested_repeat(pattern: &Pattern, repeated: bool) -> Option<Span> {
+    for (index, node) in pattern.nodes.iter().enumerate() {
+        match node {
+            AstNode::Alternatives { choices } => {
+                if let Some(span) = choices
+                    .iter()
+                    .find_map(|choice| first_nested_repeat(choice, repeated))
+                {
+                    return Some(span);
+                }
+            }
+            AstNode::Repeat { .. } if repeated => {
+                return Some(pattern.spans.get(index).cloned().unwrap_or_default());
+            }
+            AstNode::Repeat { min, max, pattern } => {
+                let least = (*min).max(1);
+                if let Some(span) = first_nested_repeat(pattern, least.max(*max) > 1) {
+                    return Some(span);
+                }
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+/// List the paths that a finite pattern matches, failing if there are more than `limit`
+///
+/// The paths are in the order the pattern spells them out, with the leftmost choice varying
+/// slowest, and are not deduplicated, so `{a,b}{1,2}` gives `a1 a2 b1 b2`.
+pub fn expand(pattern: &Pattern, limit: usize) -> anyhow::Result<Vec<PathBuf>> {
+    if let Some(span) = first_infinite(pattern) {
+        bail!(
+            "The pattern matches infinitely many paths, because of bytes {}..{}",
+            span.start,
+            span.end
+        );
+    }
+    if let Some(span) = first_nested_repeat(pattern, false) {
+        bail!(
+            "Repeats inside repeats keep counting from one pass of the outer repeat to the next, \
+             which can't be expanded, at bytes {}..{}",
+            span.start,
+            span.end
+        );
+    }
+    let start = Expansion {
+        bytes: vec![],
+        position: Position::Start,
+    };
+    let expansions = expand_nodes(&pattern.nodes, vec![start], limit)?;
+    Ok(expansions
+        .into_iter()
+        .filter(Expansion::is_complete)
+        .map(|expansion| path_from_bytes(expansion.bytes))
+        .collect())
+}
+
+/// Every expansion from `before` followed by every way `nodes` can continue it
+fn expand_nodes(
+    nodes: &[AstNode],
+    before: Vec<Expansion>,
+    limit: usize,
+) -> anyhow::Result<Vec<Expansion>> {
+    let mut current = before;
+    for node in nodes {
+        let mut next = vec![];
+        for expansion in &current {
+            expand_node(node, expansion, limit, &mut next)?;
+        }
+        // Nothing matches if any node matches nothing, however many the others match
+        if next.is_empty() {
+            return Ok(next);
+        }
+        current = next;
+    }
+    Ok(current)
+}
+
+/// Add every way `node` can continue `from` to `out`
+///
+/// This follows the matcher: a separator consumes nothing at the start of the path or after
+/// another separator, and `.` and `..` only match whole components where paths keep them.
+fn expand_node(
+    node: &AstNode,
+    from: &Expansion,
+    limit: usize,
+    out: &mut Vec<Expansion>,
+) -> anyhow::Result<()> {
+    let separator = MAIN_SEPARATOR_STR.as_bytes();
+    match (node, from.position) {
+        // A component can't end as `.` or `..` made of literal characters
+        (AstNode::Separator | AstNode::ParentDir, Position::Inside) if from.in_dot_component() => {}
+        (AstNode::Separator, Position::Start | Position::Separated) => {
+            add(out, limit, from.push(b"", Position::Separated)?)?
+        }
+        (AstNode::Separator, Position::Inside | Position::Special) => {
+            add(out, limit, from.push(separator, Position::Separated)?)?
+        }
+        (AstNode::Prefix(prefix), Position::Start) if from.bytes.is_empty() => {
+            add(out, limit, from.push(prefix.as_bytes(), Position::Start)?)?
+        }
+        // There's only one root, but it can follow a prefix
+        (AstNode::RootDir, Position::Start) if !from.bytes.ends_with(separator) => {
+            add(out, limit, from.push(separator, Position::Start)?)?
+        }
+        (AstNode::CurDir, Position::Start) if from.bytes.is_empty() => {
+            add(out, limit, from.push(b".", Position::Special)?)?
+        }
+        (AstNode::ParentDir, Position::Start | Position::Separated) => {
+            add(out, limit, from.push(b"..", Position::Special)?)?
+        }
+        // Once the component is used up, the next one can be a parent directory
+        (AstNode::ParentDir, Position::Inside | Position::Special) => add(
+            out,
+            limit,
+            from.push(&[separator, b".."].concat(), Position::Special)?,
+        )?,
+        (AstNode::Prefix(_) | AstNode::RootDir | AstNode::CurDir, _) => (),
+        (AstNode::LiteralString(string), _) if string.is_empty() => add(out, limit, from.clone())?,
+        (AstNode::LiteralString(string), _) => add(out, limit, start_component(from, string)?)?,
+        (AstNode::Characters(classes), _) => {
+            let mut chars = vec![];
+            for class in classes {
+                let (start, end) = match *class {
+                    CharacterClass::Single(ch) => (ch, ch),
+                    CharacterClass::Range(start, end) => (start, end),
+                };
+                for ch in start..=end {
+                    // Components never contain a separator, so it can't match
+                    if !is_separator(ch) && !chars.contains(&ch) {
+                        check_limit(out.len() + chars.len() + 1, limit)?;
+                        chars.push(ch);
+                    }
+                }
+            }
+            for ch in chars {
+                add(
+                    out,
+                    limit,
+                    start_component(from, ch.to_string().as_bytes())?,
+                )?;
+            }
+        }
+        (AstNode::Alternatives { choices }, _) => {
+            for choice in choices {
+                for expansion in expand_nodes(&choice.nodes, vec![from.clone()], limit)? {
+                    add(out, limit, expansion)?;
+                }
+            }
+        }
+        (AstNode::Repeat { min, max, pattern }, _) => {
+            // Like the compiled loop, the body always runs at least once and never stops before
+            // `min`, even if that's more than `max`
+            let least = (*min).max(1);
+            let most = least.max(*max);
+            let mut current = vec![from.clone()];
+            for count in 1..=most {
+                current = expand_nodes(&pattern.nodes, current, limit)?;
+                if current.is_empty() {
+                    break;
+                }
+                if count >= least {
+                    for expansion in &current {
+                        add(out, limit, expansion.clone())?;
+                    }
+                }
+            }
+        }
+        (AstNode::AnyCharacter | AstNode::Wildcard | AstNode::Recurse, _) => {
+            unreachable!("infinite patterns are rejected before expanding")
+        }
+    }
+    Ok(())
+}
+
+/// Add an expansion to `out`, failing if that makes more than `limit`
+fn add(out: &mut Vec<Expansion>, limit: usize, expansion: Expansion) -> anyhow::Result<()> {
+    check_limit(out.len() + 1, limit)?;
+    out.push(expansion);
+    Ok(())
+}
+
+/// Add `bytes` to the component being built, starting a new one after a `.` or `..`
+fn start_component(from: &Expansion, bytes: &[u8]) -> anyhow::Result<Expansion> {
+    if from.position == Position::Special {
+        from.push(
+            &[MAIN_SEPARATOR_STR.as_bytes(), bytes].concat(),
+            Position::Inside,
+        )
+    } else {
+        from.push(bytes, Position::Inside)
+    }
+}
+
+fn check_limit(count: usize, limit: usize) -> anyhow::Result<()> {
+    if count > limit {
+        bail!("The pattern expands to more than {} paths", limit);
+    }
+    Ok(())
+}
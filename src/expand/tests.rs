@@ -0,0 +1,120 @@
+use std::path::PathBuf;
+
+use crate::{
+    compiler::compile,
+    expand::{expand, is_finite},
+    matcher::path_matches,
+    parser::parse,
+};
+
+fn expand_strings(pattern: &str, limit: usize) -> anyhow::Result<Vec<String>> {
+    let paths = expand(&parse(pattern), limit)?;
+    Ok(paths
+        .into_iter()
+        .map(|path: PathBuf| path.to_string_lossy().into_owned())
+        .collect())
+}
+
+#[test]
+fn braces_expand_in_order() {
+    assert_eq!(
+        expand_strings("{src,tests}/{main,lib}.rs", 10).unwrap(),
+        ["src/main.rs", "src/lib.rs", "tests/main.rs", "tests/lib.rs"]
+    );
+}
+
+#[test]
+fn classes_and_repeats() {
+    let paths = expand_strings("img<[0-9]:2>.png", 100).unwrap();
+    assert_eq!(paths.len(), 100);
+    assert_eq!(paths[0], "img00.png");
+    assert_eq!(paths[99], "img99.png");
+    // A repeat runs at least once, and at least `min` times
+    assert_eq!(expand_strings("a<b:0,2>", 10).unwrap(), ["ab", "abb"]);
+    assert_eq!(expand_strings("a<b:0>c", 10).unwrap(), ["abc"]);
+    assert_eq!(expand_strings("<x:3,1>", 10).unwrap(), ["xxx"]);
+    assert_eq!(
+        expand_strings("/x/{,../}y", 10).unwrap(),
+        ["/x/y", "/x/../y"]
+    );
+}
+
+#[test]
+fn infinite_patterns_are_rejected() {
+    for pattern in ["*.rs", "src/**/x", "a?", "{a,<*:1>}"] {
+        assert!(!is_finite(&parse(pattern)), "{}", pattern);
+        assert!(expand(&parse(pattern), 10).is_err());
+    }
+    assert!(is_finite(&parse("{a,b}/[xy]<c:1,3>")));
+}
+
+#[test]
+fn limit_is_enforced() {
+    assert!(expand_strings("<[0-9]:3>", 999).is_err());
+    assert_eq!(expand_strings("<[0-9]:3>", 1000).unwrap().len(), 1000);
+    // Nothing matches an empty class
+    assert!(expand_strings("{a,b}[z-a]", 2).unwrap().is_empty());
+}
+
+#[test]
+fn separators_follow_the_matcher() {
+    // A separator at the start of the path, or after an empty choice, consumes nothing
+    assert_eq!(expand_strings("{,x}/b", 10).unwrap(), ["b", "x/b"]);
+    assert_eq!(expand_strings("</ab:2,0>", 10).unwrap(), ["ab/ab"]);
+    // Another component has to follow a separator
+    assert_eq!(expand_strings("a{,/}", 10).unwrap(), ["a"]);
+}
+
+#[test]
+fn dots_and_separators_in_classes_are_skipped() {
+    for pattern in ["[.]", "[.][.]", "/[.]", "a/[.]/b", "[/]"] {
+        assert!(
+            expand_strings(pattern, 10).unwrap().is_empty(),
+            "{}",
+            pattern
+        );
+    }
+    assert_eq!(expand_strings("[.]x", 10).unwrap(), [".x"]);
+    assert_eq!(expand_strings("[.-0]", 10).unwrap(), ["0"]);
+}
+
+#[test]
+fn nested_repeats_are_rejected() {
+    assert!(expand_strings("<<<ab:2,1>:2,3>:1,2>", 1000).is_err());
+    assert!(expand_strings("<{a,<b:2>}:2>", 1000).is_err());
+    // Unless the outer repeat only runs once
+    assert_eq!(expand_strings("<<a:2>:1>", 10).unwrap(), ["aa"]);
+}
+
+#[test]
+fn expanded_paths_match() {
+    for pattern in [
+        "{src,tests}/{main,lib}.rs",
+        "img<[0-9]:1,2>.png",
+        "<x:0,2>y",
+        "a<b:0>c",
+        "<x:3,1>",
+        "<{a,b}/:0,2>c",
+        "/x/{,../}y",
+        "{,x}/b",
+        "</ab:2,0>",
+        "<{,a}/b:2>",
+        "<<ab:2,1>:1>",
+        "[.]x",
+        "[.-0]",
+        "{.,a}[.]",
+        "../[.]a",
+    ] {
+        let program = compile(&parse(pattern)).unwrap();
+        let paths = expand(&parse(pattern), 1000).unwrap();
+        assert!(!paths.is_empty(), "{}", pattern);
+        for path in paths {
+            assert!(
+                path_matches(&path, &program).valid_as_complete_match,
+                "{} {}",
+                pattern,
+                path.display()
+            );
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! Records what the matcher does while running a program, to explain why a path did or didn't
+//! match
+
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    fmt::Write,
+    path::{Path, MAIN_SEPARATOR},
+};
+
+use crate::{
+    compiler::{Instruction, Program, ProgramOffset},
+    matcher::{self, FailReason, MatchRecorder, MatchResult, TraceEvent, TraceEventKind},
+    parser::CharacterClass,
+};
+
+#[cfg(test)]
+mod tests;
+
+/// The result of running a program, and everything that happened on the way to it
+#[derive(Debug, Clone)]
+pub struct Trace {
+    pub result: MatchResult,
+    pub events: Vec<TraceEvent>,
+}
+
+#[derive(Debug)]
+struct TraceRecorder {
+    result: MatchResult,
+    events: Vec<TraceEvent>,
+}
+
+impl MatchRecorder for TraceRecorder {
+    fn prefix(&mut self, pc: ProgramOffset) {
+        self.result.prefix(pc);
+    }
+
+    fn complete(&mut self, pc: ProgramOffset) {
+        self.result.complete(pc);
+    }
+
+    fn is_done(&self) -> bool {
+        self.result.is_done()
+    }
+
+    fn trace(&mut self, event: impl FnOnce() -> TraceEvent) {
+        self.events.push(event());
+    }
+}
+
+/// Run `program` against `path` like [`matcher::path_matches`], recording everything it does
+pub fn trace(path: &Path, program: &Program) -> Trace {
+    let recorder = matcher::run(
+        path,
+        program,
+        TraceRecorder {
+            result: MatchResult::none(),
+            events: vec![],
+        },
+    );
+    Trace {
+        result: recorder.result,
+        events: recorder.events,
+    }
+}
+
+/// Explain why `path` does or doesn't match `program`, which was compiled from `pattern`
+pub fn explain(pattern: impl AsRef<OsStr>, path: &Path, program: &Program) -> String {
+    trace(path, program).render(pattern, path, program)
+}
+
+/// What happened on a thread while it was executing instructions from one part of the pattern
+#[derive(Default)]
+struct Step {
+    consumed: String,
+    forks: Vec<usize>,
+    outcome: Option<String>,
+}
+
+impl Trace {
+    /// Render a report of the trace, with each step tied back to the part of `pattern` that it
+    /// came from
+    pub fn render(&self, pattern: impl AsRef<OsStr>, path: &Path, program: &Program) -> String {
+        let pattern = pattern.as_ref().as_encoded_bytes();
+        let fragment = |pc: ProgramOffset| match program.span(pc) {
+            Some(span) => String::from_utf8_lossy(&pattern[span]).into_owned(),
+            None if program.instructions.get(pc.0) == Some(&Instruction::Complete) => {
+                "(end)".into()
+            }
+            None => format!("{}", pc),
+        };
+
+        // Group the events into steps, one for each run of events on the same part of the pattern
+        let mut steps: Vec<(usize, String, Step)> = vec![];
+        let mut forked_at = HashMap::new();
+        let mut executed = 0;
+        for event in &self.events {
+            if event.kind == TraceEventKind::Execute {
+                executed += 1;
+                continue;
+            }
+            let fragment = fragment(event.pc);
+            let step = match steps.last_mut() {
+                Some((thread, last_fragment, step))
+                    if *thread == event.thread
+                        && *last_fragment == fragment
+                        && step.outcome.is_none()
+                        && event.kind != TraceEventKind::Resume =>
+                {
+                    step
+                }
+                _ => {
+                    steps.push((event.thread, fragment.clone(), Step::default()));
+                    &mut steps.last_mut().unwrap().2
+                }
+            };
+            match &event.kind {
+                TraceEventKind::Execute | TraceEventKind::Resume => (),
+                TraceEventKind::Consume(text) => step.consumed.push_str(text),
+                TraceEventKind::NextComponent => step.consumed.push(MAIN_SEPARATOR),
+                TraceEventKind::Fork { thread, .. } => {
+                    step.forks.push(*thread);
+                    forked_at.insert(*thread, (event.thread, fragment));
+                }
+                TraceEventKind::Fail(FailReason::Mismatch { found }) => {
+                    let expected = describe_instruction(&program.instructions[event.pc.0]);
+                    step.outcome = Some(format!("failed: expected {}, found {}", expected, found));
+                }
+                TraceEventKind::Fail(FailReason::Incomplete { remaining }) => {
+                    step.outcome = Some(format!(
+                        "failed: the pattern ended with {:?} left in the path",
+                        remaining
+                    ));
+                }
+                TraceEventKind::Prefix => {
+                    step.outcome = Some("the path ended, but a longer path could match".into());
+                }
+                TraceEventKind::Complete => step.outcome = Some("matched the whole path".into()),
+            }
+        }
+
+        let mut out = String::new();
+        let result = match (
+            self.result.valid_as_complete_match,
+            self.result.valid_as_prefix,
+        ) {
+            (true, true) => "matches, and longer paths could match too",
+            (true, false) => "matches",
+            (false, true) => "no match, but longer paths could match",
+            (false, false) => "no match",
+        };
+        let _ = writeln!(out, "pattern: {}", String::from_utf8_lossy(pattern));
+        let _ = writeln!(out, "path:    {}", path.display());
+        let _ = writeln!(out, "result:  {}", result);
+
+        let width = steps
+            .iter()
+            .map(|(_, fragment, _)| fragment.chars().count())
+            .max()
+            .unwrap_or(0);
+        let mut current_thread = None;
+        for (thread, fragment, step) in &steps {
+            if current_thread != Some(*thread) {
+                current_thread = Some(*thread);
+                out.push('\n');
+                match forked_at.get(thread) {
+                    Some((parent, at)) => {
+                        let _ =
+                            writeln!(out, "thread {} (from thread {} at {})", thread, parent, at);
+                    }
+                    None => {
+                        let _ = writeln!(out, "thread {}", thread);
+                    }
+                }
+            }
+
+            let mut parts = vec![];
+            if !step.consumed.is_empty() {
+                parts.push(format!("matched {:?}", step.consumed));
+            }
+            if !step.forks.is_empty() {
+                let threads: Vec<_> = step.forks.iter().map(usize::to_string).collect();
+                let noun = if threads.len() == 1 {
+                    "thread"
+                } else {
+                    "threads"
+                };
+                parts.push(format!(
+                    "saved {} {} to try later",
+                    noun,
+                    threads.join(", ")
+                ));
+            }
+            parts.extend(step.outcome.clone());
+            if parts.is_empty() {
+                continue;
+            }
+            let _ = writeln!(out, "  {:<width$}  {}", fragment, parts.join(", "));
+        }
+
+        let threads = self
+            .events
+            .iter()
+            .map(|event| event.thread)
+            .max()
+            .map_or(0, |max| max + 1);
+        let _ = writeln!(
+            out,
+            "\n{} instructions executed on {} threads",
+            executed, threads
+        );
+        out
+    }
+}
+
+/// What an instruction that can fail was expecting to find
+fn describe_instruction(instruction: &Instruction) -> String {
+    match instruction {
+        Instruction::Separator => "the end of the component".into(),
+        Instruction::Prefix(prefix) => format!("prefix {:?}", prefix),
+        Instruction::RootDir => "the root directory".into(),
+        Instruction::CurDir => "\".\"".into(),
+        Instruction::ParentDir => "\"..\"".into(),
+        Instruction::LiteralString(bytes) => format!("{:?}", String::from_utf8_lossy(bytes)),
+        Instruction::AnyCharacter => "any character".into(),
+        Instruction::AnyString => "a component".into(),
+        Instruction::SkipToLiteral(bytes) => {
+            format!("{:?} somewhere", String::from_utf8_lossy(bytes))
+        }
+        Instruction::Characters(classes) => {
+            let mut out = String::from("one of [");
+            for class in classes.iter() {
+                match class {
+                    CharacterClass::Single(ch) => out.push(*ch),
+                    CharacterClass::Range(start, end) => {
+                        let _ = write!(out, "{}-{}", start, end);
+                    }
+                }
+            }
+            out.push(']');
+            out
+        }
+        other => other.to_string(),
+    }
+}
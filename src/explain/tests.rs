@@ -0,0 +1,75 @@
+use std::path::Path;
+
+use crate::{
+    compiler::{compile, compile_with, CompileOptions},
+    explain::{explain, trace},
+    matcher::{FailReason, TraceEventKind},
+    parser::parse,
+};
+
+#[test]
+fn trace_records_consumed_text() {
+    let program = compile(&parse("src/*.rs")).unwrap();
+    let trace = trace(Path::new("src/main.rs"), &program);
+    assert!(trace.result.valid_as_complete_match);
+    let complete = trace
+        .events
+        .iter()
+        .find(|event| event.kind == TraceEventKind::Complete)
+        .unwrap();
+    let consumed: String = trace
+        .events
+        .iter()
+        .filter(|event| event.thread == complete.thread)
+        .filter_map(|event| match &event.kind {
+            TraceEventKind::Consume(text) => Some(text.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(consumed, "n.rs");
+}
+
+#[test]
+fn trace_records_failures() {
+    let program = compile(&parse("src/**/mod.rs")).unwrap();
+    let trace = trace(Path::new("src/lib.rs"), &program);
+    assert!(!trace.result.valid_as_complete_match);
+    assert!(trace.events.iter().any(|event| event.kind
+        == TraceEventKind::Fail(FailReason::Mismatch {
+            found: "\"lib.rs\"".into()
+        })));
+}
+
+#[test]
+fn report_ties_steps_to_the_pattern() {
+    let program = compile(&parse("src/**/mod.rs")).unwrap();
+    let report = explain("src/**/mod.rs", Path::new("src/lib.rs"), &program);
+    assert!(report.contains("result:  no match"), "{}", report);
+    assert!(report.contains("  src     matched \"src\""), "{}", report);
+    assert!(
+        report.contains("  mod.rs  failed: expected \"mod.rs\", found \"lib.rs\""),
+        "{}",
+        report
+    );
+}
+
+#[test]
+fn report_covers_optimized_programs() {
+    let options = CompileOptions {
+        optimize: true,
+        ..CompileOptions::default()
+    };
+    let program = compile_with(&parse("*.tar.gz"), &options).unwrap();
+    let report = explain("*.tar.gz", Path::new("a.tar.gz.tar.gz.bak"), &program);
+    assert!(report.contains("result:  no match"), "{}", report);
+    assert!(
+        report.contains("  *.tar.gz  matched \"a.tar.gz\", saved thread 1 to try later"),
+        "{}",
+        report
+    );
+    assert!(
+        report.contains("failed: the pattern ended with \".bak\" left in the path"),
+        "{}",
+        report
+    );
+}
@@ -0,0 +1,264 @@
+//! Abstraction over the filesystem that the globber walks
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ffi::OsString,
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    File,
+    Dir,
+    Symlink,
+    Other,
+}
+
+impl From<fs::FileType> for FileType {
+    fn from(file_type: fs::FileType) -> Self {
+        if file_type.is_symlink() {
+            FileType::Symlink
+        } else if file_type.is_dir() {
+            FileType::Dir
+        } else if file_type.is_file() {
+            FileType::File
+        } else {
+            FileType::Other
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata {
+    pub file_type: FileType,
+    pub len: u64,
+}
+
+impl Metadata {
+    pub fn is_dir(&self) -> bool {
+        self.file_type == FileType::Dir
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.file_type == FileType::File
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.file_type == FileType::Symlink
+    }
+}
+
+impl From<fs::Metadata> for Metadata {
+    fn from(metadata: fs::Metadata) -> Self {
+        Metadata {
+            file_type: metadata.file_type().into(),
+            len: metadata.len(),
+        }
+    }
+}
+
+/// The paths of the entries in a directory, already joined onto the directory path
+pub type ReadDir<'a> = Box<dyn Iterator<Item = io::Result<PathBuf>> + Send + 'a>;
+
+/// The filesystem operations needed to walk a directory tree
+pub trait FileSystem: Send + Sync {
+    fn read_dir(&self, path: &Path) -> io::Result<ReadDir<'_>>;
+
+    /// Get the metadata of `path`, following symlinks
+    fn metadata(&self, path: &Path) -> io::Result<Metadata>;
+
+    /// Get the metadata of `path` without following a symlink at the end of it
+    fn symlink_metadata(&self, path: &Path) -> io::Result<Metadata>;
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf>;
+}
+
+/// The real filesystem, via [`std::fs`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StdFileSystem;
+
+impl FileSystem for StdFileSystem {
+    fn read_dir(&self, path: &Path) -> io::Result<ReadDir<'_>> {
+        let entries = fs::read_dir(path)?;
+        Ok(Box::new(
+            entries.map(|result| result.map(|dir_entry| dir_entry.path())),
+        ))
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        fs::metadata(path).map(Metadata::from)
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<Metadata> {
+        fs::symlink_metadata(path).map(Metadata::from)
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::read_link(path)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum MemoryNode {
+    File { len: u64 },
+    Dir { children: BTreeSet<OsString> },
+    Symlink { target: PathBuf },
+}
+
+/// A filesystem that only exists in memory, mostly useful for tests
+///
+/// Paths are normalized lexically, so `a/../b` is the same as `b`. Only a symlink at the end of a
+/// path is followed by [`FileSystem::metadata`].
+#[derive(Debug, Clone, Default)]
+pub struct MemoryFileSystem {
+    nodes: BTreeMap<PathBuf, MemoryNode>,
+}
+
+impl MemoryFileSystem {
+    pub fn new() -> MemoryFileSystem {
+        MemoryFileSystem::default()
+    }
+
+    /// Add a directory, and any parent directories that don't exist yet
+    pub fn add_dir(&mut self, path: impl AsRef<Path>) -> &mut Self {
+        let path = normalize(path.as_ref());
+        self.add_parents(&path);
+        self.nodes.entry(path).or_insert_with(|| MemoryNode::Dir {
+            children: BTreeSet::new(),
+        });
+        self
+    }
+
+    /// Add a file, and any parent directories that don't exist yet
+    pub fn add_file(&mut self, path: impl AsRef<Path>, len: u64) -> &mut Self {
+        let path = normalize(path.as_ref());
+        self.add_parents(&path);
+        self.nodes.insert(path, MemoryNode::File { len });
+        self
+    }
+
+    /// Add a symlink pointing at `target`, and any parent directories that don't exist yet
+    pub fn add_symlink(&mut self, path: impl AsRef<Path>, target: impl Into<PathBuf>) -> &mut Self {
+        let path = normalize(path.as_ref());
+        self.add_parents(&path);
+        self.nodes.insert(
+            path,
+            MemoryNode::Symlink {
+                target: target.into(),
+            },
+        );
+        self
+    }
+
+    fn add_parents(&mut self, path: &Path) {
+        let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+            return;
+        };
+        self.add_dir(parent);
+        if let Some(MemoryNode::Dir { children }) = self.nodes.get_mut(parent) {
+            children.insert(name.to_owned());
+        }
+    }
+
+    fn get(&self, path: &Path) -> io::Result<(&Path, &MemoryNode)> {
+        self.nodes
+            .get_key_value(&normalize(path))
+            .map(|(path, node)| (path.as_path(), node))
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+    }
+
+    fn resolve(&self, path: &Path) -> io::Result<&MemoryNode> {
+        // Same limit as Linux uses for nested symlinks
+        const MAX_LINKS: usize = 40;
+
+        let mut current = self.get(path)?;
+        for _ in 0..MAX_LINKS {
+            match current {
+                (link_path, MemoryNode::Symlink { target }) => {
+                    let target = link_path
+                        .parent()
+                        .map(|parent| parent.join(target))
+                        .unwrap_or_else(|| target.clone());
+                    current = self.get(&target)?;
+                }
+                (_, node) => return Ok(node),
+            }
+        }
+        Err(io::Error::other("too many levels of symbolic links"))
+    }
+}
+
+fn node_metadata(node: &MemoryNode) -> Metadata {
+    match node {
+        MemoryNode::File { len } => Metadata {
+            file_type: FileType::File,
+            len: *len,
+        },
+        MemoryNode::Dir { .. } => Metadata {
+            file_type: FileType::Dir,
+            len: 0,
+        },
+        MemoryNode::Symlink { target } => Metadata {
+            file_type: FileType::Symlink,
+            len: target.as_os_str().len() as u64,
+        },
+    }
+}
+
+impl FileSystem for MemoryFileSystem {
+    fn read_dir(&self, path: &Path) -> io::Result<ReadDir<'_>> {
+        match self.resolve(path)? {
+            MemoryNode::Dir { children } => {
+                let path = path.to_owned();
+                Ok(Box::new(
+                    children.iter().map(move |name| Ok(path.join(name))),
+                ))
+            }
+            _ => Err(io::Error::from(io::ErrorKind::NotADirectory)),
+        }
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        self.resolve(path).map(node_metadata)
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<Metadata> {
+        self.get(path).map(|(_, node)| node_metadata(node))
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        match self.get(path)? {
+            (_, MemoryNode::Symlink { target }) => Ok(target.clone()),
+            _ => Err(io::Error::from(io::ErrorKind::InvalidInput)),
+        }
+    }
+}
+
+/// Lexically remove `.` and `..` components from a path
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => {
+                out.pop();
+            }
+            _ => out.push(component),
+        }
+    }
+    out
+}
+
+/// Make a path from bytes that came from a pattern or a path, which are only UTF-8 on some
+/// platforms
+#[cfg(unix)]
+pub(crate) fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+    OsString::from_vec(bytes).into()
+}
+
+#[cfg(not(unix))]
+pub(crate) fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    String::from_utf8_lossy(&bytes).into_owned().into()
+}
@@ -0,0 +1,409 @@
+//! Generates random paths that match a program, and near misses that don't
+//!
+//! Matching paths are built by walking the program like the matcher does, but choosing which
+//! alternative to take and what to consume instead of reading it from a path. Every path is
+//! checked with the matcher before it is returned, so a walk that runs into something it can't
+//! satisfy is simply retried.
+
+use std::{collections::HashMap, ops::RangeInclusive, path::PathBuf};
+
+use crate::{
+    compiler::{Instruction, Program, ProgramOffset},
+    filesystem::path_from_bytes,
+    matcher::path_matches,
+    parser::CharacterClass,
+};
+
+#[cfg(test)]
+mod tests;
+
+/// How many times to try generating a path before giving up
+const MAX_ATTEMPTS: usize = 1000;
+/// How many instructions a single walk can execute, in case of loops that consume nothing
+const MAX_STEPS: usize = 100_000;
+/// Characters to fill in wildcards with
+const CHARSET: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789_-";
+
+#[derive(Debug, Clone)]
+pub struct GenerateOptions {
+    /// How many characters `*` and each component of `**` match
+    pub component_length: RangeInclusive<usize>,
+    /// How many components `**` matches
+    pub recurse_depth: RangeInclusive<usize>,
+    /// Seed for the random number generator, to get the same paths every time
+    pub seed: Option<u64>,
+}
+
+impl Default for GenerateOptions {
+    fn default() -> Self {
+        GenerateOptions {
+            component_length: 1..=8,
+            recurse_depth: 0..=3,
+            seed: None,
+        }
+    }
+}
+
+pub struct Generator<'a> {
+    program: &'a Program,
+    options: GenerateOptions,
+    rng: fastrand::Rng,
+}
+
+#[derive(Debug, Clone)]
+enum Component {
+    Prefix(String),
+    RootDir,
+    CurDir,
+    ParentDir,
+    Normal(Vec<u8>),
+}
+
+/// What the walk knows about the component being built, like the matcher's current string
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// No component is loaded. If `pending`, a separator has promised there is another one.
+    Between { pending: bool },
+    /// A normal component is being built
+    Inside,
+    /// The last component is finished, but the matcher would still have it loaded
+    Drained,
+}
+
+/// The state of a single walk through the program
+struct Walk {
+    pc: usize,
+    mode: Mode,
+    components: Vec<Component>,
+    current: Vec<u8>,
+    counters: Vec<u32>,
+    /// How many more times to go around the loop at each alternative
+    loops: HashMap<usize, usize>,
+}
+
+impl Walk {
+    /// Finish the component being built, if there is one. Components can't be empty.
+    fn finish(&mut self) -> Option<()> {
+        if self.mode == Mode::Inside {
+            if self.current.is_empty() {
+                return None;
+            }
+            let current = std::mem::take(&mut self.current);
+            self.components.push(Component::Normal(current));
+            self.mode = Mode::Drained;
+        }
+        Some(())
+    }
+
+    /// Start building a component if there isn't one, so something can be consumed from it
+    fn load(&mut self) -> Option<()> {
+        match self.mode {
+            Mode::Between { .. } => {
+                self.mode = Mode::Inside;
+                Some(())
+            }
+            Mode::Inside => Some(()),
+            Mode::Drained => None,
+        }
+    }
+
+    fn path(&self) -> PathBuf {
+        let mut path = PathBuf::new();
+        for component in &self.components {
+            match component {
+                Component::Prefix(prefix) => path.push(prefix),
+                Component::RootDir => path.push(std::path::MAIN_SEPARATOR_STR),
+                Component::CurDir => path.push("."),
+                Component::ParentDir => path.push(".."),
+                Component::Normal(bytes) => path.push(path_from_bytes(bytes.clone())),
+            }
+        }
+        path
+    }
+}
+
+impl<'a> Generator<'a> {
+    pub fn new(program: &'a Program, options: GenerateOptions) -> Generator<'a> {
+        let rng = match options.seed {
+            Some(seed) => fastrand::Rng::with_seed(seed),
+            None => fastrand::Rng::new(),
+        };
+        Generator {
+            program,
+            options,
+            rng,
+        }
+    }
+
+    /// Generate a random path that the program matches, or `None` if none could be found
+    pub fn matching(&mut self) -> Option<PathBuf> {
+        (0..MAX_ATTEMPTS).find_map(|_| {
+            let path = self.walk()?;
+            path_matches(&path, self.program)
+                .valid_as_complete_match
+                .then_some(path)
+        })
+    }
+
+    /// Generate a random path close to one that matches, which the program doesn't match
+    pub fn non_matching(&mut self) -> Option<PathBuf> {
+        (0..MAX_ATTEMPTS).find_map(|_| {
+            let path = self.matching()?;
+            let path = self.mutate(path);
+            (!path_matches(&path, self.program).valid_as_complete_match).then_some(path)
+        })
+    }
+
+    fn walk(&mut self) -> Option<PathBuf> {
+        let mut walk = Walk {
+            pc: 0,
+            mode: Mode::Between { pending: false },
+            components: vec![],
+            current: vec![],
+            counters: vec![0; self.program.counters as usize],
+            loops: HashMap::new(),
+        };
+        for _ in 0..MAX_STEPS {
+            let instruction = self.program.instructions.get(walk.pc)?;
+            let mut next = walk.pc + 1;
+            match instruction {
+                Instruction::Separator => match walk.mode {
+                    Mode::Inside => {
+                        walk.finish()?;
+                        walk.mode = Mode::Between { pending: true };
+                    }
+                    Mode::Between { .. } | Mode::Drained => {
+                        walk.mode = Mode::Between { pending: true };
+                    }
+                },
+                Instruction::Prefix(_)
+                | Instruction::RootDir
+                | Instruction::CurDir
+                | Instruction::ParentDir => {
+                    walk.finish()?;
+                    walk.components.push(match instruction {
+                        Instruction::Prefix(prefix) => Component::Prefix(prefix.to_string()),
+                        Instruction::RootDir => Component::RootDir,
+                        Instruction::CurDir => Component::CurDir,
+                        _ => Component::ParentDir,
+                    });
+                    if let Mode::Between { .. } = walk.mode {
+                        walk.mode = Mode::Between { pending: false };
+                    }
+                }
+                Instruction::LiteralString(bytes) => {
+                    if walk.mode == Mode::Drained && bytes.is_empty() {
+                        // An empty literal matches the empty string that is still loaded
+                    } else {
+                        walk.load()?;
+                        walk.current.extend_from_slice(bytes);
+                    }
+                }
+                Instruction::AnyCharacter => {
+                    walk.load()?;
+                    walk.current.push(self.random_char());
+                }
+                Instruction::Characters(classes) => {
+                    walk.load()?;
+                    let ch = self.random_char_in(classes)?;
+                    let mut buf = [0; 4];
+                    walk.current
+                        .extend_from_slice(ch.encode_utf8(&mut buf).as_bytes());
+                }
+                Instruction::AnyString => {
+                    if walk.mode != Mode::Drained {
+                        walk.load()?;
+                        // The component can't end up empty, but otherwise any length will do
+                        let min = usize::from(walk.current.is_empty());
+                        let len = self.component_length().max(min);
+                        for _ in 0..len {
+                            let ch = self.random_char();
+                            walk.current.push(ch);
+                        }
+                        walk.finish()?;
+                    }
+                }
+                Instruction::SkipToLiteral(bytes) => {
+                    walk.load()?;
+                    let len = self.sample(0..=*self.options.component_length.end());
+                    for _ in 0..len {
+                        let ch = self.random_char();
+                        walk.current.push(ch);
+                    }
+                    walk.current.extend_from_slice(bytes);
+                }
+                Instruction::Jump(target) => next = target.0,
+                Instruction::Alternative(_) => next = self.choose(&mut walk),
+                Instruction::Increment(counter_id) => {
+                    walk.counters[counter_id.0 as usize] += 1;
+                }
+                Instruction::BranchIfLessThan(target, counter_id, value) => {
+                    if walk.counters[counter_id.0 as usize] < *value {
+                        next = target.0;
+                    }
+                }
+                Instruction::Complete => {
+                    if walk.mode == (Mode::Between { pending: true }) {
+                        return None;
+                    }
+                    walk.finish()?;
+                    return Some(walk.path());
+                }
+            }
+            walk.pc = next;
+        }
+        None
+    }
+
+    /// Decide where to go from the alternative at the current instruction
+    fn choose(&mut self, walk: &mut Walk) -> usize {
+        let pc = walk.pc;
+        let target = |pc: usize| match self.program.instructions.get(pc) {
+            Some(Instruction::Alternative(target)) => Some(target.0),
+            _ => None,
+        };
+
+        if let Some(recurse) = self.loop_kind(pc) {
+            // Pick how many times to go around the loop when it is entered, and count down
+            let remaining = match walk.loops.get(&pc) {
+                Some(remaining) => *remaining,
+                None if recurse => self.sample(self.options.recurse_depth.clone()),
+                None => self.component_length(),
+            };
+            return if remaining == 0 {
+                walk.loops.remove(&pc);
+                pc + 1
+            } else {
+                walk.loops.insert(pc, remaining - 1);
+                target(pc).unwrap_or(pc + 1)
+            };
+        }
+
+        // A run of alternatives is a choice between all of their targets and what follows them,
+        // so pick one of those evenly
+        let mut choices = vec![];
+        let mut end = pc;
+        while let Some(choice) = target(end).filter(|_| end == pc || self.loop_kind(end).is_none())
+        {
+            choices.push(choice);
+            end += 1;
+        }
+        choices.push(end);
+        choices[self.rng.usize(..choices.len())]
+    }
+
+    /// If the alternative at `pc` starts a loop made by `*` or `**`, whether it is `**`
+    ///
+    /// These loops are an alternative whose target consumes something and then jumps back to it.
+    fn loop_kind(&self, pc: usize) -> Option<bool> {
+        let Some(Instruction::Alternative(target)) = self.program.instructions.get(pc) else {
+            return None;
+        };
+        let body = self.program.instructions.get(target.0..)?;
+        match body {
+            [Instruction::AnyCharacter, Instruction::Jump(back), ..]
+                if *back == ProgramOffset(pc) =>
+            {
+                Some(false)
+            }
+            [Instruction::AnyString, Instruction::Separator, Instruction::Jump(back), ..]
+                if *back == ProgramOffset(pc) =>
+            {
+                Some(true)
+            }
+            _ => None,
+        }
+    }
+
+    fn component_length(&mut self) -> usize {
+        self.sample(self.options.component_length.clone())
+    }
+
+    /// Pick a number from a range, or its start if it's empty
+    fn sample(&mut self, range: RangeInclusive<usize>) -> usize {
+        if range.is_empty() {
+            *range.start()
+        } else {
+            self.rng.usize(range)
+        }
+    }
+
+    fn random_char(&mut self) -> u8 {
+        CHARSET[self.rng.usize(..CHARSET.len())]
+    }
+
+    fn random_char_in(&mut self, classes: &[CharacterClass]) -> Option<char> {
+        let class = classes.get(self.rng.usize(..classes.len().max(1)))?;
+        match *class {
+            CharacterClass::Single(ch) => Some(ch),
+            CharacterClass::Range(start, end) if start <= end => {
+                // Retry on surrogates, which are in some ranges but aren't characters
+                (0..16).find_map(|_| char::from_u32(self.rng.u32(start as u32..=end as u32)))
+            }
+            CharacterClass::Range(..) => None,
+        }
+    }
+
+    /// Make a small random change to a path
+    fn mutate(&mut self, path: PathBuf) -> PathBuf {
+        let mut components: Vec<Vec<u8>> = path
+            .iter()
+            .map(|component| component.as_encoded_bytes().to_vec())
+            .collect();
+        let is_root = |component: &[u8]| component == std::path::MAIN_SEPARATOR_STR.as_bytes();
+        let normal: Vec<usize> = (0..components.len())
+            .filter(|index| !is_root(&components[*index]))
+            .collect();
+
+        match (self.rng.u8(..5), normal.is_empty()) {
+            // Change, add or remove a character in a component, keeping multi-byte characters
+            // whole
+            (0, false) | (1, false) | (2, false) => {
+                let component = &mut components[normal[self.rng.usize(..normal.len())]];
+                let boundaries = char_boundaries(component);
+                let index = self.rng.usize(..boundaries.len());
+                let position = boundaries[index];
+                let ch = self.random_char();
+                match (self.rng.u8(..3), boundaries.get(index + 1)) {
+                    (0, Some(&end)) => {
+                        component.splice(position..end, [ch]);
+                    }
+                    (1, Some(&end)) if boundaries.len() > 2 => {
+                        component.drain(position..end);
+                    }
+                    _ => component.insert(position, ch),
+                }
+            }
+            // Remove a whole component
+            (3, false) => {
+                components.remove(normal[self.rng.usize(..normal.len())]);
+            }
+            // Add a component somewhere
+            _ => {
+                let start = usize::from(components.first().is_some_and(|first| is_root(first)));
+                let position = self.rng.usize(start..=components.len());
+                let len = self.component_length().max(1);
+                let component = (0..len).map(|_| self.random_char()).collect();
+                components.insert(position, component);
+            }
+        }
+
+        components.into_iter().map(path_from_bytes).collect()
+    }
+}
+
+/// Where each character starts in `bytes`, and the end. Bytes that aren't valid UTF-8 count as
+/// characters of their own.
+fn char_boundaries(bytes: &[u8]) -> Vec<usize> {
+    let mut boundaries = vec![];
+    let mut start = 0;
+    for chunk in bytes.utf8_chunks() {
+        let valid = chunk.valid();
+        boundaries.extend(valid.char_indices().map(|(index, _)| start + index));
+        start += valid.len();
+        boundaries.extend(start..start + chunk.invalid().len());
+        start += chunk.invalid().len();
+    }
+    boundaries.push(start);
+    boundaries
+}
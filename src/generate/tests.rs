@@ -0,0 +1,95 @@
+use crate::{
+    compiler::compile,
+    generate::{GenerateOptions, Generator},
+    matcher::path_matches,
+    parser::parse,
+};
+
+fn seeded() -> GenerateOptions {
+    GenerateOptions {
+        seed: Some(7),
+        ..GenerateOptions::default()
+    }
+}
+
+#[test]
+fn generated_paths_match() {
+    for pattern in [
+        "src/**/*.rs",
+        "*.{rs,md,toml}",
+        "/usr/lib/lib?.so",
+        "img<[0-9]:2>.png",
+        "a/<*/:1,3>b",
+        "../[a-c]*/./x",
+        "{a,b/c,d/e/f}",
+    ] {
+        let program = compile(&parse(pattern)).unwrap();
+        let mut generator = Generator::new(&program, seeded());
+        for _ in 0..50 {
+            let path = generator.matching().unwrap();
+            assert!(
+                path_matches(&path, &program).valid_as_complete_match,
+                "{} {}",
+                pattern,
+                path.display()
+            );
+        }
+    }
+}
+
+#[test]
+fn non_matching_paths_do_not_match() {
+    for pattern in ["src/**/*.rs", "*.md", "a/b", "<[0-9]:3>"] {
+        let program = compile(&parse(pattern)).unwrap();
+        let mut generator = Generator::new(&program, seeded());
+        for _ in 0..50 {
+            let path = generator.non_matching().unwrap();
+            assert!(!path_matches(&path, &program).valid_as_complete_match);
+        }
+    }
+}
+
+#[test]
+fn non_matching_paths_keep_characters_whole() {
+    let program = compile(&parse("éééé/éééé")).unwrap();
+    let mut generator = Generator::new(&program, seeded());
+    for _ in 0..200 {
+        let path = generator.non_matching().unwrap();
+        assert!(path.to_str().is_some(), "{}", path.display());
+    }
+}
+
+#[test]
+fn distributions_are_respected() {
+    let program = compile(&parse("**/*")).unwrap();
+    let options = GenerateOptions {
+        component_length: 3..=3,
+        recurse_depth: 2..=2,
+        seed: Some(1),
+    };
+    let mut generator = Generator::new(&program, options);
+    for _ in 0..20 {
+        let path = generator.matching().unwrap();
+        let components: Vec<_> = path.iter().collect();
+        assert_eq!(components.len(), 3, "{}", path.display());
+        assert!(components.iter().all(|component| component.len() == 3));
+    }
+}
+
+#[test]
+fn seed_makes_paths_repeatable() {
+    let program = compile(&parse("**/*.{a,b}")).unwrap();
+    let paths = || {
+        let mut generator = Generator::new(&program, seeded());
+        (0..10)
+            .map(|_| generator.matching().unwrap())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(paths(), paths());
+}
+
+#[test]
+fn unmatchable_patterns_give_up() {
+    let program = compile(&parse("[z-a]")).unwrap();
+    assert_eq!(Generator::new(&program, seeded()).matching(), None);
+}
@@ -1,119 +1,603 @@
 use std::path::{Path, PathBuf};
-use std::sync::mpsc::{sync_channel, SendError, SyncSender};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
 use std::sync::Arc;
-use std::{fs, io};
+use std::{io, vec};
 
 use crate::compiler::Program;
-use crate::matcher::path_matches;
+use crate::filesystem::{FileSystem, Metadata, StdFileSystem};
+use crate::globset::{GlobSet, SetMatchResult};
 
 use anyhow::anyhow;
 
-pub fn glob(
+#[cfg(feature = "stream")]
+mod stream;
+#[cfg(test)]
+mod tests;
+
+#[cfg(feature = "stream")]
+pub use stream::{glob_stream, GlobStream};
+
+#[derive(Debug, Default)]
+struct CancellationState {
+    cancelled: AtomicBool,
+    parent: Option<Arc<CancellationState>>,
+}
+
+/// Tells a walk to stop doing any more work
+///
+/// Clones share the same state, so cancelling any clone cancels all of them.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<CancellationState>);
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Create a new token that is also cancelled when this one is, but can be cancelled on its own
+    pub fn child(&self) -> CancellationToken {
+        CancellationToken(Arc::new(CancellationState {
+            cancelled: AtomicBool::new(false),
+            parent: Some(self.0.clone()),
+        }))
+    }
+
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        let mut state = Some(&self.0);
+        while let Some(current) = state {
+            if current.cancelled.load(Ordering::Relaxed) {
+                return true;
+            }
+            state = current.parent.as_ref();
+        }
+        false
+    }
+}
+
+/// Where the work of a walk happens
+#[derive(Debug, Clone, Default)]
+pub enum Parallelism {
+    /// Spawn onto the global rayon thread pool
+    #[default]
+    Global,
+    /// Create a dedicated thread pool with this many threads for the walk
+    Threads(usize),
+    /// Spawn onto an existing thread pool
+    Pool(Arc<rayon::ThreadPool>),
+    /// Walk depth-first on the thread calling [`Iterator::next`], without spawning any threads
+    Sequential,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct GlobOptions {
+    /// Stop after this many matching paths have been produced
+    pub limit: Option<usize>,
+    /// Stop the walk early when cancelled
+    pub cancel: CancellationToken,
+    pub parallelism: Parallelism,
+    /// Don't produce or descend into entries whose names start with a dot
+    pub skip_hidden: bool,
+}
+
+/// A path found by a walk over many patterns
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GlobMatch {
+    pub path: PathBuf,
+    /// Indices of the patterns that completely matched the path
+    pub patterns: Vec<usize>,
+}
+
+type GlobResult = anyhow::Result<GlobMatch>;
+
+/// The receiver has gone away, so there's no point in continuing the walk
+#[derive(Debug)]
+struct Disconnected;
+
+/// The sending half of the bounded channel that a parallel walk sends its results through
+trait ResultSender: Clone + Send + 'static {
+    /// Send a result, blocking while the channel is full
+    fn send(&mut self, result: GlobResult) -> Result<(), Disconnected>;
+}
+
+impl ResultSender for SyncSender<GlobResult> {
+    fn send(&mut self, result: GlobResult) -> Result<(), Disconnected> {
+        SyncSender::send(self, result).map_err(|_| Disconnected)
+    }
+}
+
+/// How many results can be waiting in the channel before the walk blocks
+const CHANNEL_CAPACITY: usize = 4096;
+
+enum GlobSource {
+    Channel(Receiver<GlobResult>),
+    Sequential(Box<dyn Iterator<Item = GlobResult> + Send>),
+}
+
+/// A walk in progress, and how many more of its results can be taken from `source`
+///
+/// Dropping this stops the walk.
+struct Walking<R> {
+    source: R,
+    cancel: CancellationToken,
+    remaining: Option<usize>,
+    // Keep a dedicated pool alive for as long as the walk might be using it
+    _pool: Option<Arc<rayon::ThreadPool>>,
+}
+
+impl<R> Walking<R> {
+    /// True if no more results should be produced, in which case the walk is also cancelled
+    fn is_finished(&self) -> bool {
+        if self.remaining == Some(0) || self.cancel.is_cancelled() {
+            self.cancel.cancel();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn count(&mut self, result: &GlobResult) {
+        if let (Some(remaining), Ok(_)) = (&mut self.remaining, result) {
+            *remaining -= 1;
+            if *remaining == 0 {
+                // Stop the walk now, as the caller may never ask for the next result
+                self.cancel.cancel();
+            }
+        }
+    }
+}
+
+impl<R> Drop for Walking<R> {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+    }
+}
+
+/// The results of a glob over many patterns
+///
+/// Dropping this stops the walk.
+pub struct MultiGlob(Walking<GlobSource>);
+
+impl Iterator for MultiGlob {
+    type Item = anyhow::Result<GlobMatch>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let walking = &mut self.0;
+        if walking.is_finished() {
+            return None;
+        }
+        let result = match &mut walking.source {
+            GlobSource::Channel(rx) => rx.recv().ok()?,
+            GlobSource::Sequential(iter) => iter.next()?,
+        };
+        walking.count(&result);
+        Some(result)
+    }
+}
+
+/// The results of a glob
+///
+/// Dropping this stops the walk.
+pub struct Glob(MultiGlob);
+
+impl Iterator for Glob {
+    type Item = anyhow::Result<PathBuf>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0
+            .next()
+            .map(|result| result.map(|glob_match| glob_match.path))
+    }
+}
+
+/// Glob on the real filesystem
+pub fn glob(relative_to: impl Into<PathBuf>, program: Arc<Program>) -> Glob {
+    glob_in(Arc::new(StdFileSystem), relative_to, program)
+}
+
+/// Glob on any [`FileSystem`]
+pub fn glob_in<F: FileSystem + 'static>(
+    fs: Arc<F>,
     relative_to: impl Into<PathBuf>,
     program: Arc<Program>,
-) -> impl Iterator<Item = anyhow::Result<PathBuf>> + Send {
-    let (tx, rx) = sync_channel(4096);
-
-    // Start at the program absolute prefix if the program is an absolute glob
-    let current_dir = program
-        .absolute_prefix
-        .clone()
-        .unwrap_or_else(|| relative_to.into());
-
-    rayon::spawn(move || {
-        // Don't relativize paths if this is an absolute program
-        let output_relative_to = if program.absolute_prefix.is_some() {
-            Path::new("")
-        } else {
-            &current_dir
+) -> Glob {
+    glob_with(fs, relative_to, program, GlobOptions::default())
+}
+
+/// Glob on any [`FileSystem`] with options
+pub fn glob_with<F: FileSystem + 'static>(
+    fs: Arc<F>,
+    relative_to: impl Into<PathBuf>,
+    program: Arc<Program>,
+    options: GlobOptions,
+) -> Glob {
+    Glob(glob_many(
+        fs,
+        relative_to,
+        std::slice::from_ref(&*program),
+        options,
+    ))
+}
+
+/// Glob for many patterns at once, with a single walk over each directory
+///
+/// Relative patterns are walked from `relative_to`, and absolute patterns from their absolute
+/// prefix. A path that is reachable from more than one of these starting points is produced once
+/// for each of them.
+pub fn glob_many<F: FileSystem + 'static>(
+    fs: Arc<F>,
+    relative_to: impl Into<PathBuf>,
+    programs: &[Program],
+    options: GlobOptions,
+) -> MultiGlob {
+    let cancel = walk_token(&options);
+    let walking = |source, pool| {
+        MultiGlob(Walking {
+            source,
+            cancel: cancel.clone(),
+            remaining: options.limit,
+            _pool: pool,
+        })
+    };
+    let failed = |err| {
+        walking(
+            GlobSource::Sequential(Box::new(std::iter::once(Err(err)))),
+            None,
+        )
+    };
+
+    let relative_to = relative_to.into();
+    if let Parallelism::Sequential = options.parallelism {
+        let roots = match roots(relative_to, programs, &options) {
+            Ok(roots) => roots,
+            Err(err) => return failed(err),
+        };
+        let walks = roots.into_iter().flat_map({
+            let cancel = cancel.clone();
+            move |root| SequentialWalk::new(fs.clone(), root, cancel.clone())
+        });
+        return walking(GlobSource::Sequential(Box::new(walks)), None);
+    }
+
+    let (tx, rx) = sync_channel(CHANNEL_CAPACITY);
+    match spawn_walk(fs, relative_to, programs, &options, &cancel, tx) {
+        Ok(pool) => walking(GlobSource::Channel(rx), pool),
+        Err(err) => failed(err),
+    }
+}
+
+/// The patterns that are walked from the same starting point
+struct Root {
+    start_dir: PathBuf,
+    /// What to make paths relative to for matching and output
+    relative_to: PathBuf,
+    set: GlobSet,
+    /// The index of each pattern in `set` within the patterns given to the walk
+    indices: Vec<usize>,
+    skip_hidden: bool,
+}
+
+impl Root {
+    /// Whether a directory entry should be left out of the walk entirely
+    fn skips(&self, path: &Path) -> bool {
+        self.skip_hidden
+            && path
+                .file_name()
+                .is_some_and(|name| name.as_encoded_bytes().starts_with(b"."))
+    }
+
+    /// Match a path found during the walk, relative to the directory the walk is relative to
+    fn match_candidate<'p>(&self, path: &'p Path) -> (&'p Path, SetMatchResult) {
+        let path_candidate = path.strip_prefix(&self.relative_to).unwrap_or(path);
+
+        let result = self.set.matches(path_candidate);
+
+        log::debug!(
+            "path_candidate={}, result={:?}",
+            path_candidate.display(),
+            result
+        );
+
+        (path_candidate, result)
+    }
+
+    fn glob_match(&self, path: &Path, result: &SetMatchResult) -> GlobMatch {
+        GlobMatch {
+            path: path.to_owned(),
+            patterns: result
+                .complete
+                .iter()
+                .map(|index| self.indices[*index])
+                .collect(),
+        }
+    }
+}
+
+/// Group the programs by where they need to be walked from
+fn roots(
+    relative_to: PathBuf,
+    programs: &[Program],
+    options: &GlobOptions,
+) -> anyhow::Result<Vec<Root>> {
+    let mut groups: Vec<(Option<&Path>, Vec<usize>)> = vec![];
+    for (index, program) in programs.iter().enumerate() {
+        let absolute_prefix = program.absolute_prefix.as_deref();
+        match groups
+            .iter_mut()
+            .find(|(prefix, _)| *prefix == absolute_prefix)
+        {
+            Some((_, indices)) => indices.push(index),
+            None => groups.push((absolute_prefix, vec![index])),
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|(absolute_prefix, indices)| {
+            let set = GlobSet::new(indices.iter().map(|index| &programs[*index]))?;
+            Ok(match absolute_prefix {
+                // Start at the program absolute prefix if the program is an absolute glob, and
+                // don't relativize paths
+                Some(prefix) => Root {
+                    start_dir: prefix.to_owned(),
+                    relative_to: PathBuf::new(),
+                    set,
+                    indices,
+                    skip_hidden: options.skip_hidden,
+                },
+                None => Root {
+                    start_dir: relative_to.clone(),
+                    relative_to: relative_to.clone(),
+                    set,
+                    indices,
+                    skip_hidden: options.skip_hidden,
+                },
+            })
+        })
+        .collect()
+}
+
+/// A token for a walk, that is also cancelled by `options.cancel`
+fn walk_token(options: &GlobOptions) -> CancellationToken {
+    // Our own token, so that dropping the results doesn't cancel anything else sharing the
+    // caller's token
+    let cancel = options.cancel.child();
+    if options.limit == Some(0) {
+        cancel.cancel();
+    }
+    cancel
+}
+
+/// Start a walk on a thread pool, sending its results through `tx`. Returns the pool if it's one
+/// that has to be kept alive for the walk.
+fn spawn_walk<F: FileSystem + 'static, S: ResultSender>(
+    fs: Arc<F>,
+    relative_to: PathBuf,
+    programs: &[Program],
+    options: &GlobOptions,
+    cancel: &CancellationToken,
+    tx: S,
+) -> anyhow::Result<Option<Arc<rayon::ThreadPool>>> {
+    let roots = roots(relative_to, programs, options)?;
+
+    let pool = match &options.parallelism {
+        Parallelism::Global => None,
+        Parallelism::Threads(num_threads) => Some(Arc::new(
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(*num_threads)
+                .build()?,
+        )),
+        Parallelism::Pool(pool) => Some(pool.clone()),
+        Parallelism::Sequential => anyhow::bail!("A sequential walk can't be spawned"),
+    };
+
+    for root in roots {
+        let fs = fs.clone();
+        let tx = tx.clone();
+        let walk_cancel = cancel.clone();
+        let job = move || {
+            let walk = Walk {
+                fs: &*fs,
+                root: &root,
+                cancel: &walk_cancel,
+            };
+            walk.glob_to(tx, &root.start_dir)
         };
+        match &pool {
+            Some(pool) => pool.spawn(job),
+            None => rayon::spawn(job),
+        }
+    }
+
+    Ok(pool)
+}
+
+struct Walk<'a, F> {
+    fs: &'a F,
+    root: &'a Root,
+    cancel: &'a CancellationToken,
+}
 
-        glob_to(tx, output_relative_to, &current_dir, &program)
-    });
-    rx.into_iter()
-}
-
-fn glob_to(
-    tx: SyncSender<anyhow::Result<PathBuf>>,
-    relative_to: &Path,
-    target: &Path,
-    program: &Program,
-) {
-    match fs::read_dir(target) {
-        Ok(results) => rayon::scope(|scope| -> Result<(), SendError<_>> {
-            // Try the parent dir in case the glob matches it
-            let parent_path = target.join("..");
-
-            handle_path_candidate(
-                &parent_path,
-                || fs::metadata(&parent_path),
-                &tx,
-                relative_to,
-                program,
-                &scope,
-            )?;
-
-            // All of the real results from the directory listing
-            for result in results {
-                match result {
-                    Ok(dir_entry) => {
-                        let dir_entry_path = dir_entry.path();
-
-                        handle_path_candidate(
-                            &dir_entry_path,
-                            || dir_entry.metadata(),
-                            &tx,
-                            relative_to,
-                            program,
-                            &scope,
-                        )?;
+impl<'a, F: FileSystem> Walk<'a, F> {
+    fn glob_to<S: ResultSender>(&self, mut tx: S, target: &Path) {
+        if self.cancel.is_cancelled() {
+            return;
+        }
+        match self.fs.read_dir(target) {
+            Ok(results) => rayon::scope(|scope| -> Result<(), Disconnected> {
+                // Try the parent dir in case the glob matches it
+                let parent_path = target.join("..");
+
+                self.handle_path_candidate(
+                    &parent_path,
+                    || self.fs.metadata(&parent_path),
+                    &mut tx,
+                    scope,
+                )?;
+
+                // All of the real results from the directory listing
+                for result in results {
+                    if self.cancel.is_cancelled() {
+                        break;
                     }
-                    Err(err) => {
-                        let wrapped_err = anyhow!("{}: {}", target.display(), err);
-                        tx.send(Err(wrapped_err))?;
+                    match result {
+                        Ok(entry_path) if self.root.skips(&entry_path) => (),
+                        Ok(entry_path) => {
+                            self.handle_path_candidate(
+                                &entry_path,
+                                || self.fs.symlink_metadata(&entry_path),
+                                &mut tx,
+                                scope,
+                            )?;
+                        }
+                        Err(err) => {
+                            let wrapped_err = anyhow!("{}: {}", target.display(), err);
+                            tx.send(Err(wrapped_err))?;
+                        }
                     }
                 }
+
+                Ok(())
+            })
+            .unwrap_or(()),
+            Err(err) => {
+                let wrapped_err = anyhow!("{}: {}", target.display(), err);
+                let _ = tx.send(Err(wrapped_err));
             }
+        }
+    }
 
-            Ok(())
-        })
-        .unwrap_or(()),
-        Err(err) => {
-            let wrapped_err = anyhow!("{}: {}", target.display(), err);
-            let _ = tx.send(Err(wrapped_err));
+    fn handle_path_candidate<'s, S: ResultSender>(
+        &'s self,
+        path: &Path,
+        get_metadata: impl FnOnce() -> std::io::Result<Metadata>,
+        tx: &mut S,
+        scope: &rayon::Scope<'s>,
+    ) -> Result<(), Disconnected> {
+        let (path_candidate, result) = self.root.match_candidate(path);
+
+        // If any pattern could still match inside it and it is a dir, recurse
+        if !result.prefix.is_empty()
+            && !self.cancel.is_cancelled()
+            && get_metadata().is_ok_and(|m| m.is_dir())
+        {
+            // Recurse with the full path, as the relative path is only meaningful to the matcher
+            let tx = tx.clone();
+            let path = path.to_owned();
+            scope.spawn(move |_| self.glob_to(tx, &path));
+        }
+
+        // If it is valid as a complete match for any pattern, send it out
+        if result.is_match() {
+            tx.send(Ok(self.root.glob_match(path_candidate, &result)))?;
         }
+
+        Ok(())
     }
 }
 
-fn handle_path_candidate<'a>(
-    path: &Path,
-    get_metadata: impl FnOnce() -> io::Result<fs::Metadata>,
-    tx: &SyncSender<anyhow::Result<PathBuf>>,
-    relative_to: &'a Path,
-    program: &'a Program,
-    scope: &rayon::Scope<'a>,
-) -> Result<(), SendError<anyhow::Result<PathBuf>>> {
-    let path_candidate = path.strip_prefix(relative_to).unwrap_or(&path);
-
-    let result = path_matches(path_candidate, program);
+struct Frame {
+    dir: PathBuf,
+    /// The parent dir hasn't been tried yet
+    parent_pending: bool,
+    entries: vec::IntoIter<io::Result<PathBuf>>,
+}
 
-    log::debug!(
-        "path_candidate={}, result={:?}",
-        path_candidate.display(),
-        result
-    );
+/// A depth-first walk that does all of its work inside [`Iterator::next`]
+struct SequentialWalk<F> {
+    fs: Arc<F>,
+    root: Root,
+    cancel: CancellationToken,
+    stack: Vec<Frame>,
+    /// An error from entering a directory, to be produced after the directory itself
+    pending_error: Option<anyhow::Error>,
+}
 
-    // If it is a valid prefix and a dir, recurse
-    if result.valid_as_prefix && get_metadata().is_ok_and(|m| m.is_dir()) {
-        let tx = tx.clone();
-        let path_candidate = path_candidate.to_owned();
-        scope.spawn(move |_| glob_to(tx, relative_to, &path_candidate, program));
+impl<F: FileSystem> SequentialWalk<F> {
+    fn new(fs: Arc<F>, root: Root, cancel: CancellationToken) -> SequentialWalk<F> {
+        let start_dir = root.start_dir.clone();
+        let mut walk = SequentialWalk {
+            fs,
+            root,
+            cancel,
+            stack: vec![],
+            pending_error: None,
+        };
+        walk.enter(&start_dir);
+        walk
     }
 
-    // If it is valid as a complete match, send it out
-    if result.valid_as_complete_match {
-        tx.send(Ok(path_candidate.to_owned()))?;
+    fn enter(&mut self, dir: &Path) {
+        if self.cancel.is_cancelled() {
+            return;
+        }
+        match self.fs.read_dir(dir) {
+            Ok(results) => self.stack.push(Frame {
+                dir: dir.to_owned(),
+                parent_pending: true,
+                entries: results.collect::<Vec<_>>().into_iter(),
+            }),
+            Err(err) => {
+                self.pending_error = Some(anyhow!("{}: {}", dir.display(), err));
+            }
+        }
     }
+}
+
+impl<F: FileSystem> Iterator for SequentialWalk<F> {
+    type Item = GlobResult;
 
-    Ok(())
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(err) = self.pending_error.take() {
+                return Some(Err(err));
+            }
+            if self.cancel.is_cancelled() {
+                return None;
+            }
+            let frame = self.stack.last_mut()?;
+
+            // Try the parent dir first in case the glob matches it, then the real results
+            let (path, metadata) = if frame.parent_pending {
+                frame.parent_pending = false;
+                let parent_path = frame.dir.join("..");
+                let metadata = self.fs.metadata(&parent_path);
+                (parent_path, metadata)
+            } else {
+                match frame.entries.next() {
+                    Some(Ok(entry_path)) if self.root.skips(&entry_path) => continue,
+                    Some(Ok(entry_path)) => {
+                        let metadata = self.fs.symlink_metadata(&entry_path);
+                        (entry_path, metadata)
+                    }
+                    Some(Err(err)) => {
+                        return Some(Err(anyhow!("{}: {}", frame.dir.display(), err)));
+                    }
+                    None => {
+                        self.stack.pop();
+                        continue;
+                    }
+                }
+            };
+
+            let (path_candidate, result) = self.root.match_candidate(&path);
+            let glob_match = result
+                .is_match()
+                .then(|| self.root.glob_match(path_candidate, &result));
+
+            // If any pattern could still match inside it and it is a dir, descend into it after
+            // producing it
+            if !result.prefix.is_empty() && metadata.is_ok_and(|m| m.is_dir()) {
+                self.enter(&path);
+            }
+
+            if let Some(glob_match) = glob_match {
+                return Some(Ok(glob_match));
+            }
+        }
+    }
 }
@@ -0,0 +1,104 @@
+//! An async [`Stream`] of glob results, for use from async code without `spawn_blocking`
+
+use std::{
+    path::PathBuf,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use futures::{channel::mpsc, executor::block_on, SinkExt, Stream, StreamExt};
+
+use crate::{compiler::Program, filesystem::FileSystem};
+
+use super::{
+    spawn_walk, walk_token, Disconnected, GlobOptions, GlobResult, Parallelism, ResultSender,
+    Walking, CHANNEL_CAPACITY,
+};
+
+/// The sender of a futures channel, shared by the whole walk
+///
+/// Each clone of a futures channel sender adds to its capacity, and the walk clones its sender for
+/// every directory, so they share one to keep the channel bounded.
+#[derive(Clone)]
+struct SharedSender(Arc<Mutex<mpsc::Sender<GlobResult>>>);
+
+impl ResultSender for SharedSender {
+    fn send(&mut self, result: GlobResult) -> Result<(), Disconnected> {
+        let mut tx = self.0.lock().map_err(|_| Disconnected)?;
+        // The walk runs on the thread pool, so it's fine to block there until the consumer catches
+        // up
+        block_on(SinkExt::send(&mut *tx, result)).map_err(|_| Disconnected)
+    }
+}
+
+/// The results of a glob as a [`Stream`]
+///
+/// Dropping this stops the walk.
+pub struct GlobStream(Walking<mpsc::Receiver<GlobResult>>);
+
+impl Stream for GlobStream {
+    type Item = anyhow::Result<PathBuf>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let walking = &mut self.get_mut().0;
+        if walking.is_finished() {
+            return Poll::Ready(None);
+        }
+        let result = match walking.source.poll_next_unpin(cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+        if let Some(result) = &result {
+            walking.count(result);
+        }
+        Poll::Ready(result.map(|result| result.map(|glob_match| glob_match.path)))
+    }
+}
+
+/// Glob on any [`FileSystem`] with options, producing the results as a [`Stream`]
+///
+/// The walk always runs on a thread pool, as a [`Parallelism::Sequential`] walk would block the
+/// task polling the stream. Asking for one produces an error instead.
+pub fn glob_stream<F: FileSystem + 'static>(
+    fs: Arc<F>,
+    relative_to: impl Into<PathBuf>,
+    program: Arc<Program>,
+    options: GlobOptions,
+) -> GlobStream {
+    let cancel = walk_token(&options);
+    // The one sender adds one to the buffer
+    let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY - 1);
+    let mut tx = SharedSender(Arc::new(Mutex::new(tx)));
+
+    let started = if let Parallelism::Sequential = options.parallelism {
+        Err(anyhow::anyhow!(
+            "A stream can't walk sequentially, as that would block the executor"
+        ))
+    } else {
+        let programs = std::slice::from_ref(&*program);
+        spawn_walk(
+            fs,
+            relative_to.into(),
+            programs,
+            &options,
+            &cancel,
+            tx.clone(),
+        )
+    };
+    let pool = match started {
+        Ok(pool) => pool,
+        Err(err) => {
+            // Nothing else is sending yet, so there's room for it
+            let _ = tx.send(Err(err));
+            None
+        }
+    };
+
+    GlobStream(Walking {
+        source: rx,
+        cancel,
+        remaining: options.limit,
+        _pool: pool,
+    })
+}
@@ -0,0 +1,405 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex},
+};
+
+use crate::{
+    compiler::compile,
+    filesystem::{FileSystem, MemoryFileSystem, Metadata, ReadDir},
+    globber::{glob_in, glob_many, glob_with, GlobOptions, Parallelism},
+    parser::parse,
+};
+
+fn test_fs() -> Arc<MemoryFileSystem> {
+    let mut fs = MemoryFileSystem::new();
+    fs.add_file("/proj/Cargo.toml", 10)
+        .add_file("/proj/src/main.rs", 10)
+        .add_file("/proj/src/globber/tests.rs", 10)
+        .add_file("/proj/README.md", 10)
+        .add_dir("/proj/target/debug")
+        .add_symlink("/proj/link", "src");
+    Arc::new(fs)
+}
+
+/// Records every directory that is read, and holds up reads once it runs out of permits
+struct CountingFileSystem {
+    inner: MemoryFileSystem,
+    read: Mutex<Vec<PathBuf>>,
+    permits: Mutex<usize>,
+    changed: Condvar,
+}
+
+impl CountingFileSystem {
+    fn new(permits: usize) -> Arc<CountingFileSystem> {
+        CountingFileSystem::over(Arc::unwrap_or_clone(test_fs()), permits)
+    }
+
+    fn over(inner: MemoryFileSystem, permits: usize) -> Arc<CountingFileSystem> {
+        Arc::new(CountingFileSystem {
+            inner,
+            read: Mutex::new(vec![]),
+            permits: Mutex::new(permits),
+            changed: Condvar::new(),
+        })
+    }
+
+    /// Let every read through from now on
+    fn open(&self) {
+        *self.permits.lock().unwrap() = usize::MAX;
+        self.changed.notify_all();
+    }
+
+    fn read(&self) -> Vec<PathBuf> {
+        self.read.lock().unwrap().clone()
+    }
+
+    /// Wait until every walk job, which each hold a reference, has finished
+    fn wait_until_idle(self: Arc<Self>) -> Vec<PathBuf> {
+        while Arc::strong_count(&self) > 1 {
+            std::thread::yield_now();
+        }
+        self.read()
+    }
+}
+
+impl FileSystem for CountingFileSystem {
+    fn read_dir(&self, path: &Path) -> io::Result<ReadDir<'_>> {
+        self.read.lock().unwrap().push(path.to_owned());
+        let mut permits = self
+            .changed
+            .wait_while(self.permits.lock().unwrap(), |permits| *permits == 0)
+            .unwrap();
+        *permits = permits.saturating_sub(1);
+        drop(permits);
+        self.inner.read_dir(path)
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        self.inner.metadata(path)
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<Metadata> {
+        self.inner.symlink_metadata(path)
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        self.inner.read_link(path)
+    }
+}
+
+fn glob_sorted(fs: Arc<MemoryFileSystem>, relative_to: &str, pattern: &str) -> Vec<PathBuf> {
+    let program = Arc::new(compile(&parse(pattern)).unwrap());
+    let mut paths = glob_in(fs, relative_to, program)
+        .collect::<anyhow::Result<Vec<_>>>()
+        .unwrap();
+    paths.sort();
+    paths
+}
+
+#[test]
+fn literal_file() {
+    let paths = glob_sorted(test_fs(), "/proj", "Cargo.toml");
+    assert_eq!(paths, [Path::new("Cargo.toml")]);
+}
+
+#[test]
+fn wildcard_in_dir() {
+    let paths = glob_sorted(test_fs(), "/proj", "src/*");
+    assert_eq!(paths, [Path::new("src/globber"), Path::new("src/main.rs")]);
+}
+
+#[test]
+fn recurse_finds_nested_files() {
+    let paths = glob_sorted(test_fs(), "/proj", "**/*.rs");
+    assert_eq!(
+        paths,
+        [Path::new("src/globber/tests.rs"), Path::new("src/main.rs")]
+    );
+}
+
+#[test]
+fn does_not_follow_symlinks() {
+    let paths = glob_sorted(test_fs(), "/proj", "link/*");
+    assert!(paths.is_empty());
+}
+
+#[test]
+fn absolute_pattern() {
+    let paths = glob_sorted(test_fs(), "/elsewhere", "/proj/*.md");
+    assert_eq!(paths, [Path::new("/proj/README.md")]);
+}
+
+#[test]
+fn missing_dir_is_an_error() {
+    let program = Arc::new(compile(&parse("*")).unwrap());
+    let results: Vec<_> = glob_in(test_fs(), "/nowhere", program).collect();
+    assert_eq!(results.len(), 1);
+    assert!(results[0].is_err());
+}
+
+#[test]
+fn limit_stops_early() {
+    let program = Arc::new(compile(&parse("**/*")).unwrap());
+    let options = GlobOptions {
+        limit: Some(2),
+        ..GlobOptions::default()
+    };
+    let results: Vec<_> = glob_with(test_fs(), "/proj", program, options).collect();
+    assert_eq!(results.len(), 2);
+}
+
+#[test]
+fn reaching_the_limit_cancels_the_walk() {
+    let fs = CountingFileSystem::new(1);
+    let program = Arc::new(compile(&parse("**/*")).unwrap());
+    let options = GlobOptions {
+        limit: Some(1),
+        ..GlobOptions::default()
+    };
+    let mut glob = glob_with(fs.clone(), "/proj", program, options);
+    assert!(glob.next().is_some_and(|result| result.is_ok()));
+
+    // Keep the glob alive, so only the limit can have stopped the walk
+    fs.open();
+    let read = fs.wait_until_idle();
+    assert!(!read.contains(&PathBuf::from("/proj/src/globber")));
+    assert!(!read.contains(&PathBuf::from("/proj/target/debug")));
+    drop(glob);
+}
+
+#[test]
+fn cancelled_before_start() {
+    let program = Arc::new(compile(&parse("**/*")).unwrap());
+    let options = GlobOptions::default();
+    options.cancel.cancel();
+    let results: Vec<_> = glob_with(test_fs(), "/proj", program, options).collect();
+    assert!(results.is_empty());
+}
+
+#[test]
+fn cancelled_before_start_does_not_read_sequentially() {
+    let fs = CountingFileSystem::new(usize::MAX);
+    let program = Arc::new(compile(&parse("**/*")).unwrap());
+    let options = GlobOptions {
+        parallelism: Parallelism::Sequential,
+        ..GlobOptions::default()
+    };
+    options.cancel.cancel();
+    let results: Vec<_> = glob_with(fs.clone(), "/proj", program, options).collect();
+    assert!(results.is_empty());
+    assert!(fs.read().is_empty());
+}
+
+#[test]
+fn dropping_glob_does_not_cancel_parent_token() {
+    let program = Arc::new(compile(&parse("**/*")).unwrap());
+    let fs = CountingFileSystem::new(1);
+    let options = GlobOptions::default();
+    let cancel = options.cancel.clone();
+    let mut glob = glob_with(fs.clone(), "/proj", program, options);
+    assert!(glob.next().is_some_and(|result| result.is_ok()));
+    drop(glob);
+    assert!(!cancel.is_cancelled());
+
+    // Only the directories that were already being read when it was dropped
+    fs.open();
+    let read = fs.wait_until_idle();
+    assert!(!read.contains(&PathBuf::from("/proj/src/globber")));
+    assert!(!read.contains(&PathBuf::from("/proj/target/debug")));
+}
+
+fn glob_sorted_with(parallelism: Parallelism, pattern: &str) -> Vec<PathBuf> {
+    let program = Arc::new(compile(&parse(pattern)).unwrap());
+    let options = GlobOptions {
+        parallelism,
+        ..GlobOptions::default()
+    };
+    let mut paths = glob_with(test_fs(), "/proj", program, options)
+        .collect::<anyhow::Result<Vec<_>>>()
+        .unwrap();
+    paths.sort();
+    paths
+}
+
+#[test]
+fn parallelism_modes_agree() {
+    let expected = glob_sorted(test_fs(), "/proj", "**/*");
+    assert_eq!(expected.len(), 9);
+    let pool = Arc::new(rayon::ThreadPoolBuilder::new().build().unwrap());
+    for parallelism in [
+        Parallelism::Threads(2),
+        Parallelism::Pool(pool),
+        Parallelism::Sequential,
+    ] {
+        assert_eq!(glob_sorted_with(parallelism, "**/*"), expected);
+    }
+}
+
+#[test]
+fn sequential_is_depth_first() {
+    let program = Arc::new(compile(&parse("src/**/*")).unwrap());
+    let options = GlobOptions {
+        parallelism: Parallelism::Sequential,
+        ..GlobOptions::default()
+    };
+    let paths = glob_with(test_fs(), "/proj", program, options)
+        .collect::<anyhow::Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(
+        paths,
+        [
+            Path::new("src/globber"),
+            Path::new("src/globber/tests.rs"),
+            Path::new("src/main.rs")
+        ]
+    );
+}
+
+#[test]
+fn sequential_reports_missing_dir() {
+    let program = Arc::new(compile(&parse("*")).unwrap());
+    let options = GlobOptions {
+        parallelism: Parallelism::Sequential,
+        ..GlobOptions::default()
+    };
+    let results: Vec<_> = glob_with(test_fs(), "/nowhere", program, options).collect();
+    assert_eq!(results.len(), 1);
+    assert!(results[0].is_err());
+}
+
+#[cfg(feature = "stream")]
+#[test]
+fn stream_agrees_with_iterator() {
+    use futures::{executor::block_on, StreamExt};
+
+    use crate::globber::glob_stream;
+
+    let expected = glob_sorted(test_fs(), "/proj", "**/*.rs");
+    for parallelism in [Parallelism::Global, Parallelism::Threads(2)] {
+        let program = Arc::new(compile(&parse("**/*.rs")).unwrap());
+        let options = GlobOptions {
+            parallelism,
+            ..GlobOptions::default()
+        };
+        let stream = glob_stream(test_fs(), "/proj", program, options);
+        let mut paths = block_on(stream.map(Result::unwrap).collect::<Vec<_>>());
+        paths.sort();
+        assert_eq!(paths, expected);
+    }
+}
+
+#[cfg(feature = "stream")]
+#[test]
+fn stream_respects_limit() {
+    use futures::{executor::block_on, StreamExt};
+
+    use crate::globber::glob_stream;
+
+    let program = Arc::new(compile(&parse("**/*")).unwrap());
+    let options = GlobOptions {
+        limit: Some(3),
+        ..GlobOptions::default()
+    };
+    let stream = glob_stream(test_fs(), "/proj", program, options);
+    assert_eq!(block_on(stream.count()), 3);
+}
+
+#[cfg(feature = "stream")]
+#[test]
+fn stream_rejects_sequential() {
+    use futures::{executor::block_on, StreamExt};
+
+    use crate::globber::glob_stream;
+
+    let program = Arc::new(compile(&parse("**/*")).unwrap());
+    let options = GlobOptions {
+        parallelism: Parallelism::Sequential,
+        ..GlobOptions::default()
+    };
+    let results = block_on(glob_stream(test_fs(), "/proj", program, options).collect::<Vec<_>>());
+    assert_eq!(results.len(), 1);
+    assert!(results[0].is_err());
+}
+
+#[cfg(feature = "stream")]
+#[test]
+fn stream_buffers_at_most_the_channel_capacity() {
+    use std::time::Duration;
+
+    use crate::globber::{glob_stream, CHANNEL_CAPACITY};
+
+    // More directories than fit in the channel, each sending one file
+    let mut fs = MemoryFileSystem::new();
+    for dir in 0..(CHANNEL_CAPACITY + 500) {
+        fs.add_file(format!("/many/{dir}/file"), 0);
+    }
+    let fs = CountingFileSystem::over(fs, usize::MAX);
+
+    let program = Arc::new(compile(&parse("*/*")).unwrap());
+    let stream = glob_stream(fs.clone(), "/many", program, GlobOptions::default());
+
+    // Without polling the stream, the walk should stop once the channel is full
+    let mut read = 0;
+    loop {
+        std::thread::sleep(Duration::from_millis(50));
+        let now = fs.read().len();
+        if now == read {
+            break;
+        }
+        read = now;
+    }
+    // Every thread can have read one more directory that it's waiting to send a file from, and
+    // `/many` itself is read without sending anything
+    assert!(read <= CHANNEL_CAPACITY + rayon::current_num_threads() + 1);
+    drop(stream);
+}
+
+#[test]
+fn many_patterns_tag_matches() {
+    let programs: Vec<_> = ["src/**/*.rs", "**/tests.rs", "Cargo.toml", "/proj/*.md"]
+        .iter()
+        .map(|pattern| compile(&parse(pattern)).unwrap())
+        .collect();
+    for parallelism in [Parallelism::Global, Parallelism::Sequential] {
+        let options = GlobOptions {
+            parallelism,
+            ..GlobOptions::default()
+        };
+        let mut matches = glob_many(test_fs(), "/proj", &programs, options)
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        matches.sort_by(|a, b| a.path.cmp(&b.path));
+        let expected = [
+            ("/proj/README.md", vec![3]),
+            ("Cargo.toml", vec![2]),
+            ("src/globber/tests.rs", vec![0, 1]),
+            ("src/main.rs", vec![0]),
+        ];
+        assert_eq!(matches.len(), expected.len());
+        for (glob_match, (path, patterns)) in matches.iter().zip(expected) {
+            assert_eq!(glob_match.path, Path::new(path));
+            assert_eq!(glob_match.patterns, patterns);
+        }
+    }
+}
+
+#[test]
+fn skip_hidden_leaves_out_dot_entries() {
+    let mut fs = MemoryFileSystem::new();
+    fs.add_file("/proj/.git/config", 10)
+        .add_file("/proj/.env", 10)
+        .add_file("/proj/main.rs", 10);
+    let program = Arc::new(compile(&parse("**/*")).unwrap());
+    for parallelism in [Parallelism::Global, Parallelism::Sequential] {
+        let options = GlobOptions {
+            parallelism,
+            skip_hidden: true,
+            ..GlobOptions::default()
+        };
+        let paths = glob_with(Arc::new(fs.clone()), "/proj", program.clone(), options)
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(paths, [Path::new("main.rs")]);
+    }
+}
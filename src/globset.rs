@@ -0,0 +1,157 @@
+//! Matches a path against many programs in a single pass
+
+use std::{
+    borrow::Borrow,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    compiler::{Instruction, Program, ProgramOffset},
+    matcher::{self, MatchRecorder},
+};
+
+#[cfg(test)]
+mod tests;
+
+/// Which patterns in a [`GlobSet`] matched a path
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SetMatchResult {
+    /// Indices of the patterns that could match with more path components
+    pub prefix: Vec<usize>,
+    /// Indices of the patterns that completely match
+    pub complete: Vec<usize>,
+}
+
+impl SetMatchResult {
+    pub fn is_match(&self) -> bool {
+        !self.complete.is_empty()
+    }
+}
+
+/// Many programs combined into one, which tries all of them as alternatives
+#[derive(Debug, Clone)]
+pub struct GlobSet {
+    program: Program,
+    /// The offset of the first instruction of each pattern within `program`
+    starts: Vec<ProgramOffset>,
+    absolute_prefixes: Vec<Option<PathBuf>>,
+}
+
+impl GlobSet {
+    pub fn new(
+        programs: impl IntoIterator<Item = impl Borrow<Program>>,
+    ) -> anyhow::Result<GlobSet> {
+        let programs: Vec<_> = programs.into_iter().collect();
+
+        // Start with an alternative for every pattern but the first, which just follows on
+        let mut program = Program::default();
+        for _ in 1..programs.len() {
+            program
+                .instructions
+                .push(Instruction::Alternative(ProgramOffset::PLACEHOLDER));
+        }
+
+        let mut starts = Vec::with_capacity(programs.len());
+        let mut absolute_prefixes = Vec::with_capacity(programs.len());
+        for (index, sub_program) in programs.iter().map(Borrow::borrow).enumerate() {
+            let start = ProgramOffset(program.instructions.len());
+            if index > 0 {
+                program.instructions[index - 1] = Instruction::Alternative(start);
+            }
+            starts.push(start);
+            absolute_prefixes.push(sub_program.absolute_prefix.clone());
+
+            // Check that the shifted counter ids will fit before shifting any of them
+            let counters = program
+                .counters
+                .checked_add(sub_program.counters)
+                .ok_or_else(|| {
+                    anyhow::anyhow!("Exceeded the number of repeats allowed in a glob set")
+                })?;
+            program.instructions.extend(
+                sub_program
+                    .instructions
+                    .iter()
+                    .map(|instruction| instruction.relocate(start.0, program.counters)),
+            );
+            program.counters = counters;
+        }
+
+        Ok(GlobSet {
+            program,
+            starts,
+            absolute_prefixes,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.starts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.starts.is_empty()
+    }
+
+    /// The combined program
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    pub fn absolute_prefix(&self, index: usize) -> Option<&Path> {
+        self.absolute_prefixes[index].as_deref()
+    }
+
+    pub fn matches(&self, path: &Path) -> SetMatchResult {
+        if self.is_empty() {
+            return SetMatchResult::default();
+        }
+
+        let recorder = SetRecorder {
+            starts: &self.starts,
+            prefix: vec![false; self.len()],
+            complete: vec![false; self.len()],
+        };
+        let recorder = matcher::run(path, &self.program, recorder);
+
+        let indices = |flags: Vec<bool>| {
+            flags
+                .into_iter()
+                .enumerate()
+                .filter_map(|(index, flag)| flag.then_some(index))
+                .collect()
+        };
+        SetMatchResult {
+            prefix: indices(recorder.prefix),
+            complete: indices(recorder.complete),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct SetRecorder<'a> {
+    starts: &'a [ProgramOffset],
+    prefix: Vec<bool>,
+    complete: Vec<bool>,
+}
+
+impl SetRecorder<'_> {
+    fn pattern_index(&self, pc: ProgramOffset) -> usize {
+        self.starts.partition_point(|start| start.0 <= pc.0) - 1
+    }
+}
+
+impl MatchRecorder for SetRecorder<'_> {
+    fn prefix(&mut self, pc: ProgramOffset) {
+        let index = self.pattern_index(pc);
+        self.prefix[index] = true;
+    }
+
+    fn complete(&mut self, pc: ProgramOffset) {
+        let index = self.pattern_index(pc);
+        self.complete[index] = true;
+    }
+
+    fn is_done(&self) -> bool {
+        self.prefix.iter().chain(&self.complete).all(|flag| *flag)
+    }
+}
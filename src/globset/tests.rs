@@ -0,0 +1,65 @@
+use std::path::Path;
+
+use crate::{
+    compiler::{compile, CounterId, Instruction, Program},
+    globset::GlobSet,
+    parser::parse,
+};
+
+fn glob_set(patterns: &[&str]) -> GlobSet {
+    let programs: Vec<_> = patterns
+        .iter()
+        .map(|pattern| compile(&parse(pattern)).unwrap())
+        .collect();
+    GlobSet::new(&programs).unwrap()
+}
+
+#[test]
+fn empty_set_matches_nothing() {
+    let set = glob_set(&[]);
+    let result = set.matches(Path::new("foo"));
+    assert!(result.complete.is_empty());
+    assert!(result.prefix.is_empty());
+}
+
+#[test]
+fn reports_every_complete_match() {
+    let set = glob_set(&["src/**/*.rs", "*.toml", "src/*.rs", "tests/**/*"]);
+    assert_eq!(set.matches(Path::new("src/main.rs")).complete, [0, 2]);
+    assert_eq!(set.matches(Path::new("Cargo.toml")).complete, [1]);
+    assert!(!set.matches(Path::new("README.md")).is_match());
+}
+
+#[test]
+fn reports_prefixes_per_pattern() {
+    let set = glob_set(&["src/**/*.rs", "*.toml", "src/*.rs", "tests/**/*"]);
+    let result = set.matches(Path::new("src"));
+    assert_eq!(result.prefix, [0, 2]);
+    assert!(result.complete.is_empty());
+    let result = set.matches(Path::new("tests/unit"));
+    assert_eq!(result.prefix, [3]);
+    assert_eq!(result.complete, [3]);
+}
+
+#[test]
+fn counters_are_separate() {
+    let set = glob_set(&["<a:2>", "<b:1,3>"]);
+    assert_eq!(set.program().counters, 2);
+    assert_eq!(set.matches(Path::new("aa")).complete, [0]);
+    assert_eq!(set.matches(Path::new("bbb")).complete, [1]);
+    assert!(!set.matches(Path::new("bbbb")).is_match());
+}
+
+#[test]
+fn too_many_counters_is_an_error() {
+    let program = Program {
+        instructions: vec![
+            Instruction::Increment(CounterId(39_999)),
+            Instruction::Complete,
+        ],
+        counters: 40_000,
+        ..Program::default()
+    };
+    let err = GlobSet::new([&program, &program]).unwrap_err();
+    assert!(err.to_string().contains("number of repeats"));
+}
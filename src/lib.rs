@@ -0,0 +1,21 @@
+pub mod analysis;
+pub mod archive;
+pub mod assembler;
+pub mod compiler;
+pub mod dot;
+pub mod encoding;
+pub mod expand;
+pub mod explain;
+pub mod filesystem;
+pub mod generate;
+pub mod globber;
+pub mod globset;
+pub mod lint;
+#[cfg(feature = "serde")]
+pub mod lossless;
+pub mod matcher;
+pub mod optimizer;
+pub mod parser;
+pub mod prefilter;
+pub mod regex;
+pub mod verifier;
@@ -0,0 +1,173 @@
+//! Warns about parts of a pattern that parse fine, but are almost certainly not what was meant
+
+use std::{ffi::OsStr, fmt::Write};
+
+use crate::parser::{AstNode, CharacterClass, Pattern, Span};
+
+#[cfg(test)]
+mod tests;
+
+/// A warning about part of a pattern
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lint {
+    /// The part of the pattern string that the warning is about
+    pub span: Span,
+    pub message: String,
+    /// What to replace the span with to fix it, if there is an obvious fix
+    pub replacement: Option<String>,
+}
+
+impl Lint {
+    /// The pattern string with the suggested replacement applied, if there is one
+    pub fn suggestion(&self, source: impl AsRef<OsStr>) -> Option<String> {
+        let source = source.as_ref().as_encoded_bytes();
+        let replacement = self.replacement.as_ref()?;
+        let mut out = String::from_utf8_lossy(&source[..self.span.start]).into_owned();
+        out.push_str(replacement);
+        out.push_str(&String::from_utf8_lossy(&source[self.span.end..]));
+        Some(out)
+    }
+}
+
+/// Check a pattern for likely mistakes, given the string it was parsed from
+pub fn lint(pattern: &Pattern, source: impl AsRef<OsStr>) -> Vec<Lint> {
+    let mut linter = Linter {
+        source: source.as_ref().as_encoded_bytes(),
+        lints: vec![],
+    };
+    linter.lint_nodes(pattern, true, true);
+    linter.lints
+}
+
+struct Linter<'a> {
+    source: &'a [u8],
+    lints: Vec<Lint>,
+}
+
+impl Linter<'_> {
+    /// Check some nodes. `starts_component` and `ends_component` say whether the nodes are at
+    /// the edges of a path component, which matters for nodes at the edges of a nested pattern.
+    fn lint_nodes(&mut self, pattern: &Pattern, starts_component: bool, ends_component: bool) {
+        let nodes = &pattern.nodes;
+        let span = |index: usize| pattern.spans.get(index).cloned().unwrap_or_default();
+        let mut index = 0;
+        while index < nodes.len() {
+            let before = match index.checked_sub(1) {
+                Some(previous) => matches!(
+                    nodes[previous],
+                    AstNode::Separator | AstNode::RootDir | AstNode::Prefix(_)
+                ),
+                None => starts_component,
+            };
+            let after = match nodes.get(index + 1) {
+                Some(next) => matches!(next, AstNode::Separator),
+                None => ends_component,
+            };
+
+            match &nodes[index] {
+                AstNode::Recurse if before && after => {
+                    // Look for more `**` components straight after this one
+                    let mut end = index;
+                    while let (Some(AstNode::Separator), Some(AstNode::Recurse)) =
+                        (nodes.get(end + 1), nodes.get(end + 2))
+                    {
+                        let ends = nodes
+                            .get(end + 3)
+                            .map_or(ends_component, |next| matches!(next, AstNode::Separator));
+                        if !ends {
+                            break;
+                        }
+                        end += 2;
+                    }
+                    if end > index {
+                        self.lints.push(Lint {
+                            span: span(index).start..span(end).end,
+                            message: "`**` already matches any number of directories, so \
+                                      repeating it does nothing"
+                                .into(),
+                            replacement: Some("**".into()),
+                        });
+                        index = end;
+                    }
+                }
+                AstNode::Recurse => self.lints.push(Lint {
+                    span: span(index),
+                    message: "`**` is only special as a whole path component, here it matches \
+                              the rest of the component and then whole directories"
+                        .into(),
+                    replacement: Some("*".into()),
+                }),
+                AstNode::Characters(classes) if classes.iter().any(is_reversed) => {
+                    self.lints.push(Lint {
+                        span: span(index),
+                        message: "a range is backwards, so it doesn't match anything".into(),
+                        replacement: Some(render_classes(classes)),
+                    });
+                }
+                AstNode::Alternatives { choices } => {
+                    if let [choice] = &choices[..] {
+                        self.lints.push(Lint {
+                            span: span(index),
+                            message: "there is only one choice, so the braces do nothing".into(),
+                            replacement: Some(self.text(choice)),
+                        });
+                    }
+                    for choice in choices {
+                        self.lint_nodes(choice, before, after);
+                    }
+                }
+                AstNode::Repeat { min, max, pattern } => {
+                    if *max == 0 && *min <= 1 {
+                        self.lints.push(Lint {
+                            span: span(index),
+                            message: "the pattern matches once despite the count of 0, since \
+                                      a repeat always matches at least once"
+                                .into(),
+                            replacement: Some(self.text(pattern)),
+                        });
+                    } else if min > max {
+                        self.lints.push(Lint {
+                            span: span(index),
+                            message: "the minimum is greater than the maximum, so the \
+                                      pattern repeats exactly the minimum number of times"
+                                .into(),
+                            replacement: Some(format!("<{}:{}>", self.text(pattern), min)),
+                        });
+                    }
+                    self.lint_nodes(pattern, before, after);
+                }
+                _ => (),
+            }
+            index += 1;
+        }
+    }
+
+    /// The part of the source string that a nested pattern was parsed from
+    fn text(&self, pattern: &Pattern) -> String {
+        match (pattern.spans.first(), pattern.spans.last()) {
+            (Some(first), Some(last)) => {
+                String::from_utf8_lossy(&self.source[first.start..last.end]).into_owned()
+            }
+            _ => String::new(),
+        }
+    }
+}
+
+fn is_reversed(class: &CharacterClass) -> bool {
+    matches!(class, CharacterClass::Range(start, end) if start > end)
+}
+
+/// Write character classes back out as a pattern, with any backwards ranges turned around
+fn render_classes(classes: &[CharacterClass]) -> String {
+    let mut out = String::from("[");
+    for class in classes {
+        match *class {
+            CharacterClass::Single(ch) => out.push(ch),
+            CharacterClass::Range(start, end) => {
+                let _ = write!(out, "{}-{}", start.min(end), start.max(end));
+            }
+        }
+    }
+    out.push(']');
+    out
+}
@@ -0,0 +1,69 @@
+use crate::{lint::lint, parser::parse};
+
+/// The span, and the pattern with the fix applied, of each warning
+fn lint_strings(pattern: &str) -> Vec<(std::ops::Range<usize>, Option<String>)> {
+    lint(&parse(pattern), pattern)
+        .into_iter()
+        .map(|lint| (lint.span.clone(), lint.suggestion(pattern)))
+        .collect()
+}
+
+#[test]
+fn clean_patterns_have_no_warnings() {
+    for pattern in [
+        "src/**/*.rs",
+        "**",
+        "/**/x",
+        "{a,b}/<[0-9]:1,3>.txt",
+        "[a-z0-9_]*",
+        "**/a/**",
+    ] {
+        assert_eq!(lint_strings(pattern), [], "{}", pattern);
+    }
+}
+
+#[test]
+fn recurse_inside_a_component() {
+    assert_eq!(lint_strings("a**b"), [(1..3, Some("a*b".into()))]);
+    assert_eq!(lint_strings("src/**.rs"), [(4..6, Some("src/*.rs".into()))]);
+    assert_eq!(lint_strings("x{a**,b}"), [(3..5, Some("x{a*,b}".into()))]);
+}
+
+#[test]
+fn repeated_recurse() {
+    assert_eq!(lint_strings("**/**"), [(0..5, Some("**".into()))]);
+    assert_eq!(
+        lint_strings("src/**/**/**/x"),
+        [(4..12, Some("src/**/x".into()))]
+    );
+}
+
+#[test]
+fn single_choice_alternatives() {
+    assert_eq!(lint_strings("{a}.rs"), [(0..3, Some("a.rs".into()))]);
+    assert_eq!(lint_strings("x{}y"), [(1..3, Some("xy".into()))]);
+}
+
+#[test]
+fn empty_and_backwards_repeats() {
+    // The body still matches once, so the suggestion keeps it
+    assert_eq!(lint_strings("a<x:0,0>b"), [(1..8, Some("axb".into()))]);
+    assert_eq!(lint_strings("<x:0>"), [(0..5, Some("x".into()))]);
+    // A repeat never stops before its minimum, so `<ab:3,1>` matches `ababab` and nothing else
+    assert_eq!(lint_strings("<ab:3,1>"), [(0..8, Some("<ab:3>".into()))]);
+    assert_eq!(lint_strings("<x:3,0>"), [(0..7, Some("<x:3>".into()))]);
+}
+
+#[test]
+fn backwards_ranges() {
+    assert_eq!(lint_strings("[z-a]"), [(0..5, Some("[a-z]".into()))]);
+    assert_eq!(lint_strings("x[_9-0]"), [(1..7, Some("x[_0-9]".into()))]);
+}
+
+#[test]
+fn nested_warnings_are_all_reported() {
+    assert_eq!(
+        lint_strings("{[z-a]}"),
+        [(0..7, Some("[z-a]".into())), (1..6, Some("{[a-z]}".into()))]
+    );
+}
@@ -0,0 +1,81 @@
+//! Lossless encoding of byte strings that are usually, but not always, valid UTF-8
+//!
+//! Valid UTF-8 is encoded as a plain string, and anything else as `{"bytes": [...]}`.
+
+use std::path::PathBuf;
+
+use serde::{ser::SerializeMap, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::filesystem::path_from_bytes;
+
+/// Bytes to be encoded losslessly
+#[derive(Debug, Clone, Copy)]
+pub struct LosslessBytes<'a>(pub &'a [u8]);
+
+impl Serialize for LosslessBytes<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match std::str::from_utf8(self.0) {
+            Ok(string) => serializer.serialize_str(string),
+            Err(_) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("bytes", self.0)?;
+                map.end()
+            }
+        }
+    }
+}
+
+/// Either form that [`LosslessBytes`] is encoded as
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LosslessBuf {
+    String(String),
+    Bytes { bytes: Vec<u8> },
+}
+
+impl From<LosslessBuf> for Vec<u8> {
+    fn from(buf: LosslessBuf) -> Vec<u8> {
+        match buf {
+            LosslessBuf::String(string) => string.into_bytes(),
+            LosslessBuf::Bytes { bytes } => bytes,
+        }
+    }
+}
+
+/// For `#[serde(serialize_with)]` on anything that is bytes
+pub fn serialize<T, S>(bytes: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: AsRef<[u8]> + ?Sized,
+    S: Serializer,
+{
+    LosslessBytes(bytes.as_ref()).serialize(serializer)
+}
+
+/// For `#[serde(serialize_with)]` on an optional path
+pub fn serialize_optional_path<S: Serializer>(
+    path: &Option<PathBuf>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    path.as_ref()
+        .map(|path| LosslessBytes(path.as_os_str().as_encoded_bytes()))
+        .serialize(serializer)
+}
+
+/// For `#[serde(deserialize_with)]` on anything that can be made from bytes
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+where
+    T: From<Vec<u8>>,
+    D: Deserializer<'de>,
+{
+    Ok(Vec::from(LosslessBuf::deserialize(deserializer)?).into())
+}
+
+/// For `#[serde(deserialize_with)]` on an optional path
+pub fn deserialize_optional_path<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<PathBuf>, D::Error> {
+    Ok(
+        Option::<LosslessBuf>::deserialize(deserializer)?
+            .map(|buf| path_from_bytes(Vec::from(buf))),
+    )
+}
@@ -1,14 +1,36 @@
-use std::{io::Write, path::PathBuf, sync::Arc};
+use std::{
+    ffi::{OsStr, OsString},
+    io::stdout,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
 use anyhow::{anyhow, bail};
+use rayon::prelude::*;
 
-mod compiler;
-mod globber;
-mod matcher;
-mod parser;
+mod cli;
+
+use cli::output::{write_value, Format, MatchWriter};
+
+use glob_experiment::{
+    analysis, archive, compiler, dot, explain, filesystem::StdFileSystem, generate, globber, lint,
+    matcher, parser, regex,
+};
 
 fn main() -> anyhow::Result<()> {
-    const USAGE: &str = "Usage: glob_experiment <pattern> <parse|compile|matches|glob> [path]";
+    const USAGE: &str =
+        "Usage: glob_experiment <pattern> <parse|compile|matches|explain|repl|overlap|lint|regex|from-regex|generate|glob|filter|exec> [path] \
+                         [--format <lines|null|json|jsonl|dot>] [--optimize] \
+                         [--flavor <rust|pcre|posix>] \
+                         [pattern...] \
+                         [--archive <file>] [--limit <n>] [--threads <n>] [--sequential] \
+                         [-n <count>] [--seed <n>] [--length <min>..<max>] [--depth <min>..<max>] \
+                         [--non-matching] \
+                         [-z|--null] [--invert] [--stats] \
+                         [-j|--jobs <n>] [--batch] [--] [command...]";
 
     env_logger::init();
 
@@ -18,13 +40,33 @@ fn main() -> anyhow::Result<()> {
 
     match args.next().map(|s| s.into_encoded_bytes()).as_deref() {
         Some(b"parse") => {
+            let format = parse_format_option(args)?;
             let pattern = parser::parse(pattern_string);
-            println!("{:#?}", pattern);
+            write_value(format, &pattern, || format!("{:#?}\n", pattern), stdout())?;
         }
         Some(b"compile") => {
-            let pattern = parser::parse(pattern_string);
-            let program = compiler::compile(&pattern)?;
-            print!("{}", program);
+            let mut options = compiler::CompileOptions::default();
+            let mut dot = false;
+            let mut rest = vec![];
+            let mut args = args.peekable();
+            while let Some(arg) = args.next() {
+                if arg == "--optimize" {
+                    options.optimize = true;
+                } else if arg == "--format" && args.peek().is_some_and(|format| format == "dot") {
+                    args.next();
+                    dot = true;
+                } else {
+                    rest.push(arg);
+                }
+            }
+            let format = parse_format_option(rest.into_iter())?;
+            let pattern = parser::parse(&pattern_string);
+            let program = compiler::compile_with(&pattern, &options)?;
+            if dot {
+                print!("{}", dot::render(&program, &pattern_string));
+            } else {
+                write_value(format, &program, || program.to_string(), stdout())?;
+            }
         }
         Some(b"matches") => {
             let path: PathBuf = args.next().ok_or_else(|| anyhow!(USAGE))?.into();
@@ -33,30 +75,315 @@ fn main() -> anyhow::Result<()> {
             let result = matcher::path_matches(&path, &program);
             print!("{:?}", result);
         }
-        Some(b"glob") => {
-            let pattern = parser::parse(pattern_string);
-            let program = Arc::new(compiler::compile(&pattern)?);
+        Some(b"explain") => {
+            let path: PathBuf = args.next().ok_or_else(|| anyhow!(USAGE))?.into();
+            let pattern = parser::parse(&pattern_string);
+            let program = compiler::compile(&pattern)?;
+            print!("{}", explain::explain(&pattern_string, &path, &program));
+        }
+        Some(b"repl") => {
             let current_dir = std::env::current_dir()?;
-            let mut stdout = std::io::stdout();
+            let mut repl = cli::repl::Repl::new(Arc::new(StdFileSystem), current_dir);
+            if !pattern_string.is_empty() {
+                repl.handle_line(&pattern_string.to_string_lossy(), &mut stdout())?;
+            }
+            repl.run(std::io::stdin().lock(), stdout())?;
+        }
+        Some(b"overlap") => {
+            let pattern_strings: Vec<_> = std::iter::once(pattern_string).chain(args).collect();
+            let programs = pattern_strings
+                .iter()
+                .map(|pattern_string| compiler::compile(&parser::parse(pattern_string)))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let pairs: Vec<_> = (0..programs.len())
+                .flat_map(|a| ((a + 1)..programs.len()).map(move |b| (a, b)))
+                .collect();
+            let overlaps: Vec<_> = pairs
+                .par_iter()
+                .filter_map(|(a, b)| {
+                    analysis::overlap_example(&programs[*a], &programs[*b])
+                        .map(|example| (*a, *b, example))
+                })
+                .collect();
+            for (a, b, example) in &overlaps {
+                println!(
+                    "{} and {} both match {}",
+                    pattern_strings[*a].to_string_lossy(),
+                    pattern_strings[*b].to_string_lossy(),
+                    example.display()
+                );
+            }
+            if !overlaps.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        Some(b"lint") => {
+            let mut warned = false;
+            for pattern_string in std::iter::once(pattern_string).chain(args) {
+                let pattern = parser::parse(&pattern_string);
+                for warning in lint::lint(&pattern, &pattern_string) {
+                    warned = true;
+                    println!(
+                        "{}: bytes {}..{}: {}",
+                        pattern_string.to_string_lossy(),
+                        warning.span.start,
+                        warning.span.end,
+                        warning.message
+                    );
+                    if let Some(suggestion) = warning.suggestion(&pattern_string) {
+                        println!("  try: {}", suggestion);
+                    }
+                }
+            }
+            if warned {
+                std::process::exit(1);
+            }
+        }
+        Some(b"regex") => {
+            let mut flavor = regex::Flavor::default();
+            while let Some(arg) = args.next() {
+                match arg.as_encoded_bytes() {
+                    b"--flavor" => {
+                        let flavor_arg = args.next().ok_or_else(|| anyhow!(USAGE))?;
+                        flavor = flavor_arg.to_string_lossy().parse()?;
+                    }
+                    _ => bail!(USAGE),
+                }
+            }
+            let pattern = parser::parse(&pattern_string);
+            match regex::to_regex(&pattern, flavor) {
+                Ok(regex) => println!("{}", regex),
+                Err(unsupported) => {
+                    for part in unsupported {
+                        eprintln!(
+                            "{}: bytes {}..{}: {}",
+                            pattern_string.to_string_lossy(),
+                            part.span.start,
+                            part.span.end,
+                            part.message
+                        );
+                    }
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some(b"from-regex") => {
+            let regex = pattern_string
+                .to_str()
+                .ok_or_else(|| anyhow!("the regex has to be valid UTF-8"))?;
+            match regex::from_regex(regex) {
+                Ok(pattern) => println!("{}", pattern),
+                Err(unsupported) => {
+                    for part in unsupported {
+                        eprintln!(
+                            "{}: bytes {}..{}: {}",
+                            regex, part.span.start, part.span.end, part.message
+                        );
+                    }
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some(b"generate") => {
+            let mut count = 10;
+            let mut non_matching = false;
+            let mut options = generate::GenerateOptions::default();
+            while let Some(arg) = args.next() {
+                let mut value = || {
+                    args.next()
+                        .map(|value| value.to_string_lossy().into_owned())
+                        .ok_or_else(|| anyhow!(USAGE))
+                };
+                match arg.as_encoded_bytes() {
+                    b"-n" => count = value()?.parse()?,
+                    b"--seed" => options.seed = Some(value()?.parse()?),
+                    b"--length" => options.component_length = parse_range(&value()?)?,
+                    b"--depth" => options.recurse_depth = parse_range(&value()?)?,
+                    b"--non-matching" => non_matching = true,
+                    _ => bail!(USAGE),
+                }
+            }
+            let pattern = parser::parse(pattern_string);
+            let program = compiler::compile(&pattern)?;
+            let mut generator = generate::Generator::new(&program, options);
+            for _ in 0..count {
+                let path = if non_matching {
+                    generator.non_matching()
+                } else {
+                    generator.matching()
+                };
+                let path = path.ok_or_else(|| anyhow!("Couldn't generate a path"))?;
+                println!("{}", path.display());
+            }
+        }
+        Some(b"glob") => {
+            let mut pattern_strings = vec![pattern_string];
+            let mut archive_path = None;
+            let mut options = globber::GlobOptions::default();
+            let mut format = Format::default();
+            while let Some(arg) = args.next() {
+                match arg.as_encoded_bytes() {
+                    b"--format" => {
+                        let format_arg = args.next().ok_or_else(|| anyhow!(USAGE))?;
+                        format = format_arg.to_string_lossy().parse()?;
+                    }
+                    b"--archive" => {
+                        archive_path = Some(args.next().ok_or_else(|| anyhow!(USAGE))?);
+                    }
+                    b"--limit" => {
+                        let limit = args.next().ok_or_else(|| anyhow!(USAGE))?;
+                        options.limit = Some(limit.to_string_lossy().parse()?);
+                    }
+                    b"--threads" => {
+                        let threads = args.next().ok_or_else(|| anyhow!(USAGE))?;
+                        options.parallelism =
+                            globber::Parallelism::Threads(threads.to_string_lossy().parse()?);
+                    }
+                    b"--sequential" => {
+                        options.parallelism = globber::Parallelism::Sequential;
+                    }
+                    [b'-', b'-', ..] => bail!(USAGE),
+                    _ => pattern_strings.push(arg),
+                }
+            }
+            let programs = pattern_strings
+                .iter()
+                .map(compile_optimized)
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let results = if let Some(archive_path) = archive_path {
+                let fs = Arc::new(archive::index(archive_path)?);
+                globber::glob_many(fs, "", &programs, options)
+            } else {
+                let current_dir = std::env::current_dir()?;
+                globber::glob_many(Arc::new(StdFileSystem), current_dir, &programs, options)
+            };
+            let mut writer = MatchWriter::new(format, stdout().lock());
             let mut failed = false;
-            for result in globber::glob(current_dir, program) {
+            for result in results {
                 match result {
-                    Ok(path) => {
-                        stdout.write_all(path.as_os_str().as_encoded_bytes())?;
-                        stdout.write_all(b"\n")?;
-                    }
+                    Ok(glob_match) => writer.write(&glob_match)?,
                     Err(err) => {
                         eprintln!("{}", err);
                         failed = true;
                     }
                 }
             }
+            writer.finish()?;
             if failed {
                 std::process::exit(1);
             }
         }
+        Some(b"filter") => {
+            let program = compile_optimized(pattern_string)?;
+            let mut options = cli::filter::FilterOptions::default();
+            let mut show_stats = false;
+            for arg in args {
+                match arg.as_encoded_bytes() {
+                    b"-z" | b"--null" => options.null = true,
+                    b"--invert" => options.invert = true,
+                    b"--stats" => show_stats = true,
+                    _ => bail!(USAGE),
+                }
+            }
+            let stats = cli::filter::filter(
+                &program,
+                options,
+                std::io::stdin().lock(),
+                std::io::stdout(),
+            )?;
+            if show_stats {
+                eprintln!("{}", stats);
+            }
+            if stats.written == 0 {
+                std::process::exit(1);
+            }
+        }
+        Some(b"exec") => {
+            let mut jobs = std::thread::available_parallelism().map_or(1, |jobs| jobs.get());
+            let mut batch = false;
+            let mut command = vec![];
+            while let Some(arg) = args.next() {
+                match arg.as_encoded_bytes() {
+                    b"-j" | b"--jobs" => {
+                        let jobs_arg = args.next().ok_or_else(|| anyhow!(USAGE))?;
+                        jobs = jobs_arg.to_string_lossy().parse()?;
+                    }
+                    b"--batch" => batch = true,
+                    b"--" => {
+                        command.extend(args.by_ref());
+                    }
+                    _ => {
+                        command.push(arg);
+                        command.extend(args.by_ref());
+                    }
+                }
+            }
+            let template = cli::exec::CommandTemplate::parse(command)?;
+            if batch {
+                // Before walking, so a bad template doesn't wait for the whole walk
+                template.check_batch()?;
+            }
+            let program = Arc::new(compile_optimized(pattern_string)?);
+            let current_dir = std::env::current_dir()?;
+            let failed = AtomicBool::new(false);
+            let paths = globber::glob(current_dir, program).filter_map(|result| match result {
+                Ok(path) => Some(path),
+                Err(err) => {
+                    eprintln!("{}", err);
+                    failed.store(true, Ordering::Relaxed);
+                    None
+                }
+            });
+            let failure = if batch {
+                cli::exec::exec_batch(&template, paths)?
+            } else {
+                cli::exec::exec_each(&template, paths, jobs)
+            };
+            if let Some(code) = failure {
+                std::process::exit(code);
+            }
+            if failed.load(Ordering::Relaxed) {
+                std::process::exit(1);
+            }
+        }
         _ => bail!(USAGE),
     }
 
     Ok(())
 }
+
+/// Parse the arguments of a subcommand that only takes `--format`
+fn parse_format_option(mut args: impl Iterator<Item = OsString>) -> anyhow::Result<Format> {
+    let mut format = Format::default();
+    while let Some(arg) = args.next() {
+        match arg.as_encoded_bytes() {
+            b"--format" => {
+                let format_arg = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--format needs a value"))?;
+                format = format_arg.to_string_lossy().parse()?;
+            }
+            _ => bail!("unexpected argument {:?}", arg),
+        }
+    }
+    Ok(format)
+}
+
+/// Compile a pattern to match paths with, rather than to look at
+fn compile_optimized(pattern_string: impl AsRef<OsStr>) -> anyhow::Result<compiler::Program> {
+    let options = compiler::CompileOptions {
+        optimize: true,
+        ..compiler::CompileOptions::default()
+    };
+    compiler::compile_with(&parser::parse(pattern_string), &options)
+}
+
+/// Parse an inclusive range like `1..8`, or a single number
+fn parse_range(string: &str) -> anyhow::Result<std::ops::RangeInclusive<usize>> {
+    let (min, max) = string.split_once("..").unwrap_or((string, string));
+    let range = min.parse()?..=max.trim_start_matches('=').parse()?;
+    if range.is_empty() {
+        bail!("empty range {}", string);
+    }
+    Ok(range)
+}
@@ -5,8 +5,8 @@ use std::{
 
 use crate::compiler::{Instruction, Program, ProgramOffset};
 
-// #[cfg(test)]
-// mod tests;
+#[cfg(test)]
+mod tests;
 
 #[derive(Debug, Clone, Copy)]
 pub struct MatchResult {
@@ -59,6 +59,13 @@ fn next_string<'a, 'b>(
     }
 }
 
+fn first_char(string: &[u8]) -> Option<char> {
+    string
+        .utf8_chunks()
+        .next()
+        .and_then(|chunk| chunk.valid().chars().next())
+}
+
 fn length_of_first_char(string: &[u8]) -> Option<usize> {
     string.utf8_chunks().next().map(|chunk| {
         chunk
@@ -70,9 +77,37 @@ fn length_of_first_char(string: &[u8]) -> Option<usize> {
     })
 }
 
+fn lossy(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+/// The rest of the path that hasn't been consumed yet
+fn remaining_path(current_string: Option<&[u8]>, path_components: Peekable<Components>) -> String {
+    let mut remaining = current_string.map(lossy).unwrap_or_default();
+    for component in path_components {
+        if !remaining.is_empty() && !remaining.ends_with(std::path::is_separator) {
+            remaining.push(std::path::MAIN_SEPARATOR);
+        }
+        remaining.push_str(&component.as_os_str().to_string_lossy());
+    }
+    remaining
+}
+
+fn describe_component(component: Component) -> String {
+    match component {
+        Component::Prefix(prefix) => format!("prefix {:?}", prefix.as_os_str()),
+        Component::RootDir => "the root directory".into(),
+        Component::CurDir => "\".\"".into(),
+        Component::ParentDir => "\"..\"".into(),
+        Component::Normal(string) => format!("{:?}", string),
+    }
+}
+
 #[derive(Debug, Clone)]
 struct ProgramState<'a> {
     pc: ProgramOffset,
+    /// Identifies the state, and any it was cloned from, for tracing
+    thread: usize,
     path_components: Peekable<Components<'a>>,
     current_string: Option<&'a [u8]>,
     fresh_string: bool,
@@ -83,6 +118,7 @@ impl<'a> ProgramState<'a> {
     fn new(path_components: Components<'a>, num_counters: u16) -> ProgramState<'a> {
         ProgramState {
             pc: ProgramOffset(0),
+            thread: 0,
             path_components: path_components.peekable(),
             current_string: None,
             fresh_string: false,
@@ -91,21 +127,91 @@ impl<'a> ProgramState<'a> {
     }
 }
 
+/// Something that happened on one of the matcher's threads, for recorders that keep a trace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEvent {
+    /// Thread 0 is the initial one, the rest are started by alternatives
+    pub thread: usize,
+    /// The instruction that was being executed
+    pub pc: ProgramOffset,
+    pub kind: TraceEventKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TraceEventKind {
+    /// The instruction started executing
+    Execute,
+    /// Some of the path was consumed
+    Consume(String),
+    /// A separator moved on to the next path component
+    NextComponent,
+    /// An alternative was saved as a new thread, to be tried later from `target`
+    Fork {
+        thread: usize,
+        target: ProgramOffset,
+    },
+    /// The thread was picked up again after the previous one failed
+    Resume,
+    Fail(FailReason),
+    /// The path ran out, but the pattern could match more components
+    Prefix,
+    /// The pattern matched the whole path
+    Complete,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FailReason {
+    /// The instruction didn't accept what was next in the path, which is described by `found`
+    Mismatch { found: String },
+    /// The pattern ended before the path did
+    Incomplete { remaining: String },
+}
+
+/// Collects the results of running a program, as reported by the matcher
+pub(crate) trait MatchRecorder {
+    /// The path ran out while the thread at `pc` could still accept more components
+    fn prefix(&mut self, pc: ProgramOffset);
+    /// The thread at `pc` completed with the whole path consumed
+    fn complete(&mut self, pc: ProgramOffset);
+    /// Nothing else could change the result, so there's no need to try any more alternatives
+    fn is_done(&self) -> bool;
+    /// Something happened while running the program. Only called if the recorder wants a trace.
+    fn trace(&mut self, _event: impl FnOnce() -> TraceEvent) {}
+}
+
+impl MatchRecorder for MatchResult {
+    fn prefix(&mut self, _pc: ProgramOffset) {
+        self.valid_as_prefix = true;
+    }
+
+    fn complete(&mut self, _pc: ProgramOffset) {
+        self.valid_as_complete_match = true;
+    }
+
+    fn is_done(&self) -> bool {
+        self.valid_as_prefix && self.valid_as_complete_match
+    }
+}
+
 #[derive(Debug, Clone)]
-struct Matcher<'a> {
+struct Matcher<'a, R> {
     state: ProgramState<'a>,
     alternatives: Vec<ProgramState<'a>>,
-    result: MatchResult,
+    /// How many threads have been started, for tracing
+    threads: usize,
+    result: R,
 }
 
-impl<'a> Matcher<'a> {
+impl<'a, R: MatchRecorder + std::fmt::Debug> Matcher<'a, R> {
     fn advance(&mut self, program: &Program) -> bool {
         log::debug!("{:#?}", self);
         log::debug!("{}", &program.instructions[self.state.pc.0]);
+        self.trace(|| TraceEventKind::Execute);
         match &program.instructions[self.state.pc.0] {
             Instruction::Separator if !self.has_string() => {
                 self.state.current_string = None;
                 if self.state.path_components.peek().is_some() {
+                    self.trace(|| TraceEventKind::NextComponent);
                     self.next()
                 } else {
                     self.end_of_input()
@@ -114,30 +220,30 @@ impl<'a> Matcher<'a> {
             // Collapse multiple separators with no consumption in between
             Instruction::Separator if self.state.fresh_string => self.next(),
             Instruction::Prefix(string) if !self.has_string() => {
-                match self.state.path_components.next() {
+                match self.state.path_components.peek() {
                     Some(Component::Prefix(prefix_component))
                         if prefix_component.as_os_str() == &string[..] =>
                     {
-                        self.next()
+                        self.consume_component()
                     }
-                    Some(_) => self.try_alternative(),
+                    Some(_) => self.mismatch(),
                     None => self.end_of_input(),
                 }
             }
-            Instruction::RootDir if !self.has_string() => match self.state.path_components.next() {
-                Some(Component::RootDir) => self.next(),
-                Some(_) => self.try_alternative(),
+            Instruction::RootDir if !self.has_string() => match self.state.path_components.peek() {
+                Some(Component::RootDir) => self.consume_component(),
+                Some(_) => self.mismatch(),
                 None => self.end_of_input(),
             },
-            Instruction::CurDir if !self.has_string() => match self.state.path_components.next() {
-                Some(Component::CurDir) => self.next(),
-                Some(_) => self.try_alternative(),
+            Instruction::CurDir if !self.has_string() => match self.state.path_components.peek() {
+                Some(Component::CurDir) => self.consume_component(),
+                Some(_) => self.mismatch(),
                 None => self.end_of_input(),
             },
-            Instruction::ParentDir if !self.has_string() => match self.state.path_components.next()
+            Instruction::ParentDir if !self.has_string() => match self.state.path_components.peek()
             {
-                Some(Component::ParentDir) => self.next(),
-                Some(_) => self.try_alternative(),
+                Some(Component::ParentDir) => self.consume_component(),
+                Some(_) => self.mismatch(),
                 None => self.end_of_input(),
             },
             Instruction::LiteralString(bytes) => match next_string(
@@ -148,9 +254,10 @@ impl<'a> Matcher<'a> {
                 NextString::Normal(current_string) if current_string.starts_with(&bytes[..]) => {
                     *current_string = &current_string[bytes.len()..];
                     self.state.fresh_string = false;
+                    self.trace(|| TraceEventKind::Consume(lossy(bytes)));
                     self.next()
                 }
-                NextString::Normal(_) | NextString::NotNormal => self.try_alternative(),
+                NextString::Normal(_) | NextString::NotNormal => self.mismatch(),
                 NextString::EndOfInput => self.end_of_input(),
             },
             Instruction::AnyCharacter => {
@@ -162,14 +269,16 @@ impl<'a> Matcher<'a> {
                     NextString::Normal(current_string) => {
                         // consume the first actual UTF-8 character
                         if let Some(length) = length_of_first_char(current_string) {
+                            let consumed = &current_string[..length];
                             *current_string = &current_string[length..];
                             self.state.fresh_string = false;
+                            self.trace(|| TraceEventKind::Consume(lossy(consumed)));
                             self.next()
                         } else {
-                            self.try_alternative()
+                            self.mismatch()
                         }
                     }
-                    NextString::NotNormal => self.try_alternative(),
+                    NextString::NotNormal => self.mismatch(),
                     NextString::EndOfInput => self.end_of_input(),
                 }
             }
@@ -179,27 +288,105 @@ impl<'a> Matcher<'a> {
                     &mut self.state.current_string,
                     &mut self.state.fresh_string,
                 ) {
-                    NextString::Normal(_) => {
+                    NextString::Normal(current_string) => {
                         // consume the entire string
-                        self.state.current_string = Some(b"");
+                        let consumed = std::mem::take(current_string);
                         self.state.fresh_string = false;
+                        self.trace(|| TraceEventKind::Consume(lossy(consumed)));
                         self.next()
                     }
-                    NextString::NotNormal => self.try_alternative(),
+                    NextString::NotNormal => self.mismatch(),
                     NextString::EndOfInput => self.end_of_input(),
                 }
             }
-            Instruction::Characters(_) => todo!(),
+            Instruction::Characters(character_classes) => {
+                match next_string(
+                    &mut self.state.path_components,
+                    &mut self.state.current_string,
+                    &mut self.state.fresh_string,
+                ) {
+                    NextString::Normal(current_string) => {
+                        // consume the first character if it's in one of the classes
+                        match first_char(current_string) {
+                            Some(ch)
+                                if character_classes.iter().any(|class| class.contains(ch)) =>
+                            {
+                                let consumed = &current_string[..ch.len_utf8()];
+                                *current_string = &current_string[ch.len_utf8()..];
+                                self.state.fresh_string = false;
+                                self.trace(|| TraceEventKind::Consume(lossy(consumed)));
+                                self.next()
+                            }
+                            _ => self.mismatch(),
+                        }
+                    }
+                    NextString::NotNormal => self.mismatch(),
+                    NextString::EndOfInput => self.end_of_input(),
+                }
+            }
+            Instruction::SkipToLiteral(bytes) => match next_string(
+                &mut self.state.path_components,
+                &mut self.state.current_string,
+                &mut self.state.fresh_string,
+            ) {
+                NextString::Normal(current_string) => {
+                    // Find every place the literal starts, stepping a character at a time like
+                    // AnyCharacter would
+                    let string = *current_string;
+                    let mut starts = vec![];
+                    let mut position = 0;
+                    loop {
+                        if string[position..].starts_with(bytes) {
+                            starts.push(position);
+                        }
+                        match length_of_first_char(&string[position..]) {
+                            Some(length) => position += length,
+                            None => break,
+                        }
+                    }
+                    let Some((first, rest)) = starts.split_first() else {
+                        return self.mismatch();
+                    };
+                    // Save the later occurrences as alternatives, so the earliest is tried first
+                    for start in rest.iter().rev() {
+                        self.threads += 1;
+                        let thread = self.threads;
+                        self.alternatives.push(ProgramState {
+                            pc: self.state.pc + 1,
+                            thread,
+                            current_string: Some(&string[(start + bytes.len())..]),
+                            fresh_string: false,
+                            ..self.state.clone()
+                        });
+                        let target = self.state.pc + 1;
+                        self.trace(|| TraceEventKind::Fork { thread, target });
+                    }
+                    let consumed = &string[..(first + bytes.len())];
+                    self.state.current_string = Some(&string[consumed.len()..]);
+                    self.state.fresh_string = false;
+                    self.trace(|| TraceEventKind::Consume(lossy(consumed)));
+                    self.next()
+                }
+                NextString::NotNormal => self.mismatch(),
+                NextString::EndOfInput => self.end_of_input(),
+            },
             Instruction::Jump(index) => {
                 self.state.pc = *index;
                 true
             }
             Instruction::Alternative(index) => {
                 // Save a snapshot so we can try it later
+                self.threads += 1;
+                let thread = self.threads;
                 self.alternatives.push(ProgramState {
                     pc: *index,
+                    thread,
                     ..self.state.clone()
                 });
+                self.trace(|| TraceEventKind::Fork {
+                    thread,
+                    target: *index,
+                });
                 self.next()
             }
             Instruction::Increment(counter_id) => {
@@ -216,8 +403,43 @@ impl<'a> Matcher<'a> {
                 }
             }
             Instruction::Complete => self.complete(),
-            _ => self.try_alternative(),
+            _ => self.mismatch(),
+        }
+    }
+
+    fn trace(&mut self, kind: impl FnOnce() -> TraceEventKind) {
+        let (thread, pc) = (self.state.thread, self.state.pc);
+        self.result.trace(|| TraceEvent {
+            thread,
+            pc,
+            kind: kind(),
+        });
+    }
+
+    /// Consume a component that isn't a normal one, after it has been peeked at
+    fn consume_component(&mut self) -> bool {
+        if let Some(component) = self.state.path_components.next() {
+            self.trace(|| {
+                TraceEventKind::Consume(component.as_os_str().to_string_lossy().into_owned())
+            });
         }
+        self.next()
+    }
+
+    /// The current instruction doesn't accept what's next in the path
+    fn mismatch(&mut self) -> bool {
+        let current_string = self.state.current_string.filter(|s| !s.is_empty());
+        let next_component = self.state.path_components.peek().copied();
+        self.trace(|| {
+            TraceEventKind::Fail(FailReason::Mismatch {
+                found: match (current_string, next_component) {
+                    (Some(string), _) => format!("{:?}", lossy(string)),
+                    (None, Some(component)) => describe_component(component),
+                    (None, None) => "the end of the path".into(),
+                },
+            })
+        });
+        self.try_alternative()
     }
 
     fn has_string(&self) -> bool {
@@ -234,6 +456,7 @@ impl<'a> Matcher<'a> {
         if let Some(alternative_state) = self.alternatives.pop() {
             log::debug!("try alternative");
             self.state = alternative_state;
+            self.trace(|| TraceEventKind::Resume);
             true
         } else {
             log::debug!("no alternative");
@@ -243,32 +466,52 @@ impl<'a> Matcher<'a> {
 
     fn end_of_input(&mut self) -> bool {
         log::debug!("end of input");
-        self.result.valid_as_prefix = true;
+        self.trace(|| TraceEventKind::Prefix);
+        self.result.prefix(self.state.pc);
         self.try_alternative()
     }
 
     fn complete(&mut self) -> bool {
-        if !self.has_string() && self.state.path_components.next().is_none() {
+        if !self.has_string() && self.state.path_components.peek().is_none() {
             log::debug!("complete");
-            self.result.valid_as_complete_match = true;
+            self.trace(|| TraceEventKind::Complete);
+            self.result.complete(self.state.pc);
         } else {
             log::debug!("pattern is complete but path not fully consumed");
+            // Borrowed rather than copied, so the components are only cloned if anything is
+            // tracing
+            let state = &self.state;
+            self.result.trace(|| TraceEvent {
+                thread: state.thread,
+                pc: state.pc,
+                kind: TraceEventKind::Fail(FailReason::Incomplete {
+                    remaining: remaining_path(state.current_string, state.path_components.clone()),
+                }),
+            });
         }
         self.try_alternative()
     }
 }
 
 pub fn path_matches(path: &Path, program: &Program) -> MatchResult {
+    run(path, program, MatchResult::none())
+}
+
+/// Run `program` against `path`, reporting to `recorder` until it's done or there are no more
+/// alternatives to try
+pub(crate) fn run<R: MatchRecorder + std::fmt::Debug>(
+    path: &Path,
+    program: &Program,
+    recorder: R,
+) -> R {
     let mut matcher = Matcher {
         state: ProgramState::new(path.components(), program.counters),
         alternatives: vec![],
-        result: MatchResult {
-            valid_as_prefix: false,
-            valid_as_complete_match: false,
-        },
+        threads: 0,
+        result: recorder,
     };
 
-    while !matcher.result.valid_as_prefix || !matcher.result.valid_as_complete_match {
+    while !matcher.result.is_done() {
         if !matcher.advance(program) {
             break;
         }
@@ -0,0 +1,322 @@
+//! Rewrites compiled programs into smaller ones that match exactly the same paths
+//!
+//! The compiler emits each node on its own, which leaves a lot of slack: literals split across
+//! several instructions, jumps to jumps, jumps to the next instruction and counters for repeats
+//! that could just be written out. Every pass here keeps both kinds of result the matcher reports
+//! the same, including which paths are valid prefixes, so an optimized program can be used
+//! anywhere the original could.
+
+use std::collections::HashSet;
+
+use crate::{
+    compiler::{Instruction, Program, ProgramOffset},
+    parser::Span,
+};
+
+#[cfg(test)]
+mod tests;
+
+/// Repeats are only unrolled if that takes at most this many instructions
+const MAX_UNROLLED: usize = 64;
+
+pub fn optimize(program: &mut Program) {
+    program.spans.resize(program.instructions.len(), None);
+    while unroll_repeat(program) {}
+    thread_jumps(program);
+    remove_dead_instructions(program);
+    merge_literals(program);
+    fuse_skips(program);
+    remove_unused_counters(program);
+}
+
+/// Where an instruction can go other than the next instruction
+fn target(instruction: &Instruction) -> Option<ProgramOffset> {
+    match instruction {
+        Instruction::Jump(target)
+        | Instruction::Alternative(target)
+        | Instruction::BranchIfLessThan(target, ..) => Some(*target),
+        _ => None,
+    }
+}
+
+fn target_mut(instruction: &mut Instruction) -> Option<&mut ProgramOffset> {
+    match instruction {
+        Instruction::Jump(target)
+        | Instruction::Alternative(target)
+        | Instruction::BranchIfLessThan(target, ..) => Some(target),
+        _ => None,
+    }
+}
+
+/// The instructions that can run straight after the one at `pc`
+pub(crate) fn successors(program: &Program, pc: usize) -> impl Iterator<Item = usize> {
+    let instruction = &program.instructions[pc];
+    let falls_through = !matches!(instruction, Instruction::Jump(_) | Instruction::Complete);
+    falls_through
+        .then_some(pc + 1)
+        .into_iter()
+        .chain(target(instruction).map(|target| target.0))
+}
+
+/// Every instruction reachable from `start`
+fn reachable(program: &Program, start: usize) -> Vec<bool> {
+    let mut seen = vec![false; program.instructions.len()];
+    let mut stack = vec![start];
+    while let Some(pc) = stack.pop() {
+        if pc >= seen.len() || seen[pc] {
+            continue;
+        }
+        seen[pc] = true;
+        stack.extend(successors(program, pc));
+    }
+    seen
+}
+
+/// The instructions that something jumps or branches to
+fn targets(program: &Program) -> HashSet<usize> {
+    program
+        .instructions
+        .iter()
+        .filter_map(|instruction| target(instruction).map(|target| target.0))
+        .collect()
+}
+
+/// Drop every instruction that isn't kept. Anything that went to a dropped instruction goes to
+/// the next one that is kept instead.
+fn retain(program: &mut Program, keep: &[bool]) {
+    let mut new_offsets = Vec::with_capacity(keep.len() + 1);
+    let mut kept = 0;
+    for keep in keep {
+        new_offsets.push(kept);
+        kept += usize::from(*keep);
+    }
+    new_offsets.push(kept);
+
+    let instructions = std::mem::take(&mut program.instructions);
+    let spans = std::mem::take(&mut program.spans);
+    for ((mut instruction, span), keep) in instructions.into_iter().zip(spans).zip(keep) {
+        if *keep {
+            if let Some(target) = target_mut(&mut instruction) {
+                target.0 = new_offsets[target.0];
+            }
+            program.instructions.push(instruction);
+            program.spans.push(span);
+        }
+    }
+}
+
+/// Write out a fixed repeat as copies of its body, so it doesn't need a counter
+///
+/// A fixed repeat compiles to an increment, the body, and a branch back while the counter is
+/// below the count. Counters are never reset, so this is only the same as copying the body if
+/// the increment can't run again after the repeat is done, or if the body only runs once anyway.
+/// Returns whether a repeat was unrolled.
+fn unroll_repeat(program: &mut Program) -> bool {
+    let instructions = &program.instructions;
+    for (start, instruction) in instructions.iter().enumerate() {
+        let Instruction::Increment(counter_id) = instruction else {
+            continue;
+        };
+        let Some(end) = instructions.iter().position(|instruction| {
+            matches!(instruction, Instruction::BranchIfLessThan(_, id, _) if id == counter_id)
+        }) else {
+            continue;
+        };
+        let Instruction::BranchIfLessThan(back, _, count) = instructions[end] else {
+            unreachable!()
+        };
+        if end < start {
+            continue;
+        }
+        let body = (start + 1)..end;
+        let uses = instructions
+            .iter()
+            .filter(|instruction| match instruction {
+                Instruction::Increment(id) | Instruction::BranchIfLessThan(_, id, _) => {
+                    id == counter_id
+                }
+                _ => false,
+            })
+            .count();
+        let copies = count.max(1) as usize;
+        let self_contained = instructions
+            .iter()
+            .enumerate()
+            .all(|(pc, instruction)| match target(instruction) {
+                Some(target) if body.contains(&pc) => (start + 1..=end).contains(&target.0),
+                Some(target) if pc != end => !(start + 1..=end).contains(&target.0),
+                _ => true,
+            });
+        if back.0 != start
+            || uses != 2
+            || !self_contained
+            || copies * body.len() > MAX_UNROLLED
+            || instructions[body.clone()].iter().any(|instruction| {
+                matches!(
+                    instruction,
+                    Instruction::Increment(_) | Instruction::BranchIfLessThan(..)
+                )
+            })
+            || (copies > 1 && reachable(program, end + 1).get(start) == Some(&true))
+        {
+            continue;
+        }
+
+        // Everything after the repeat moves by how much longer the copies are than the original
+        let length = body.len();
+        let moved = |offset: usize| {
+            if offset > end {
+                offset + copies * length - (end + 1 - start)
+            } else {
+                offset
+            }
+        };
+        let old_instructions = std::mem::take(&mut program.instructions);
+        let old_spans = std::mem::take(&mut program.spans);
+        let mut push = |pc: usize, relocate: &dyn Fn(usize) -> usize| {
+            let mut instruction = old_instructions[pc].clone();
+            if let Some(target) = target_mut(&mut instruction) {
+                target.0 = relocate(target.0);
+            }
+            program.instructions.push(instruction);
+            program.spans.push(old_spans[pc].clone());
+        };
+        for pc in 0..start {
+            push(pc, &moved);
+        }
+        for copy in 0..copies {
+            for pc in body.clone() {
+                push(pc, &|target| target - 1 + copy * length);
+            }
+        }
+        for pc in (end + 1)..old_instructions.len() {
+            push(pc, &moved);
+        }
+        return true;
+    }
+    false
+}
+
+/// Send jumps, alternatives and branches that go to a jump straight to where it goes
+fn thread_jumps(program: &mut Program) {
+    for pc in 0..program.instructions.len() {
+        let Some(mut destination) = target(&program.instructions[pc]) else {
+            continue;
+        };
+        // Give up on cycles of jumps, which never go anywhere
+        for _ in 0..program.instructions.len() {
+            match program.instructions.get(destination.0) {
+                Some(Instruction::Jump(next)) if *next != destination => destination = *next,
+                _ => break,
+            }
+        }
+        if let Some(target) = target_mut(&mut program.instructions[pc]) {
+            *target = destination;
+        }
+    }
+}
+
+/// Remove instructions that can never run, and jumps to the next instruction
+fn remove_dead_instructions(program: &mut Program) {
+    loop {
+        let reachable = reachable(program, 0);
+        let keep: Vec<bool> = program
+            .instructions
+            .iter()
+            .enumerate()
+            .map(|(pc, instruction)| {
+                reachable[pc] && *instruction != Instruction::Jump(ProgramOffset(pc + 1))
+            })
+            .collect();
+        if keep.iter().all(|keep| *keep) {
+            return;
+        }
+        retain(program, &keep);
+    }
+}
+
+/// Join literals that always run one after the other into a single literal
+fn merge_literals(program: &mut Program) {
+    let targets = targets(program);
+    let mut keep = vec![true; program.instructions.len()];
+    let mut pc = 0;
+    while pc < program.instructions.len() {
+        let mut next = pc + 1;
+        while next < program.instructions.len() && !targets.contains(&next) {
+            let (Instruction::LiteralString(first), Instruction::LiteralString(second)) =
+                (&program.instructions[pc], &program.instructions[next])
+            else {
+                break;
+            };
+            let merged = [&first[..], &second[..]].concat().into();
+            program.instructions[pc] = Instruction::LiteralString(merged);
+            program.spans[pc] = join_spans(&program.spans[pc], &program.spans[next]);
+            keep[next] = false;
+            next += 1;
+        }
+        pc = next;
+    }
+    retain(program, &keep);
+}
+
+/// Replace the loop that `*` compiles to with [`Instruction::SkipToLiteral`] when a literal comes
+/// straight after it
+fn fuse_skips(program: &mut Program) {
+    let mut keep = vec![true; program.instructions.len()];
+    for start in 0..program.instructions.len() {
+        let Some(
+            [Instruction::Alternative(any), Instruction::Jump(skip), Instruction::AnyCharacter, Instruction::Jump(back), Instruction::LiteralString(literal)],
+        ) = program.instructions.get(start..(start + 5))
+        else {
+            continue;
+        };
+        if any.0 != start + 2 || skip.0 != start + 4 || back.0 != start || literal.is_empty() {
+            continue;
+        }
+        // Nothing else can go into the middle of the loop
+        let entered = program
+            .instructions
+            .iter()
+            .enumerate()
+            .filter(|(pc, _)| !(start..(start + 4)).contains(pc))
+            .filter_map(|(_, instruction)| target(instruction))
+            .any(|target| ((start + 1)..(start + 5)).contains(&target.0));
+        if entered {
+            continue;
+        }
+        let span = join_spans(&program.spans[start], &program.spans[start + 4]);
+        program.instructions[start] = Instruction::SkipToLiteral(literal.clone());
+        program.spans[start] = span;
+        keep[(start + 1)..(start + 5)].fill(false);
+    }
+    retain(program, &keep);
+}
+
+/// Number the counters that are still used from zero
+fn remove_unused_counters(program: &mut Program) {
+    let mut new_ids = vec![None; program.counters as usize];
+    let mut used = 0;
+    for instruction in &mut program.instructions {
+        if let Instruction::Increment(counter_id)
+        | Instruction::BranchIfLessThan(_, counter_id, _) = instruction
+        {
+            let new_id = new_ids[counter_id.0 as usize].get_or_insert_with(|| {
+                used += 1;
+                used - 1
+            });
+            counter_id.0 = *new_id;
+        }
+    }
+    program.counters = used;
+}
+
+/// The span covering both spans, or whichever one is known
+fn join_spans(first: &Option<Span>, second: &Option<Span>) -> Option<Span> {
+    match (first, second) {
+        (Some(first), Some(second)) => {
+            Some(first.start.min(second.start)..first.end.max(second.end))
+        }
+        (Some(span), None) | (None, Some(span)) => Some(span.clone()),
+        (None, None) => None,
+    }
+}
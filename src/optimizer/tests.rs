@@ -0,0 +1,157 @@
+use std::path::PathBuf;
+
+use crate::{
+    analysis::equivalence_counterexample,
+    compiler::{compile, compile_with, CompileOptions, Instruction, Program},
+    generate::{GenerateOptions, Generator},
+    matcher::path_matches,
+    parser::parse,
+};
+
+const PATTERNS: &[&str] = &[
+    "*.rs",
+    "src/**/*.rs",
+    "a{b}c",
+    "{a,{b,{c,d}}}/x",
+    "*.tar.*",
+    "*a*a",
+    "<[0-9]:3>.png",
+    "<ab:2>/*",
+    "<*.:2>x",
+    "<a:0>b",
+    "<a:0,2>b",
+    "<<a:2>b:3>",
+    "**/<x/:2>y",
+    "{*.rs,*.md}",
+    "x{*.,y}z",
+    "/tmp/*.log",
+    "../*-{1,2}",
+    "**",
+];
+
+fn optimized(pattern: &str) -> Program {
+    let options = CompileOptions {
+        optimize: true,
+        ..CompileOptions::default()
+    };
+    compile_with(&parse(pattern), &options).unwrap()
+}
+
+/// Some paths near the edges of what the program matches
+fn sample_paths(program: &Program) -> Vec<PathBuf> {
+    let mut generator = Generator::new(
+        program,
+        GenerateOptions {
+            seed: Some(7),
+            ..GenerateOptions::default()
+        },
+    );
+    let mut paths = vec![];
+    for _ in 0..50 {
+        paths.extend(generator.matching());
+        paths.extend(generator.non_matching());
+    }
+    paths
+}
+
+#[test]
+fn optimized_programs_match_the_same_paths() {
+    for pattern in PATTERNS {
+        let naive = compile(&parse(pattern)).unwrap();
+        let optimized = optimized(pattern);
+        assert_eq!(
+            equivalence_counterexample(&naive, &optimized),
+            None,
+            "{}\n{}\n{}",
+            pattern,
+            naive,
+            optimized
+        );
+    }
+}
+
+#[test]
+fn optimized_programs_report_the_same_prefixes() {
+    for pattern in PATTERNS {
+        let naive = compile(&parse(pattern)).unwrap();
+        let optimized = optimized(pattern);
+        for path in sample_paths(&naive) {
+            for path in path.ancestors() {
+                let (before, after) = (path_matches(path, &naive), path_matches(path, &optimized));
+                assert_eq!(
+                    (before.valid_as_complete_match, before.valid_as_prefix),
+                    (after.valid_as_complete_match, after.valid_as_prefix),
+                    "{} against {}",
+                    pattern,
+                    path.display()
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn literals_are_merged() {
+    assert_eq!(
+        optimized("a{b}c").instructions,
+        [
+            Instruction::LiteralString(b"abc"[..].into()),
+            Instruction::Complete
+        ]
+    );
+}
+
+#[test]
+fn wildcards_before_literals_are_fused() {
+    let program = optimized("src/*.rs");
+    assert_eq!(
+        program.instructions,
+        [
+            Instruction::LiteralString(b"src"[..].into()),
+            Instruction::Separator,
+            Instruction::SkipToLiteral(b".rs"[..].into()),
+            Instruction::Complete
+        ]
+    );
+    assert_eq!(program.span(crate::compiler::ProgramOffset(2)), Some(4..8));
+}
+
+#[test]
+fn jumps_are_threaded() {
+    let program = optimized("{a,{b,{c,d}}}/x");
+    for instruction in &program.instructions {
+        if let Instruction::Jump(target) | Instruction::Alternative(target) = instruction {
+            assert!(
+                !matches!(program.instructions[target.0], Instruction::Jump(_)),
+                "{}",
+                program
+            );
+        }
+    }
+    assert!(
+        program.instructions.len()
+            < compile(&parse("{a,{b,{c,d}}}/x"))
+                .unwrap()
+                .instructions
+                .len()
+    );
+}
+
+#[test]
+fn fixed_repeats_are_unrolled() {
+    let program = optimized("img<[0-9]:3>.png");
+    assert_eq!(program.counters, 0);
+    assert_eq!(
+        program
+            .instructions
+            .iter()
+            .filter(|instruction| matches!(instruction, Instruction::Characters(_)))
+            .count(),
+        3
+    );
+
+    // Counters aren't reset, so a repeat that can run again keeps its counter
+    assert_eq!(optimized("<<a:2>b:3>").counters, 2);
+    // Loops before or after the repeat don't matter
+    assert_eq!(optimized("**/<a:2>*").counters, 0);
+}
@@ -2,15 +2,144 @@ use anyhow::Result;
 
 use std::{
     ffi::OsStr,
-    path::{is_separator, Component, Path, PathBuf},
+    fmt::{self, Write},
+    ops::Range,
+    path::{is_separator, Component, Path, PathBuf, MAIN_SEPARATOR},
 };
 
-#[derive(Debug, Clone)]
+#[cfg(test)]
+mod tests;
+
+/// A range of bytes in the pattern string
+pub type Span = Range<usize>;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Pattern {
     pub nodes: Vec<AstNode>,
+    /// Where each of the nodes came from in the pattern string
+    pub spans: Vec<Span>,
+}
+
+impl Pattern {
+    pub fn push(&mut self, node: AstNode, span: Span) {
+        self.nodes.push(node);
+        self.spans.push(span);
+    }
+
+    fn map_spans(&mut self, f: &impl Fn(Span) -> Span) {
+        for (node, span) in self.nodes.iter_mut().zip(&mut self.spans) {
+            *span = f(span.clone());
+            match node {
+                AstNode::Alternatives { choices } => {
+                    for choice in choices {
+                        choice.map_spans(f);
+                    }
+                }
+                AstNode::Repeat { pattern, .. } => pattern.map_spans(f),
+                _ => (),
+            }
+        }
+    }
+}
+
+/// Writes the pattern out in the syntax that [`parse`] reads
+///
+/// Characters that would mean something else are written as character classes, so the string
+/// parses to a pattern that matches the same paths, though it may not be the one it came from.
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_nodes(f, self, true)
+    }
+}
+
+fn write_nodes(f: &mut fmt::Formatter<'_>, pattern: &Pattern, whole: bool) -> fmt::Result {
+    for (index, node) in pattern.nodes.iter().enumerate() {
+        match node {
+            AstNode::Separator | AstNode::RootDir => f.write_char(MAIN_SEPARATOR)?,
+            AstNode::Prefix(prefix) => f.write_str(prefix)?,
+            AstNode::CurDir => f.write_str(".")?,
+            AstNode::ParentDir => f.write_str("..")?,
+            AstNode::LiteralString(bytes) => {
+                let string = String::from_utf8_lossy(bytes);
+                let mut chars = string.chars();
+                // A literal "." or ".." that's a whole component would be read as a directory
+                let starts_component = index == 0
+                    || matches!(
+                        pattern.nodes[index - 1],
+                        AstNode::Separator | AstNode::RootDir
+                    );
+                let ends_component = match pattern.nodes.get(index + 1) {
+                    Some(next) => *next == AstNode::Separator,
+                    None => whole,
+                };
+                if (string == "." || string == "..") && starts_component && ends_component {
+                    chars.next();
+                    f.write_str("[.]")?;
+                }
+                for ch in chars {
+                    if ch.is_ascii() && MEANINGFUL_BYTES.contains(&(ch as u8)) {
+                        write!(f, "[{}]", ch)?;
+                    } else {
+                        f.write_char(ch)?;
+                    }
+                }
+            }
+            AstNode::AnyCharacter => f.write_str("?")?,
+            AstNode::Wildcard => f.write_str("*")?,
+            AstNode::Recurse => f.write_str("**")?,
+            AstNode::Characters(classes) => write_classes(f, classes)?,
+            AstNode::Alternatives { choices } => {
+                f.write_char('{')?;
+                for (index, choice) in choices.iter().enumerate() {
+                    if index > 0 {
+                        f.write_char(',')?;
+                    }
+                    write_nodes(f, choice, false)?;
+                }
+                f.write_char('}')?;
+            }
+            AstNode::Repeat { min, max, pattern } => {
+                f.write_char('<')?;
+                write_nodes(f, pattern, false)?;
+                if min == max {
+                    write!(f, ":{}>", min)?;
+                } else {
+                    write!(f, ":{},{}>", min, max)?;
+                }
+            }
+        }
+    }
+    Ok(())
 }
 
-#[derive(Debug, Clone)]
+/// Write a character class so that it parses back the same
+///
+/// There's no escaping inside a class: a `]` can only come first, and a `-` after a single
+/// character makes a range, so a single character before a `-` is written as a range of one.
+fn write_classes(f: &mut fmt::Formatter<'_>, classes: &[CharacterClass]) -> fmt::Result {
+    let start = |class: &CharacterClass| match *class {
+        CharacterClass::Single(ch) | CharacterClass::Range(ch, _) => ch,
+    };
+    let mut ordered: Vec<_> = classes.iter().filter(|class| start(class) == ']').collect();
+    ordered.extend(classes.iter().filter(|class| start(class) != ']'));
+    f.write_char('[')?;
+    for (index, class) in ordered.iter().enumerate() {
+        let before_dash = ordered
+            .get(index + 1)
+            .is_some_and(|next| start(next) == '-');
+        match **class {
+            CharacterClass::Single(ch) if before_dash => write!(f, "{}-{}", ch, ch)?,
+            CharacterClass::Single(ch) => f.write_char(ch)?,
+            CharacterClass::Range(first, last) => write!(f, "{}-{}", first, last)?,
+        }
+    }
+    f.write_char(']')
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
 pub enum AstNode {
     Separator,
     Prefix(String),
@@ -18,7 +147,13 @@ pub enum AstNode {
     CurDir,
     ParentDir,
     Recurse,
-    LiteralString(Vec<u8>),
+    LiteralString(
+        #[cfg_attr(
+            feature = "serde",
+            serde(serialize_with = "crate::lossless::serialize")
+        )]
+        Vec<u8>,
+    ),
     AnyCharacter,
     Wildcard,
     Characters(Vec<CharacterClass>),
@@ -33,39 +168,93 @@ pub enum AstNode {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
 pub enum CharacterClass {
     Single(char),
     Range(char, char),
 }
 
+impl CharacterClass {
+    pub fn contains(&self, ch: char) -> bool {
+        match *self {
+            CharacterClass::Single(single) => ch == single,
+            CharacterClass::Range(start, end) => (start..=end).contains(&ch),
+        }
+    }
+}
+
 pub fn parse(string: impl AsRef<OsStr>) -> Pattern {
+    let original = string.as_ref().as_encoded_bytes();
     let path = Path::new(string.as_ref());
     let mut components_iter = path.components().peekable();
 
     // Split the path into prefix components (where no glob pattern is allowed) and others
-    let mut nodes = vec![];
+    let mut pattern = Pattern::default();
+    let mut position = 0;
     let mut path_relative = PathBuf::new();
     while let Some(Component::Prefix(..) | Component::RootDir) = components_iter.peek() {
-        nodes.push(match components_iter.next() {
+        match components_iter.next() {
             Some(Component::Prefix(prefix_component)) => {
-                AstNode::Prefix(prefix_component.as_os_str().to_string_lossy().into_owned())
+                let len = prefix_component.as_os_str().len();
+                pattern.push(
+                    AstNode::Prefix(prefix_component.as_os_str().to_string_lossy().into_owned()),
+                    position..(position + len),
+                );
+                position += len;
+            }
+            Some(Component::RootDir) => {
+                let len = original[position..]
+                    .iter()
+                    .take_while(|byte| is_separator(**byte as char))
+                    .count();
+                pattern.push(AstNode::RootDir, position..(position + len));
+                position += len;
             }
-            Some(Component::RootDir) => AstNode::RootDir,
             _ => unreachable!(),
-        });
+        }
     }
     path_relative.extend(components_iter);
 
     // Parse the remainder of the path into nodes
-    parse_nodes(
-        path_relative.as_os_str().as_encoded_bytes(),
-        |_| true,
-        &mut nodes,
-    );
+    let normalized = path_relative.as_os_str().as_encoded_bytes();
+    let mut relative = Pattern::default();
+    parse_spanned_nodes(normalized, |_| true, &mut relative);
+
+    // The remainder was normalized by splitting it into components, so the spans have to be
+    // mapped back onto the original string
+    let offsets = align(&original[position..], normalized);
+    relative.map_spans(&|span| {
+        // Spans are relative to the end of the string until now
+        let (start, end) = (normalized.len() - span.start, normalized.len() - span.end);
+        let start = position + offsets[start];
+        let end = position + if end == 0 { 0 } else { offsets[end - 1] + 1 };
+        start..end.max(start)
+    });
+    pattern.nodes.extend(relative.nodes);
+    pattern.spans.extend(relative.spans);
 
-    Pattern { nodes }
+    pattern
 }
 
+/// Find where each byte of `normalized` came from in `original`, which it was made from by only
+/// removing bytes and possibly changing separators
+fn align(original: &[u8], normalized: &[u8]) -> Vec<usize> {
+    let same = |a: u8, b: u8| a == b || (is_separator(a as char) && is_separator(b as char));
+    let mut offsets = Vec::with_capacity(normalized.len() + 1);
+    let mut index = 0;
+    for byte in normalized {
+        while index < original.len() && !same(original[index], *byte) {
+            index += 1;
+        }
+        offsets.push(index.min(original.len().saturating_sub(1)));
+        index += 1;
+    }
+    offsets.push(original.len());
+    offsets
+}
+
+/// Parse nodes while `cond` holds, without keeping their spans
 pub fn parse_nodes<'a>(
     mut string: &'a [u8],
     mut cond: impl FnMut(&[u8]) -> bool,
@@ -77,6 +266,21 @@ pub fn parse_nodes<'a>(
     string
 }
 
+/// Parse nodes while `cond` holds, leaving their spans relative to the end of the whole string
+/// (`start` and `end` are the number of bytes remaining)
+fn parse_spanned_nodes<'a>(
+    mut string: &'a [u8],
+    mut cond: impl FnMut(&[u8]) -> bool,
+    out: &mut Pattern,
+) -> &'a [u8] {
+    while !string.is_empty() && cond(string) {
+        let remaining_before = string.len();
+        string = next_node(string, &mut out.nodes);
+        out.spans.push(remaining_before..string.len());
+    }
+    string
+}
+
 pub fn next_node<'a>(string: &'a [u8], out: &mut Vec<AstNode>) -> &'a [u8] {
     node_separator((string, out))
         .or_else(node_any_character)
@@ -107,7 +311,7 @@ fn node_separator<'a, 'b>((string, out): NodeInput<'a, 'b>) -> NodeResult<'a, 'b
 }
 
 fn node_any_character<'a, 'b>((string, out): NodeInput<'a, 'b>) -> NodeResult<'a, 'b> {
-    if string.get(0) == Some(&b'?') {
+    if string.first() == Some(&b'?') {
         out.push(AstNode::AnyCharacter);
         Ok((&string[1..], out))
     } else {
@@ -125,7 +329,7 @@ fn node_recurse<'a, 'b>((string, out): NodeInput<'a, 'b>) -> NodeResult<'a, 'b>
 }
 
 fn node_wildcard<'a, 'b>((string, out): NodeInput<'a, 'b>) -> NodeResult<'a, 'b> {
-    if string.get(0) == Some(&b'*') {
+    if string.first() == Some(&b'*') {
         out.push(AstNode::Wildcard);
         Ok((&string[1..], out))
     } else {
@@ -136,24 +340,23 @@ fn node_wildcard<'a, 'b>((string, out): NodeInput<'a, 'b>) -> NodeResult<'a, 'b>
 fn node_alternatives<'a, 'b>((mut string, out): NodeInput<'a, 'b>) -> NodeResult<'a, 'b> {
     let original_string = string;
     let mut choices = vec![];
-    let mut current_out = vec![];
-    if string.get(0) == Some(&b'{') {
+    let mut current_out = Pattern::default();
+    if string.first() == Some(&b'{') {
         string = &string[1..];
         loop {
-            string = parse_nodes(
+            string = parse_spanned_nodes(
                 string,
-                |string| !matches!(string.get(0), Some(b',' | b'}')),
+                |string| !matches!(string.first(), Some(b',' | b'}')),
                 &mut current_out,
             );
-            match string.get(0) {
+            match string.first() {
                 Some(b',') => {
                     string = &string[1..];
-                    let nodes = std::mem::replace(&mut current_out, vec![]);
-                    choices.push(Pattern { nodes });
+                    choices.push(std::mem::take(&mut current_out));
                 }
                 Some(b'}') => {
                     string = &string[1..];
-                    choices.push(Pattern { nodes: current_out });
+                    choices.push(current_out);
                     break;
                 }
                 Some(_) => continue,
@@ -171,7 +374,7 @@ fn node_alternatives<'a, 'b>((mut string, out): NodeInput<'a, 'b>) -> NodeResult
 
 fn node_character_class<'a, 'b>((mut string, out): NodeInput<'a, 'b>) -> NodeResult<'a, 'b> {
     let original_string = string;
-    if string.get(0) == Some(&b'[') {
+    if string.first() == Some(&b'[') {
         string = &string[1..];
         let mut classes = vec![];
         loop {
@@ -179,7 +382,7 @@ fn node_character_class<'a, 'b>((mut string, out): NodeInput<'a, 'b>) -> NodeRes
                 return Err((original_string, out));
             };
             string = next_string;
-            let ch_class = if string.get(0) == Some(&b'-') {
+            let ch_class = if string.first() == Some(&b'-') {
                 // This is a range, due to the - char
                 string = &string[1..];
                 let Some((end_char, next_string)) = get_utf8_char(string) else {
@@ -192,7 +395,7 @@ fn node_character_class<'a, 'b>((mut string, out): NodeInput<'a, 'b>) -> NodeRes
                 CharacterClass::Single(start_char)
             };
             classes.push(ch_class);
-            match string.get(0) {
+            match string.first() {
                 Some(b']') => {
                     string = &string[1..];
                     break;
@@ -210,20 +413,20 @@ fn node_character_class<'a, 'b>((mut string, out): NodeInput<'a, 'b>) -> NodeRes
 
 fn node_repeat<'a, 'b>((mut string, out): NodeInput<'a, 'b>) -> NodeResult<'a, 'b> {
     let original_string = string;
-    let mut current_out = vec![];
+    let mut current_out = Pattern::default();
     macro_rules! fail {
         () => {
             return Err((original_string, out));
         };
     }
-    if string.get(0) == Some(&b'<') {
+    if string.first() == Some(&b'<') {
         string = &string[1..];
-        string = parse_nodes(
+        string = parse_spanned_nodes(
             string,
-            |string| !matches!(string.get(0), Some(b':')),
+            |string| !matches!(string.first(), Some(b':')),
             &mut current_out,
         );
-        if string.get(0) != Some(&b':') {
+        if string.first() != Some(&b':') {
             fail!();
         }
         string = &string[1..];
@@ -249,7 +452,7 @@ fn node_repeat<'a, 'b>((mut string, out): NodeInput<'a, 'b>) -> NodeResult<'a, '
                 AstNode::Repeat {
                     min,
                     max,
-                    pattern: Pattern { nodes: current_out },
+                    pattern: current_out,
                 }
             } else {
                 let Ok(times): Result<u32, _> = repeat_params_string.parse() else {
@@ -259,7 +462,7 @@ fn node_repeat<'a, 'b>((mut string, out): NodeInput<'a, 'b>) -> NodeResult<'a, '
                 AstNode::Repeat {
                     min: times,
                     max: times,
-                    pattern: Pattern { nodes: current_out },
+                    pattern: current_out,
                 }
             };
         out.push(node);
@@ -299,9 +502,10 @@ fn node_cur_or_parent_dir<'a, 'b>((string, out): NodeInput<'a, 'b>) -> NodeResul
     }
 }
 
+/// Bytes that can start other nodes
+const MEANINGFUL_BYTES: &[u8] = b"*?[]{}<>,:/\\";
+
 fn node_literal_string<'a, 'b>((string, out): NodeInput<'a, 'b>) -> NodeResult<'a, 'b> {
-    // Bytes that can start other nodes
-    const MEANINGFUL_BYTES: &[u8] = b"*?[]{}<>,:/\\";
     // Take at least one byte, but if we find a meaningful byte, leave that alone for further parsing
     if let Some(index_of_meaningful_byte) = string[1..]
         .iter()
@@ -0,0 +1,7 @@
+use crate::parser::parse;
+
+#[test]
+fn spans_point_into_the_pattern() {
+    let pattern = parse("src//**/{a,b}.rs");
+    assert_eq!(pattern.spans, [0..3, 3..4, 5..7, 7..8, 8..13, 13..16]);
+}
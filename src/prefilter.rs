@@ -0,0 +1,221 @@
+//! Rejects paths that can't match a program, without running it
+//!
+//! Most paths fail patterns like `**/*_test.go` simply because they don't contain `_test.go`.
+//! The literals that every match consumes, and the literal that every match ends with, are found
+//! from the program once, so each path only needs a fast substring search before the matcher.
+
+use std::{
+    collections::BTreeSet,
+    path::{Component, Path},
+};
+
+use memchr::memmem::Finder;
+
+use crate::{
+    compiler::{Instruction, Program},
+    optimizer::successors,
+};
+
+#[cfg(test)]
+mod tests;
+
+/// The literals a path has to contain for a program to match it
+#[derive(Debug, Clone)]
+pub struct Prefilter {
+    /// Found somewhere in every matching path, longest first
+    literals: Vec<Finder<'static>>,
+    /// The end of the last component of every matching path
+    suffix: Box<[u8]>,
+}
+
+impl Prefilter {
+    pub fn new(program: &Program) -> Prefilter {
+        let suffix = required_suffix(program);
+        let mut literals: Vec<&[u8]> = required_literals(program)
+            .into_iter()
+            .filter(|literal| !literal.is_empty() && !contains(&suffix, literal))
+            .collect();
+        literals.sort_by_key(|literal| std::cmp::Reverse(literal.len()));
+        // A literal inside a longer one is found whenever that one is
+        let mut kept: Vec<&[u8]> = vec![];
+        for literal in literals {
+            if !kept.iter().any(|longer| contains(longer, literal)) {
+                kept.push(literal);
+            }
+        }
+        Prefilter {
+            literals: kept
+                .into_iter()
+                .map(|literal| Finder::new(literal).into_owned())
+                .collect(),
+            suffix: suffix.into(),
+        }
+    }
+
+    /// The literals that every matching path contains, other than the suffix
+    pub fn literals(&self) -> impl Iterator<Item = &[u8]> {
+        self.literals.iter().map(Finder::needle)
+    }
+
+    /// What the last component of every matching path ends with, which may be empty
+    pub fn suffix(&self) -> &[u8] {
+        &self.suffix
+    }
+
+    /// Whether there's nothing to check, so every path might match
+    pub fn is_empty(&self) -> bool {
+        self.literals.is_empty() && self.suffix.is_empty()
+    }
+
+    /// False if the program can't match `path`. True doesn't mean that it does.
+    pub fn might_match(&self, path: &Path) -> bool {
+        if !self.suffix.is_empty() {
+            match path.components().next_back() {
+                Some(Component::Normal(last))
+                    if last.as_encoded_bytes().ends_with(&self.suffix) => {}
+                _ => return false,
+            }
+        }
+        let bytes = path.as_os_str().as_encoded_bytes();
+        self.literals
+            .iter()
+            .all(|finder| finder.find(bytes).is_some())
+    }
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    memchr::memmem::find(haystack, needle).is_some()
+}
+
+/// The literals consumed on every way through the program to `complete`
+///
+/// Each instruction gets the literals consumed on every way to reach it, which only shrinks as
+/// more ways are found, so this settles once nothing changes.
+fn required_literals(program: &Program) -> BTreeSet<&[u8]> {
+    let instructions = &program.instructions;
+    // None for an instruction that hasn't been reached yet
+    let mut reaching: Vec<Option<BTreeSet<&[u8]>>> = vec![None; instructions.len()];
+    let mut required: Option<BTreeSet<&[u8]>> = None;
+    let mut stack = vec![0];
+    if let Some(first) = reaching.first_mut() {
+        *first = Some(BTreeSet::new());
+    }
+    while let Some(pc) = stack.pop() {
+        let Some(mut literals) = reaching[pc].clone() else {
+            continue;
+        };
+        match &instructions[pc] {
+            Instruction::LiteralString(bytes) | Instruction::SkipToLiteral(bytes) => {
+                literals.insert(&bytes[..]);
+            }
+            Instruction::Complete => {
+                intersect(&mut required, &literals);
+            }
+            _ => {}
+        }
+        for next in successors(program, pc).filter(|next| *next < instructions.len()) {
+            if intersect(&mut reaching[next], &literals) {
+                stack.push(next);
+            }
+        }
+    }
+    required.unwrap_or_default()
+}
+
+/// Narrow `known` down to what's also in `literals`, returning whether it changed
+fn intersect<'p>(known: &mut Option<BTreeSet<&'p [u8]>>, literals: &BTreeSet<&'p [u8]>) -> bool {
+    match known {
+        None => {
+            *known = Some(literals.clone());
+            true
+        }
+        Some(known) => {
+            let before = known.len();
+            known.retain(|literal| literals.contains(literal));
+            known.len() != before
+        }
+    }
+}
+
+/// What the rest of a match consumes at the end of its last component
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Ending {
+    suffix: Vec<u8>,
+    /// The suffix starts right where the instruction does, so a literal before it extends it
+    open: bool,
+}
+
+impl Ending {
+    /// The ending shared by two ways through the program
+    fn meet(&self, other: &Ending) -> Ending {
+        let length = self
+            .suffix
+            .iter()
+            .rev()
+            .zip(other.suffix.iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        Ending {
+            suffix: self.suffix[self.suffix.len() - length..].to_vec(),
+            // If either got cut short, something else comes before the shared part
+            open: self.open && other.open && self.suffix == other.suffix,
+        }
+    }
+}
+
+/// The literal that the last component of every match ends with
+///
+/// This works backwards from `complete`, like [`required_literals`] works forwards, and settles
+/// because an ending only gets shorter once it's known.
+fn required_suffix(program: &Program) -> Vec<u8> {
+    let instructions = &program.instructions;
+    // None for an instruction that can't reach `complete`, as far as is known yet
+    let mut endings: Vec<Option<Ending>> = vec![None; instructions.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for pc in (0..instructions.len()).rev() {
+            let after = successors(program, pc)
+                .filter_map(|next| endings.get(next).cloned().flatten())
+                .reduce(|a, b| a.meet(&b));
+            let ending = match (&instructions[pc], after) {
+                (Instruction::Complete, _) => Some(Ending {
+                    suffix: vec![],
+                    open: true,
+                }),
+                (_, None) => None,
+                (
+                    Instruction::LiteralString(bytes) | Instruction::SkipToLiteral(bytes),
+                    Some(Ending { suffix, open: true }),
+                ) => Some(Ending {
+                    suffix: [&bytes[..], &suffix].concat(),
+                    // Anything could be skipped before the literal
+                    open: matches!(instructions[pc], Instruction::LiteralString(_)),
+                }),
+                (
+                    Instruction::Jump(_)
+                    | Instruction::Alternative(_)
+                    | Instruction::Increment(_)
+                    | Instruction::BranchIfLessThan(..),
+                    Some(ending),
+                ) => Some(ending),
+                // Anything else either consumes something that isn't a literal, or moves on to
+                // another component
+                (_, Some(Ending { suffix, .. })) => Some(Ending {
+                    suffix,
+                    open: false,
+                }),
+            };
+            if ending != endings[pc] {
+                endings[pc] = ending;
+                changed = true;
+            }
+        }
+    }
+    endings
+        .into_iter()
+        .next()
+        .flatten()
+        .map(|ending| ending.suffix)
+        .unwrap_or_default()
+}
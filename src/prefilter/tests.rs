@@ -0,0 +1,132 @@
+use std::path::Path;
+
+use crate::{
+    compiler::{compile, compile_with, CompileOptions, Program},
+    generate::{GenerateOptions, Generator},
+    matcher::path_matches,
+    parser::parse,
+    prefilter::Prefilter,
+};
+
+fn programs(pattern: &str) -> [Program; 2] {
+    let pattern = parse(pattern);
+    let optimized = CompileOptions {
+        optimize: true,
+        ..CompileOptions::default()
+    };
+    [
+        compile(&pattern).unwrap(),
+        compile_with(&pattern, &optimized).unwrap(),
+    ]
+}
+
+const PATTERNS: &[&str] = &[
+    "**/*_test.go",
+    "src/**/*.rs",
+    "**/*.{jpg,png}",
+    "*.{tar.gz,tgz}",
+    "**/{a,b}/*",
+    "{src,tests}/**/mod.rs",
+    "**/node_modules/**/*",
+    "a*b*c",
+    "*.<[0-9]:2>",
+    "<ab:1,3>x",
+    "<a*:2>b.txt",
+    "/etc/*.conf",
+    "**/build/*.o",
+    "**/*",
+    "*",
+];
+
+#[test]
+fn finds_required_literals() {
+    for (pattern, literals, suffix) in [
+        ("**/*_test.go", &[][..], "_test.go"),
+        ("src/**/*.rs", &["src"][..], ".rs"),
+        ("**/*.{jpg,png}", &["."][..], "g"),
+        ("*.{tar.gz,tgz}", &["."][..], "gz"),
+        ("{src,tests}/**/mod.rs", &[][..], "mod.rs"),
+        ("**/node_modules/**/*", &["node_modules"][..], ""),
+        ("a*b*c", &["a", "b"][..], "c"),
+        ("<ab:1,3>x", &["ab"][..], "x"),
+        ("**/build/*.o", &["build"][..], ".o"),
+        ("**/{a,b}/*", &[][..], ""),
+        ("**/*", &[][..], ""),
+    ] {
+        for program in programs(pattern) {
+            let prefilter = Prefilter::new(&program);
+            let found: Vec<_> = prefilter
+                .literals()
+                .map(|literal| String::from_utf8_lossy(literal).into_owned())
+                .collect();
+            assert_eq!(found, literals, "{}", pattern);
+            assert_eq!(prefilter.suffix(), suffix.as_bytes(), "{}", pattern);
+        }
+    }
+}
+
+#[test]
+fn never_rejects_a_match() {
+    for pattern in PATTERNS {
+        for program in programs(pattern) {
+            let prefilter = Prefilter::new(&program);
+            let mut generator = Generator::new(
+                &program,
+                GenerateOptions {
+                    component_length: 0..=4,
+                    seed: Some(3),
+                    ..GenerateOptions::default()
+                },
+            );
+            for _ in 0..200 {
+                for path in [generator.matching(), generator.non_matching()]
+                    .into_iter()
+                    .flatten()
+                {
+                    if path_matches(&path, &program).valid_as_complete_match {
+                        assert!(
+                            prefilter.might_match(&path),
+                            "{} {}",
+                            pattern,
+                            path.display()
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn rejects_paths_without_the_literals() {
+    let [program, _] = programs("**/*_test.go");
+    let prefilter = Prefilter::new(&program);
+    for (path, might_match) in [
+        ("src/main.go", false),
+        ("a_test.go/b", false),
+        ("a_test.go.orig", false),
+        ("pkg/a_test.go", true),
+        ("pkg/a_test.go/", true),
+        ("pkg/a_test.go/.", true),
+    ] {
+        let path = Path::new(path);
+        assert_eq!(
+            prefilter.might_match(path),
+            might_match,
+            "{}",
+            path.display()
+        );
+        assert_eq!(
+            path_matches(path, &program).valid_as_complete_match,
+            might_match,
+            "{}",
+            path.display()
+        );
+    }
+    let [program, _] = programs("src/**/*.rs");
+    let prefilter = Prefilter::new(&program);
+    assert!(!prefilter.might_match(Path::new("lib/main.rs")));
+    assert!(!prefilter.might_match(Path::new("src/main.go")));
+    assert!(prefilter.might_match(Path::new("lib/src/main.rs")));
+    assert!(Prefilter::new(&programs("**/*")[0]).is_empty());
+}
@@ -0,0 +1,585 @@
+//! Translates glob patterns into regular expressions, for tools that only take regexes
+//!
+//! The regex matches a path as a string with `/` separators, and is anchored at both ends, using
+//! `\z` rather than `$` for PCRE. It follows what the matcher does rather than what a glob usually
+//! means, since the point is to match the same paths:
+//!
+//! - A separator only consumes anything part way through a component, so a part of the pattern
+//!   that matches an empty component disappears along with its separator. `a/*/b` matches `a/b`.
+//! - The body of a repeat always runs at least once, so `<x:0,2>` is `x{1,2}`.
+//! - Counters aren't reset, so a repeat nested inside another one that runs more than once can't
+//!   be translated.
+//!
+//! [`from_regex`] goes the other way, importing regexes as patterns.
+
+use std::str::FromStr;
+
+use anyhow::bail;
+
+use crate::parser::{AstNode, CharacterClass, Pattern, Span};
+
+pub use import::from_regex;
+
+mod import;
+#[cfg(test)]
+mod tests;
+
+/// How many passes of a repeat are written out when they can't be a quantifier
+const MAX_UNROLLED: u32 = 16;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Flavor {
+    /// The `regex` crate
+    #[default]
+    Rust,
+    Pcre,
+    /// POSIX extended regular expressions, as used by `grep -E`
+    Posix,
+}
+
+impl Flavor {
+    /// The anchor for the end of the string
+    ///
+    /// PCRE's `$` also matches before a newline at the end, which a path could end with.
+    fn end_anchor(self) -> &'static str {
+        match self {
+            Flavor::Rust | Flavor::Posix => "$",
+            Flavor::Pcre => "\\z",
+        }
+    }
+
+    /// The most times a quantifier can repeat something, if there's a limit
+    fn max_repeat(self) -> Option<u32> {
+        match self {
+            Flavor::Rust => None,
+            Flavor::Pcre => Some(65535),
+            // RE_DUP_MAX
+            Flavor::Posix => Some(255),
+        }
+    }
+}
+
+impl FromStr for Flavor {
+    type Err = anyhow::Error;
+
+    fn from_str(string: &str) -> anyhow::Result<Flavor> {
+        Ok(match string {
+            "rust" => Flavor::Rust,
+            "pcre" => Flavor::Pcre,
+            "posix" => Flavor::Posix,
+            _ => bail!("unknown regex flavor {string:?}, expected rust, pcre or posix"),
+        })
+    }
+}
+
+/// Part of a pattern that can't be written as a regex, or of a regex that can't be imported
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unsupported {
+    /// The part of the pattern string or regex that can't be translated
+    pub span: Span,
+    pub message: String,
+}
+
+/// Translate a pattern into a regex, or explain every part of it that can't be
+pub fn to_regex(pattern: &Pattern, flavor: Flavor) -> Result<String, Vec<Unsupported>> {
+    let mut translator = Translator {
+        flavor,
+        unsupported: vec![],
+        repeated: false,
+    };
+    let ends = translator.nodes(pattern, only(Position::Start, vec![]));
+    let regex = translator.alternation(ends.into_iter().flatten().collect());
+    if !translator.unsupported.is_empty() {
+        return Err(translator.unsupported);
+    }
+    let Some(regex) = regex else {
+        let span = match (pattern.spans.first(), pattern.spans.last()) {
+            (Some(first), Some(last)) => first.start..last.end,
+            _ => 0..0,
+        };
+        return Err(vec![Unsupported {
+            span,
+            message: "the pattern can't match any path".into(),
+        }]);
+    };
+    let regex: String = regex.iter().map(|atom| atom.text.as_str()).collect();
+    Ok(format!("^{}{}", regex, flavor.end_anchor()))
+}
+
+/// Where the matcher is in the path, which decides what a separator does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Position {
+    /// At the start of the path, or just after a separator
+    Start,
+    /// Part way through a component
+    Inside,
+}
+
+const POSITIONS: [Position; 2] = [Position::Start, Position::Inside];
+
+/// Part of a regex that a quantifier can be applied to
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Atom {
+    text: String,
+    /// Whether the atom already ends with a quantifier, so it needs a group to take another
+    quantified: bool,
+}
+
+impl Atom {
+    fn new(text: impl Into<String>) -> Atom {
+        Atom {
+            text: text.into(),
+            quantified: false,
+        }
+    }
+}
+
+type Regex = Vec<Atom>;
+
+/// The regexes for the ways through part of a pattern, indexed by the position they end at, or
+/// `None` if there's no way to end there
+type Ends = [Option<Regex>; 2];
+
+fn only(position: Position, regex: Regex) -> Ends {
+    let mut ends = [None, None];
+    ends[position as usize] = Some(regex);
+    ends
+}
+
+/// The only position there's a way to end at, if there's exactly one
+fn single_end(ends: &Ends) -> Option<Position> {
+    match ends {
+        [Some(_), None] => Some(Position::Start),
+        [None, Some(_)] => Some(Position::Inside),
+        _ => None,
+    }
+}
+
+fn concat(before: &Regex, after: &Regex) -> Regex {
+    before.iter().chain(after).cloned().collect()
+}
+
+struct Translator {
+    flavor: Flavor,
+    unsupported: Vec<Unsupported>,
+    /// Whether we're inside a repeat that can run more than once
+    repeated: bool,
+}
+
+impl Translator {
+    fn unsupported(&mut self, span: &Span, message: impl Into<String>) {
+        // Parts of the pattern are translated once for each position they can start at
+        let unsupported = Unsupported {
+            span: span.clone(),
+            message: message.into(),
+        };
+        if !self.unsupported.contains(&unsupported) {
+            self.unsupported.push(unsupported);
+        }
+    }
+
+    /// Translate a sequence of nodes, carrying on from each of the ways through what came before
+    fn nodes(&mut self, pattern: &Pattern, before: Ends) -> Ends {
+        let mut current = before;
+        for (index, node) in pattern.nodes.iter().enumerate() {
+            let span = pattern.spans.get(index).cloned().unwrap_or_default();
+            let mut next: [Vec<Regex>; 2] = [vec![], vec![]];
+            for from in POSITIONS {
+                let Some(before) = &current[from as usize] else {
+                    continue;
+                };
+                let after = self.node(node, &span, from);
+                for to in POSITIONS {
+                    if let Some(regex) = &after[to as usize] {
+                        next[to as usize].push(concat(before, regex));
+                    }
+                }
+            }
+            current = next.map(|choices| self.alternation(choices));
+        }
+        current
+    }
+
+    fn node(&mut self, node: &AstNode, span: &Span, from: Position) -> Ends {
+        let component = Atom::new("[^/]");
+        let inside = |regex| only(Position::Inside, regex);
+        match node {
+            AstNode::Separator => match from {
+                Position::Start => only(Position::Start, vec![]),
+                Position::Inside => only(Position::Start, vec![Atom::new("/")]),
+            },
+            AstNode::Prefix(prefix) if from == Position::Start => {
+                inside(prefix.chars().map(|ch| self.literal(ch)).collect())
+            }
+            AstNode::RootDir => only(Position::Start, vec![Atom::new("/")]),
+            AstNode::CurDir if from == Position::Start => inside(vec![self.literal('.')]),
+            AstNode::ParentDir if from == Position::Start => {
+                inside(vec![self.literal('.'), self.literal('.')])
+            }
+            AstNode::Prefix(_) | AstNode::CurDir | AstNode::ParentDir => [None, None],
+            AstNode::LiteralString(bytes) => match std::str::from_utf8(bytes) {
+                Ok(string) => inside(string.chars().map(|ch| self.literal(ch)).collect()),
+                Err(_) => {
+                    self.unsupported(span, "literals that aren't valid UTF-8 can't be in a regex");
+                    inside(vec![])
+                }
+            },
+            AstNode::AnyCharacter => inside(vec![component]),
+            // Matching nothing at the start of a component doesn't start one
+            AstNode::Wildcard => match from {
+                Position::Start => [Some(vec![]), Some(self.quantify(vec![component], 1, None))],
+                Position::Inside => inside(self.quantify(vec![component], 0, None)),
+            },
+            AstNode::Characters(classes) => match self.class(classes) {
+                Some(atom) => inside(vec![atom]),
+                None => [None, None],
+            },
+            // Whole components followed by separators, after finishing the current one
+            AstNode::Recurse => {
+                let mut directory = self.quantify(vec![component.clone()], 1, None);
+                directory.push(Atom::new("/"));
+                let directories = self.quantify(directory, 0, None);
+                match from {
+                    Position::Start => only(Position::Start, directories),
+                    Position::Inside => {
+                        let mut rest = self.quantify(vec![component], 0, None);
+                        rest.push(Atom::new("/"));
+                        rest.extend(directories);
+                        [Some(rest), Some(vec![])]
+                    }
+                }
+            }
+            AstNode::Alternatives { choices } if choices.is_empty() => only(from, vec![]),
+            AstNode::Alternatives { choices } => {
+                let mut ends: [Vec<Regex>; 2] = [vec![], vec![]];
+                for choice in choices {
+                    let after = self.nodes(choice, only(from, vec![]));
+                    for (to, regex) in after.into_iter().enumerate() {
+                        ends[to].extend(regex);
+                    }
+                }
+                ends.map(|choices| self.alternation(choices))
+            }
+            AstNode::Repeat { min, max, pattern } => self.repeat(*min, *max, pattern, span, from),
+        }
+    }
+
+    fn repeat(
+        &mut self,
+        min: u32,
+        max: u32,
+        pattern: &Pattern,
+        span: &Span,
+        from: Position,
+    ) -> Ends {
+        // The matcher checks the counter after each pass, so there's always at least one
+        let min = min.max(1);
+        let max = max.max(min);
+        if self.repeated {
+            self.unsupported(
+                span,
+                "repeats inside repeats keep counting from one pass of the outer repeat to the \
+                 next, which a regex can't do",
+            );
+            return only(from, vec![]);
+        }
+        if let Some(limit) = self.flavor.max_repeat().filter(|limit| max > *limit) {
+            self.unsupported(
+                span,
+                format!("this flavor can only repeat something {} times", limit),
+            );
+            return only(from, vec![]);
+        }
+
+        let outer = std::mem::replace(&mut self.repeated, max > 1);
+        let bodies = POSITIONS.map(|start| self.nodes(pattern, only(start, vec![])));
+        self.repeated = outer;
+
+        let mut current = only(from, vec![]);
+        let mut passes = 0;
+        while passes < max {
+            // Once every way through ends up in the same position, the rest of the passes can be
+            // a quantifier, as long as the body keeps going the same way from there
+            if let Some(end) = single_end(&current) {
+                let before = current[end as usize].clone().unwrap();
+                let body = &bodies[end as usize];
+                if body.iter().all(Option::is_none) {
+                    return if passes >= min { current } else { [None, None] };
+                }
+                if let Some(to) = single_end(body) {
+                    let regex = body[to as usize].clone().unwrap();
+                    let same_again = bodies[to as usize] == only(to, regex.clone());
+                    if to == end || (passes < min && same_again) {
+                        let rest =
+                            self.quantify(regex, min.saturating_sub(passes), Some(max - passes));
+                        return only(to, concat(&before, &rest));
+                    }
+                }
+                // Like `*`, a body that can match nothing at the start of a component, and stays
+                // in the component once it's in one
+                let inside = &bodies[Position::Inside as usize];
+                if end == Position::Start
+                    && body[Position::Start as usize] == Some(vec![])
+                    && single_end(inside) == Some(Position::Inside)
+                {
+                    let mut regex =
+                        concat(&before, body[Position::Inside as usize].as_ref().unwrap());
+                    let rest = inside[Position::Inside as usize].clone().unwrap();
+                    regex.extend(self.quantify(rest, 0, Some(max - passes - 1)));
+                    return [Some(before), Some(regex)];
+                }
+            }
+            // A body that does the same wherever it starts only depends on the last pass
+            if passes == 0 && bodies[0] == bodies[1] {
+                let Some(any) = self.alternation(bodies[0].iter().flatten().cloned().collect())
+                else {
+                    return [None, None];
+                };
+                let before = self.quantify(any, min - 1, Some(max - 1));
+                return bodies[0]
+                    .clone()
+                    .map(|regex| regex.map(|regex| concat(&before, &regex)));
+            }
+            if passes == MAX_UNROLLED {
+                self.unsupported(
+                    span,
+                    "this repeat moves between components in ways that would have to be written \
+                     out too many times",
+                );
+                return current;
+            }
+
+            let mut next: [Vec<Regex>; 2] = [vec![], vec![]];
+            for position in POSITIONS {
+                let Some(before) = &current[position as usize] else {
+                    continue;
+                };
+                if passes >= min {
+                    next[position as usize].push(before.clone());
+                }
+                for to in POSITIONS {
+                    if let Some(regex) = &bodies[position as usize][to as usize] {
+                        next[to as usize].push(concat(before, regex));
+                    }
+                }
+            }
+            current = next.map(|choices| self.alternation(choices));
+            passes += 1;
+        }
+        current
+    }
+
+    /// A regex matching any of the choices, with the atoms they all start and end with factored
+    /// out
+    fn alternation(&self, choices: Vec<Regex>) -> Option<Regex> {
+        let mut unique: Vec<Regex> = vec![];
+        for choice in choices {
+            if !unique.contains(&choice) {
+                unique.push(choice);
+            }
+        }
+        let mut choices = unique;
+        if choices.len() <= 1 {
+            return choices.pop();
+        }
+
+        let shortest = choices.iter().map(Vec::len).min().unwrap_or(0);
+        let prefix_len = (0..shortest)
+            .take_while(|index| {
+                choices
+                    .iter()
+                    .all(|choice| choice[*index] == choices[0][*index])
+            })
+            .count();
+        let suffix_len = (0..shortest - prefix_len)
+            .take_while(|index| {
+                let atom = &choices[0][choices[0].len() - 1 - index];
+                choices
+                    .iter()
+                    .all(|choice| &choice[choice.len() - 1 - index] == atom)
+            })
+            .count();
+        let mut out = choices[0][..prefix_len].to_vec();
+        let suffix = choices[0][choices[0].len() - suffix_len..].to_vec();
+
+        let mut middles: Vec<Regex> = choices
+            .into_iter()
+            .map(|choice| choice[prefix_len..choice.len() - suffix_len].to_vec())
+            .collect();
+        let optional = middles.iter().any(Vec::is_empty);
+        middles.retain(|middle| !middle.is_empty());
+        let middle = match middles.len() {
+            0 => vec![],
+            1 => middles.pop().unwrap(),
+            _ => {
+                let texts: Vec<String> = middles
+                    .iter()
+                    .map(|middle| middle.iter().map(|atom| atom.text.as_str()).collect())
+                    .collect();
+                vec![self.group(&texts.join("|"))]
+            }
+        };
+        if optional {
+            out.extend(self.quantify(middle, 0, Some(1)));
+        } else {
+            out.extend(middle);
+        }
+        out.extend(suffix);
+        Some(out)
+    }
+
+    fn group(&self, inner: &str) -> Atom {
+        let open = match self.flavor {
+            Flavor::Posix => "(",
+            Flavor::Rust | Flavor::Pcre => "(?:",
+        };
+        Atom::new(format!("{}{})", open, inner))
+    }
+
+    /// Repeat a regex between `min` and `max` times, or any number of times from `min` if there
+    /// is no `max`
+    fn quantify(&self, regex: Regex, min: u32, max: Option<u32>) -> Regex {
+        if regex.is_empty() || max == Some(0) {
+            return vec![];
+        }
+        if (min, max) == (1, Some(1)) {
+            return regex;
+        }
+        let mut atom = match &regex[..] {
+            [atom] if !atom.quantified => atom.clone(),
+            _ => {
+                // `x*` or `x?` that can also be skipped is the same, and `x+` is just `x*`
+                if let ([atom], 0, Some(1)) = (&regex[..], min, max) {
+                    if atom.text.ends_with('*') || atom.text.ends_with('?') {
+                        return regex;
+                    }
+                    if let Some(repeated) = atom.text.strip_suffix('+') {
+                        return vec![Atom {
+                            text: format!("{}*", repeated),
+                            quantified: true,
+                        }];
+                    }
+                }
+                let text: String = regex.iter().map(|atom| atom.text.as_str()).collect();
+                self.group(&text)
+            }
+        };
+        match (min, max) {
+            (0, None) => atom.text.push('*'),
+            (1, None) => atom.text.push('+'),
+            (min, None) => atom.text.push_str(&format!("{{{},}}", min)),
+            (0, Some(1)) => atom.text.push('?'),
+            (min, Some(max)) if min == max => atom.text.push_str(&format!("{{{}}}", min)),
+            (min, Some(max)) => atom.text.push_str(&format!("{{{},{}}}", min, max)),
+        }
+        atom.quantified = true;
+        vec![atom]
+    }
+
+    fn literal(&self, ch: char) -> Atom {
+        let special = match self.flavor {
+            Flavor::Rust | Flavor::Pcre => "\\.+*?()|[]{}^$",
+            Flavor::Posix => "\\.+*?()|[{^$",
+        };
+        if special.contains(ch) {
+            Atom::new(format!("\\{}", ch))
+        } else {
+            Atom::new(ch)
+        }
+    }
+
+    /// A bracket expression for the classes, or `None` if they can't match anything
+    fn class(&self, classes: &[CharacterClass]) -> Option<Atom> {
+        // Separators are never part of a component, and backwards ranges match nothing
+        let mut ranges = vec![];
+        for class in classes {
+            let (start, end) = match *class {
+                CharacterClass::Single(ch) => (ch, ch),
+                CharacterClass::Range(start, end) => (start, end),
+            };
+            if start > end {
+                continue;
+            }
+            if (start..=end).contains(&'/') {
+                if start < '/' {
+                    ranges.push((start, '.'));
+                }
+                if end > '/' {
+                    ranges.push(('0', end));
+                }
+            } else {
+                ranges.push((start, end));
+            }
+        }
+        ranges.sort();
+        ranges.dedup();
+        match ranges[..] {
+            [] => return None,
+            [(start, end)] if start == end => return Some(self.literal(start)),
+            _ => (),
+        }
+
+        let mut text = String::from("[");
+        match self.flavor {
+            Flavor::Rust | Flavor::Pcre => {
+                let escape = |text: &mut String, ch: char| {
+                    if "\\[]^-&~".contains(ch) {
+                        text.push('\\');
+                    }
+                    text.push(ch);
+                };
+                for (start, end) in ranges {
+                    escape(&mut text, start);
+                    if end != start {
+                        text.push('-');
+                        escape(&mut text, end);
+                    }
+                }
+            }
+            // Bracket expressions have no escapes, so the characters that mean something have to
+            // go where they don't
+            Flavor::Posix => {
+                const SPECIAL: &str = "[]^-";
+                let mut specials = vec![];
+                let mut plain = String::new();
+                for (mut start, mut end) in ranges {
+                    while start <= end && SPECIAL.contains(start) {
+                        specials.push(start);
+                        start = char::from_u32(start as u32 + 1).unwrap();
+                    }
+                    while start <= end && SPECIAL.contains(end) {
+                        specials.push(end);
+                        end = char::from_u32(end as u32 - 1).unwrap();
+                    }
+                    if start < end {
+                        plain.push(start);
+                        plain.push('-');
+                        plain.push(end);
+                    } else if start == end {
+                        plain.push(start);
+                    }
+                }
+                if specials.contains(&']') {
+                    text.push(']');
+                }
+                text.push_str(&plain);
+                if specials.contains(&'[') {
+                    text.push('[');
+                }
+                let mut dash = specials.contains(&'-');
+                if specials.contains(&'^') {
+                    if text == "[" && dash {
+                        text.push('-');
+                        dash = false;
+                    }
+                    text.push('^');
+                }
+                if dash {
+                    text.push('-');
+                }
+            }
+        }
+        text.push(']');
+        Some(Atom::new(text))
+    }
+}
@@ -0,0 +1,1143 @@
+//! Imports regexes that match paths as glob patterns
+//!
+//! Only part of the regex syntax is understood: literals, `.`, character classes, the `*`, `+`,
+//! `?` and `{m,n}` quantifiers, and groups with alternatives, with `^` and `$` at the ends. The
+//! regex is taken to match a whole path written with `/` separators, and it's assumed that:
+//!
+//! - Paths are normalized, with no empty components or trailing separator, and have at least one
+//!   component that isn't the root.
+//! - Paths are relative, unless the regex starts with `^/`. A `/` that only sometimes starts the
+//!   path is reported.
+//! - `.` matches any character, even a newline.
+//!
+//! A glob can only repeat any character (`*`) or whole components (`**`) without a limit, so
+//! those are the only things that can be repeated without one in the regex. Anything that can't
+//! be imported is reported, rather than turned into a pattern that matches different paths.
+
+use std::ops::{Range, RangeInclusive};
+
+use crate::parser::{AstNode, CharacterClass, Pattern, Span};
+
+use super::{Position, Unsupported, MAX_UNROLLED, POSITIONS};
+
+const MAX_CHAR: u32 = char::MAX as u32;
+const SLASH: u32 = '/' as u32;
+const SURROGATES: RangeInclusive<u32> = 0xd800..=0xdfff;
+
+/// A set of characters, as ranges in order that don't overlap or touch
+type Ranges = Vec<(u32, u32)>;
+
+/// The sequences of pieces a regex can match, one for each alternative
+type Alternatives = Vec<Vec<Piece>>;
+
+/// Part of a regex, with how many times it repeats
+struct Piece {
+    item: Item,
+    /// Where the item came from in the regex, without its quantifier
+    item_span: Span,
+    span: Span,
+    min: u32,
+    max: Option<u32>,
+}
+
+enum Item {
+    /// Characters in a row without quantifiers, or a `/` on its own
+    Literal(String),
+    Set(Ranges),
+    Group(Alternatives),
+    StartAnchor,
+    EndAnchor,
+}
+
+/// Import a regex as a glob pattern, or explain every part of it that can't be
+///
+/// The spans in the pattern, and in anything unsupported, are ranges of bytes in the regex.
+pub fn from_regex(regex: &str) -> Result<Pattern, Vec<Unsupported>> {
+    let mut parser = Parser {
+        regex,
+        position: 0,
+        unsupported: vec![],
+    };
+    let alternatives = parser.parse();
+    if !parser.unsupported.is_empty() {
+        return Err(parser.unsupported);
+    }
+
+    let mut translator = Translator::default();
+    let mut choices = vec![];
+    let mut roots = vec![];
+    for sequence in &alternatives {
+        let mut pieces = &sequence[..];
+        let start = pieces.first().map_or(0, |piece| piece.span.start);
+        let end = pieces.last().map_or(regex.len(), |piece| piece.span.end);
+        // A regex that isn't anchored can match anything before or after
+        let anchored_start = pieces
+            .first()
+            .is_some_and(|piece| piece.is(&Item::StartAnchor));
+        if anchored_start {
+            pieces = &pieces[1..];
+        }
+        let anchored_end = pieces
+            .last()
+            .is_some_and(|piece| piece.is(&Item::EndAnchor));
+        if anchored_end {
+            pieces = &pieces[..pieces.len() - 1];
+        }
+        if anchored_start
+            && pieces
+                .first()
+                .is_some_and(|piece| piece.is(&Item::Literal("/".into())))
+        {
+            roots.push(pieces[0].span.clone());
+            pieces = &pieces[1..];
+        } else if let Some(span) = anchored_start.then(|| optional_root(pieces)).flatten() {
+            translator.unsupported(
+                &span,
+                "a `/` at the start can only be the root if it's always there, since a pattern \
+                 can't match both absolute and relative paths",
+            );
+        }
+
+        if !anchored_start {
+            while pieces.first().is_some_and(Piece::is_any_directories) {
+                pieces = &pieces[1..];
+            }
+        }
+        let mut current = if anchored_start || pieces.first().is_some_and(Piece::is_dot_star) {
+            only(Position::Start, vec![])
+        } else {
+            dot_star(Position::Start, &(start..start))
+        };
+        current = translator.sequence(pieces, current);
+        if !anchored_end && !pieces.last().is_some_and(Piece::is_dot_star) {
+            current = translator.then(current, |_, from| dot_star(from, &(end..end)));
+        }
+        // Ending just after a separator would leave an empty component
+        choices.extend(current[Position::Inside as usize].take());
+    }
+    if !roots.is_empty() && roots.len() < alternatives.len() {
+        translator.unsupported(
+            &(0..regex.len()),
+            "either all of the alternatives have to start with `^/`, or none of them, since a \
+             pattern can't match both absolute and relative paths",
+        );
+    }
+    if !translator.unsupported.is_empty() {
+        return Err(translator.unsupported);
+    }
+
+    let Some(nodes) = choose(choices) else {
+        return Err(vec![Unsupported {
+            span: 0..regex.len(),
+            message: "the regex can't match any path".into(),
+        }]);
+    };
+    let root = roots.first().map(|span| (AstNode::RootDir, span.clone()));
+    let mut pattern = tidy(root.into_iter().chain(nodes).collect());
+    loosen_end(&mut pattern);
+    Ok(pattern)
+}
+
+/// The span of a `/` that only sometimes starts the path, or of the piece it's in
+fn optional_root(pieces: &[Piece]) -> Option<Span> {
+    // A `/` that's always first is the root, even with a quantifier
+    if let Some(first) = pieces.first() {
+        if matches!(&first.item, Item::Literal(string) if string == "/") && first.min > 0 {
+            return None;
+        }
+    }
+    optional_slash(pieces)
+}
+
+/// The span of a `/` that can come first in `pieces`, or of the piece it's in
+fn optional_slash(pieces: &[Piece]) -> Option<Span> {
+    for piece in pieces {
+        let (starts_with_slash, can_be_empty) = match &piece.item {
+            Item::Literal(string) => (string.starts_with('/'), false),
+            Item::Group(alternatives) => (
+                alternatives
+                    .iter()
+                    .any(|sequence| optional_slash(sequence).is_some()),
+                alternatives
+                    .iter()
+                    .any(|sequence| sequence.iter().all(|piece| piece.min == 0)),
+            ),
+            // A set is taken to match within a relative path, even if it includes `/`
+            Item::Set(_) => return None,
+            Item::StartAnchor | Item::EndAnchor => (false, false),
+        };
+        if starts_with_slash {
+            return Some(piece.span.clone());
+        }
+        if !can_be_empty && piece.min > 0 {
+            return None;
+        }
+    }
+    None
+}
+
+impl Piece {
+    /// Whether this is the item on its own, without a quantifier
+    fn is(&self, item: &Item) -> bool {
+        let same = match (&self.item, item) {
+            (Item::Literal(a), Item::Literal(b)) => a == b,
+            (Item::StartAnchor, Item::StartAnchor) | (Item::EndAnchor, Item::EndAnchor) => true,
+            _ => false,
+        };
+        same && self.is_plain()
+    }
+
+    /// The two literals as one, if they're both without quantifiers and neither is a `/`
+    fn join(a: &Piece, b: &Piece) -> Option<Piece> {
+        match (&a.item, &b.item) {
+            (Item::Literal(a_string), Item::Literal(b_string))
+                if a.is_plain() && b.is_plain() && a_string != "/" && b_string != "/" =>
+            {
+                Some(Piece {
+                    item: Item::Literal(format!("{}{}", a_string, b_string)),
+                    item_span: a.item_span.start..b.item_span.end,
+                    span: a.span.start..b.span.end,
+                    min: 1,
+                    max: Some(1),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn is_plain(&self) -> bool {
+        (self.min, self.max) == (1, Some(1))
+    }
+
+    /// Whether this is `(.*/)?` or `(.*/)*`, which doesn't add anything to a `.*` before it
+    fn is_any_directories(&self) -> bool {
+        let Item::Group(alternatives) = &self.item else {
+            return false;
+        };
+        self.min == 0
+            && matches!(&alternatives[..], [sequence] if matches!(
+                &sequence[..],
+                [any, slash] if any.is_dot_star() && slash.is(&Item::Literal("/".into()))
+            ))
+    }
+
+    fn is_dot_star(&self) -> bool {
+        matches!(&self.item, Item::Set(ranges) if *ranges == [(0, MAX_CHAR)])
+            && (self.min, self.max) == (0, None)
+    }
+}
+
+struct Parser<'a> {
+    regex: &'a str,
+    /// How many bytes have been parsed
+    position: usize,
+    unsupported: Vec<Unsupported>,
+}
+
+impl Parser<'_> {
+    fn parse(&mut self) -> Alternatives {
+        let alternatives = self.alternatives();
+        // Only a `)` stops the alternatives before the end
+        if self.position < self.regex.len() {
+            self.unsupported(
+                self.position..self.position + 1,
+                "this `)` doesn't close a group",
+            );
+        }
+        alternatives
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.regex[self.position..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let ch = self.peek()?;
+        self.position += ch.len_utf8();
+        Some(ch)
+    }
+
+    fn eat(&mut self, string: &str) -> bool {
+        let found = self.regex[self.position..].starts_with(string);
+        if found {
+            self.position += string.len();
+        }
+        found
+    }
+
+    fn unsupported(&mut self, span: Span, message: impl Into<String>) {
+        self.unsupported.push(Unsupported {
+            span,
+            message: message.into(),
+        });
+    }
+
+    fn alternatives(&mut self) -> Alternatives {
+        let mut alternatives = vec![self.sequence()];
+        while self.eat("|") {
+            alternatives.push(self.sequence());
+        }
+        alternatives
+    }
+
+    fn sequence(&mut self) -> Vec<Piece> {
+        let mut pieces = vec![];
+        while !matches!(self.peek(), None | Some('|' | ')')) {
+            let start = self.position;
+            let item = self.item();
+            let item_span = start..self.position;
+            let (min, max) = self.quantifier();
+            let Some(item) = item else {
+                continue;
+            };
+            let piece = Piece {
+                item,
+                item_span,
+                span: start..self.position,
+                min,
+                max,
+            };
+            // Literals are kept together, so they aren't split up when choices are factored
+            if let Some(last) = pieces.last_mut() {
+                if let Some(joined) = Piece::join(last, &piece) {
+                    *last = joined;
+                    continue;
+                }
+            }
+            pieces.push(piece);
+        }
+        pieces
+    }
+
+    /// Parse an item, or `None` if it isn't supported
+    fn item(&mut self) -> Option<Item> {
+        let start = self.position;
+        Some(match self.next()? {
+            '.' => Item::Set(vec![(0, MAX_CHAR)]),
+            '^' => Item::StartAnchor,
+            '$' => Item::EndAnchor,
+            '[' => Item::Set(self.class(start)?),
+            '(' => Item::Group(self.group(start)?),
+            '\\' => Item::Literal(self.escape(start)?.into()),
+            '*' | '+' | '?' => {
+                self.unsupported(start..self.position, "there's nothing here to repeat");
+                return None;
+            }
+            '{' => {
+                self.position = start;
+                let message = if self.count().is_some() {
+                    "there's nothing here to repeat"
+                } else {
+                    self.next();
+                    "a `{` that isn't a repeat count has to be escaped"
+                };
+                self.unsupported(start..self.position, message);
+                return None;
+            }
+            ch => Item::Literal(ch.into()),
+        })
+    }
+
+    fn quantifier(&mut self) -> (u32, Option<u32>) {
+        let start = self.position;
+        let (min, max) = if self.eat("*") {
+            (0, None)
+        } else if self.eat("+") {
+            (1, None)
+        } else if self.eat("?") {
+            (0, Some(1))
+        } else if let Some(count) = self.count() {
+            count
+        } else {
+            return (1, Some(1));
+        };
+        // Being lazy doesn't change which whole paths match, but being possessive does
+        if !self.eat("?") && self.eat("+") {
+            self.unsupported(
+                start..self.position,
+                "possessive quantifiers can't be written as a glob",
+            );
+        }
+        if max.is_some_and(|max| max < min) {
+            self.unsupported(start..self.position, "this repeat count goes backwards");
+        }
+        (min, max)
+    }
+
+    /// Parse a repeat count like `{2}`, `{2,}` or `{2,5}`, if there is one
+    fn count(&mut self) -> Option<(u32, Option<u32>)> {
+        let rest = &self.regex[self.position..];
+        let inner = rest.strip_prefix('{')?.split('}').next()?;
+        let (min, max) = match inner.split_once(',') {
+            Some((min, max)) => (min, Some(max)),
+            None => (inner, None),
+        };
+        let is_number =
+            |string: &str| !string.is_empty() && string.bytes().all(|b| b.is_ascii_digit());
+        if !is_number(min) || max.is_some_and(|max| !max.is_empty() && !is_number(max)) {
+            return None;
+        }
+        let start = self.position;
+        self.position += inner.len() + 2;
+        let number = |string: &str| string.parse::<u32>().ok();
+        let count = match max {
+            None => number(min).map(|min| (min, Some(min))),
+            Some("") => number(min).map(|min| (min, None)),
+            Some(max) => number(min)
+                .zip(number(max))
+                .map(|(min, max)| (min, Some(max))),
+        };
+        if count.is_none() {
+            self.unsupported(start..self.position, "this repeat count is too large");
+        }
+        Some(count.unwrap_or((1, Some(1))))
+    }
+
+    /// Parse what comes after a `\`, or `None` if it isn't a character
+    fn escape(&mut self, start: usize) -> Option<char> {
+        let Some(ch) = self.next() else {
+            self.unsupported(start..self.position, "the regex ends with a `\\`");
+            return None;
+        };
+        let message = match ch {
+            ch if ch.is_ascii_punctuation() => return Some(ch),
+            'n' => return Some('\n'),
+            't' => return Some('\t'),
+            'r' => return Some('\r'),
+            'x' => match self.hex() {
+                Some(ch) => return Some(ch),
+                None => "`\\x` has to be followed by a character code, like `\\x41` or `\\x{41}`",
+            },
+            'd' | 'D' | 'w' | 'W' | 's' | 'S' => {
+                "`\\d`, `\\w` and `\\s` match different characters in different flavors, so the \
+                 class has to be written out, like `[0-9]`"
+            }
+            'p' | 'P' => {
+                if self.peek() == Some('{') {
+                    self.position += self.regex[self.position..]
+                        .find('}')
+                        .map_or(0, |end| end + 1);
+                } else {
+                    self.next();
+                }
+                "Unicode classes can't be written as a glob"
+            }
+            'b' | 'B' | 'A' | 'z' | 'Z' | 'G' => "only `^` and `$` are supported as anchors",
+            '1'..='9' => "backreferences can't be written as a glob",
+            _ => "unknown escape",
+        };
+        self.unsupported(start..self.position, message);
+        None
+    }
+
+    /// Parse the code in `\x41` or `\x{41}`
+    fn hex(&mut self) -> Option<char> {
+        let rest = &self.regex[self.position..];
+        let (digits, len) = match rest.strip_prefix('{') {
+            Some(braced) => {
+                let digits = &braced[..braced.find('}')?];
+                (digits, digits.len() + 2)
+            }
+            None => (rest.get(..2)?, 2),
+        };
+        if !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        let ch = u32::from_str_radix(digits, 16)
+            .ok()
+            .and_then(char::from_u32)?;
+        self.position += len;
+        Some(ch)
+    }
+
+    /// Parse a class after its `[`, or `None` if it isn't supported
+    fn class(&mut self, start: usize) -> Option<Ranges> {
+        let negated = self.eat("^");
+        let mut ranges = vec![];
+        let mut supported = true;
+        let mut first = true;
+        loop {
+            let item_start = self.position;
+            let Some(ch) = self.next() else {
+                self.unsupported(start..self.position, "this class is never closed");
+                return None;
+            };
+            let is_first = std::mem::replace(&mut first, false);
+            let low = match ch {
+                ']' if !is_first => break,
+                '[' => {
+                    let message = if self.peek() == Some(':') {
+                        let end = self.regex[self.position..]
+                            .find(":]")
+                            .map_or(0, |end| end + 2);
+                        self.position += end;
+                        "POSIX classes like `[:alpha:]` can't be written as a glob"
+                    } else {
+                        "a `[` in a class means different things in different flavors, so it has \
+                         to be escaped"
+                    };
+                    self.unsupported(item_start..self.position, message);
+                    supported = false;
+                    continue;
+                }
+                '&' | '-' | '~' if !first && self.peek() == Some(ch) => {
+                    self.next();
+                    self.unsupported(
+                        item_start..self.position,
+                        "only some flavors have operations on classes, so this has to be escaped",
+                    );
+                    supported = false;
+                    continue;
+                }
+                '\\' => match self.escape(item_start) {
+                    Some(ch) => ch,
+                    None => {
+                        supported = false;
+                        continue;
+                    }
+                },
+                ch => ch,
+            };
+            let rest = &self.regex[self.position..];
+            let high = if rest.starts_with('-') && !rest.starts_with("-]") && rest.len() > 1 {
+                self.next();
+                let end_start = self.position;
+                match self.next() {
+                    Some('\\') => match self.escape(end_start) {
+                        Some(ch) => ch,
+                        None => {
+                            supported = false;
+                            continue;
+                        }
+                    },
+                    Some(ch) => ch,
+                    None => continue,
+                }
+            } else {
+                low
+            };
+            if high < low {
+                self.unsupported(item_start..self.position, "this range goes backwards");
+                supported = false;
+            }
+            ranges.push((low as u32, high as u32));
+        }
+        let ranges = normalize(ranges);
+        supported.then(|| if negated { complement(&ranges) } else { ranges })
+    }
+
+    /// Parse a group after its `(`, or `None` if it isn't supported
+    fn group(&mut self, start: usize) -> Option<Alternatives> {
+        let mut supported = true;
+        if self.eat("?") {
+            let named = self.eat("P<")
+                || (!self.regex[self.position..].starts_with("<=")
+                    && !self.regex[self.position..].starts_with("<!")
+                    && self.eat("<"));
+            if named {
+                let end = self.regex[self.position..]
+                    .find('>')
+                    .map_or(0, |end| end + 1);
+                self.position += end;
+            } else if !self.eat(":") {
+                let lookaround = ["=", "!", "<=", "<!"].iter().any(|kind| self.eat(kind));
+                let message = if lookaround {
+                    "lookarounds can't be written as a glob"
+                } else {
+                    // Flags for the rest of the group, or for the group after the `:`
+                    while !matches!(self.peek(), None | Some(':' | ')')) {
+                        self.next();
+                    }
+                    if self.eat(")") {
+                        self.unsupported(
+                            start..self.position,
+                            "flags change how the regex matches, which can't be written as a glob",
+                        );
+                        return None;
+                    }
+                    self.eat(":");
+                    "flags change how the regex matches, which can't be written as a glob"
+                };
+                self.unsupported(start..self.position, message);
+                supported = false;
+            }
+        }
+        let alternatives = self.alternatives();
+        if !self.eat(")") {
+            self.unsupported(start..self.position, "this group is never closed");
+            return None;
+        }
+        supported.then_some(alternatives)
+    }
+}
+
+/// Sort ranges and join up the ones that overlap or touch
+fn normalize(mut ranges: Ranges) -> Ranges {
+    ranges.sort();
+    let mut out: Ranges = vec![];
+    for (low, high) in ranges {
+        match out.last_mut() {
+            Some(last) if low <= last.1.saturating_add(1) => last.1 = last.1.max(high),
+            _ => out.push((low, high)),
+        }
+    }
+    out
+}
+
+fn complement(ranges: &Ranges) -> Ranges {
+    let mut out = vec![];
+    let mut next = 0;
+    for &(low, high) in ranges {
+        if low > next {
+            out.push((next, low - 1));
+        }
+        next = high + 1;
+    }
+    if next <= MAX_CHAR {
+        out.push((next, MAX_CHAR));
+    }
+    out
+}
+
+/// Split a set into the characters that can be part of a component, and whether it has `/`
+fn split_slash(ranges: &Ranges) -> (Ranges, bool) {
+    let mut out = vec![];
+    let mut slash = false;
+    for &(low, high) in ranges {
+        if (low..=high).contains(&SLASH) {
+            slash = true;
+            if low < SLASH {
+                out.push((low, SLASH - 1));
+            }
+            if high > SLASH {
+                out.push((SLASH + 1, high));
+            }
+        } else {
+            out.push((low, high));
+        }
+    }
+    (out, slash)
+}
+
+fn any_component_character() -> Ranges {
+    vec![(0, SLASH - 1), (SLASH + 1, MAX_CHAR)]
+}
+
+/// A node matching any one of a set of characters that can be in a component
+fn characters(ranges: &Ranges) -> AstNode {
+    if *ranges == any_component_character() {
+        return AstNode::AnyCharacter;
+    }
+    let classes: Vec<_> = ranges
+        .iter()
+        .filter_map(|&(low, high)| {
+            // Complements can start or end in the middle of the surrogates, which aren't chars
+            let low = if SURROGATES.contains(&low) {
+                0xe000
+            } else {
+                low
+            };
+            let high = if SURROGATES.contains(&high) {
+                0xd7ff
+            } else {
+                high
+            };
+            match (char::from_u32(low)?, char::from_u32(high)?) {
+                (low, high) if low == high => Some(CharacterClass::Single(low)),
+                (low, high) if low < high => Some(CharacterClass::Range(low, high)),
+                _ => None,
+            }
+        })
+        .collect();
+    match classes[..] {
+        [CharacterClass::Single(ch)] => AstNode::LiteralString(ch.to_string().into_bytes()),
+        _ => AstNode::Characters(classes),
+    }
+}
+
+/// The characters a piece matches, if it's a single character on its own
+fn single_set(item: &Item) -> Option<Ranges> {
+    match item {
+        Item::Literal(string) => {
+            let mut chars = string.chars();
+            match (chars.next(), chars.next()) {
+                (Some(ch), None) => Some(vec![(ch as u32, ch as u32)]),
+                _ => None,
+            }
+        }
+        Item::Set(ranges) => Some(ranges.clone()),
+        Item::Group(alternatives) => match &alternatives[..] {
+            [sequence] => match &sequence[..] {
+                [piece] if (piece.min, piece.max) == (1, Some(1)) => single_set(&piece.item),
+                _ => None,
+            },
+            _ => None,
+        },
+        Item::StartAnchor | Item::EndAnchor => None,
+    }
+}
+
+/// The pattern for a way through part of a regex, with the spans of the regex each node came from
+type Nodes = Vec<(AstNode, Span)>;
+
+/// The patterns for the ways through part of a regex, indexed by the position they end at, or
+/// `None` if there's no way to end there
+type Ends = [Option<Nodes>; 2];
+
+fn only(position: Position, nodes: Nodes) -> Ends {
+    let mut ends = [None, None];
+    ends[position as usize] = Some(nodes);
+    ends
+}
+
+/// The only position there's a way to end at, if there's exactly one
+fn single_end(ends: &Ends) -> Option<Position> {
+    match ends {
+        [Some(_), None] => Some(Position::Start),
+        [None, Some(_)] => Some(Position::Inside),
+        _ => None,
+    }
+}
+
+/// Add a way through that matches nothing
+fn optional(mut ends: Ends, from: Position) -> Ends {
+    let mut choices = vec![vec![]];
+    choices.extend(ends[from as usize].take());
+    ends[from as usize] = choose(choices);
+    ends
+}
+
+fn concat(before: &Nodes, after: &Nodes) -> Nodes {
+    before.iter().chain(after).cloned().collect()
+}
+
+fn same_nodes(nodes: &[(AstNode, Span)], expected: &[AstNode]) -> bool {
+    nodes.iter().map(|(node, _)| node).eq(expected)
+}
+
+/// `.*`, which can carry on into any number of components
+fn dot_star(from: Position, span: &Span) -> Ends {
+    use AstNode::{AnyCharacter, Recurse, Separator, Wildcard};
+    let nodes = |nodes: &[AstNode]| -> Nodes {
+        nodes
+            .iter()
+            .map(|node| (node.clone(), span.clone()))
+            .collect()
+    };
+    match from {
+        Position::Start => [
+            Some(nodes(&[Recurse, Separator])),
+            Some(nodes(&[Recurse, Separator, AnyCharacter, Wildcard])),
+        ],
+        Position::Inside => [
+            Some(nodes(&[Wildcard, Separator, Recurse, Separator])),
+            choose(vec![
+                nodes(&[Wildcard]),
+                nodes(&[
+                    Wildcard,
+                    Separator,
+                    Recurse,
+                    Separator,
+                    AnyCharacter,
+                    Wildcard,
+                ]),
+            ]),
+        ],
+    }
+}
+
+/// A pattern matching any of the choices, with the nodes they all start and end with factored
+/// out
+fn choose(choices: Vec<Nodes>) -> Option<Nodes> {
+    let mut choices = unique(choices);
+    if choices.len() <= 1 {
+        return choices.pop();
+    }
+
+    let same = |a: &(AstNode, Span), b: &(AstNode, Span)| a.0 == b.0;
+    let shortest = choices.iter().map(Vec::len).min().unwrap_or(0);
+    let prefix_len = (0..shortest)
+        .take_while(|index| {
+            choices
+                .iter()
+                .all(|choice| same(&choice[*index], &choices[0][*index]))
+        })
+        .count();
+    let suffix_len = (0..shortest - prefix_len)
+        .take_while(|index| {
+            let node = &choices[0][choices[0].len() - 1 - index];
+            choices
+                .iter()
+                .all(|choice| same(&choice[choice.len() - 1 - index], node))
+        })
+        .count();
+    let mut out = choices[0][..prefix_len].to_vec();
+    let suffix = choices[0][choices[0].len() - suffix_len..].to_vec();
+
+    let middles: Vec<Nodes> = choices
+        .into_iter()
+        .map(|choice| choice[prefix_len..choice.len() - suffix_len].to_vec())
+        .collect();
+    let mut middles = unique(simplify(middles));
+    if middles.len() == 1 {
+        out.extend(middles.pop().unwrap());
+    } else {
+        let spans = middles.iter().flatten().map(|(_, span)| span);
+        let start = spans.clone().map(|span| span.start).min().unwrap_or(0);
+        let end = spans.map(|span| span.end).max().unwrap_or(start);
+        let choices = middles.into_iter().map(pattern).collect();
+        out.push((AstNode::Alternatives { choices }, start..end));
+    }
+    out.extend(suffix);
+    Some(out)
+}
+
+/// Remove choices with the same nodes as an earlier one
+fn unique(choices: Vec<Nodes>) -> Vec<Nodes> {
+    let mut unique: Vec<Nodes> = vec![];
+    for choice in choices {
+        let nodes = choice
+            .iter()
+            .map(|(node, _)| node.clone())
+            .collect::<Vec<_>>();
+        if !unique.iter().any(|other| same_nodes(other, &nodes)) {
+            unique.push(choice);
+        }
+    }
+    unique
+}
+
+/// Merge choices that can be written as one, where one of them is another followed by something
+/// that can also match nothing
+fn simplify(middles: Vec<Nodes>) -> Vec<Nodes> {
+    use AstNode::{Alternatives, AnyCharacter, Recurse, Separator, Wildcard};
+    let mut middles = unique(
+        middles
+            .into_iter()
+            .flat_map(|middle| match &middle[..] {
+                [(Alternatives { choices }, _)] => choices.iter().map(pattern_nodes).collect(),
+                _ => vec![middle],
+            })
+            .collect(),
+    );
+    let rewrites: [(&[AstNode], Range<usize>); 3] = [
+        (&[AnyCharacter, Wildcard], 1..2),
+        (&[Wildcard], 0..1),
+        // Only ever reached at the start of a component
+        (
+            &[Recurse, Separator, AnyCharacter, Wildcard, Separator],
+            0..2,
+        ),
+    ];
+    loop {
+        let merge = middles.iter().enumerate().find_map(|(index, middle)| {
+            rewrites.iter().find_map(|(tail, keep)| {
+                let split = middle.len().checked_sub(tail.len())?;
+                if !same_nodes(&middle[split..], tail) {
+                    return None;
+                }
+                let base: Vec<AstNode> = middle[..split]
+                    .iter()
+                    .map(|(node, _)| node.clone())
+                    .collect();
+                let other = middles.iter().position(|other| same_nodes(other, &base))?;
+                let mut merged = middle[..split].to_vec();
+                merged.extend_from_slice(&middle[split..][keep.clone()]);
+                Some((index, other, merged))
+            })
+        });
+        let Some((index, other, merged)) = merge else {
+            return middles;
+        };
+        middles[index] = merged;
+        middles.remove(other);
+    }
+}
+
+/// At the end of a pattern, `/?*` can be `/*`, since a separator needs something after it
+fn loosen_end(pattern: &mut Pattern) {
+    use AstNode::{AnyCharacter, Separator, Wildcard};
+    let len = pattern.nodes.len();
+    if let Some(AstNode::Alternatives { choices }) = pattern.nodes.last_mut() {
+        choices.iter_mut().for_each(loosen_end);
+    } else if matches!(
+        pattern.nodes[..],
+        [.., Separator | AstNode::RootDir, AnyCharacter, Wildcard]
+    ) {
+        pattern.nodes.remove(len - 2);
+        pattern.spans.remove(len - 2);
+    }
+}
+
+fn pattern_nodes(pattern: &Pattern) -> Nodes {
+    pattern
+        .nodes
+        .iter()
+        .cloned()
+        .zip(pattern.spans.iter().cloned())
+        .collect()
+}
+
+fn pattern(nodes: Nodes) -> Pattern {
+    let mut pattern = Pattern::default();
+    for (node, span) in nodes {
+        pattern.push(node, span);
+    }
+    pattern
+}
+
+/// Join up literals that ended up next to each other, and wildcards that would read as `**`
+fn tidy(nodes: Nodes) -> Pattern {
+    let tidy_pattern = |pattern: Pattern| tidy(pattern_nodes(&pattern));
+    let mut out = Pattern::default();
+    for (node, span) in nodes {
+        let node = match node {
+            AstNode::Alternatives { choices } => AstNode::Alternatives {
+                choices: choices.into_iter().map(tidy_pattern).collect(),
+            },
+            AstNode::Repeat { min, max, pattern } => AstNode::Repeat {
+                min,
+                max,
+                pattern: tidy_pattern(pattern),
+            },
+            node => node,
+        };
+        match (out.nodes.last_mut(), node) {
+            (Some(AstNode::LiteralString(before)), AstNode::LiteralString(after)) => {
+                before.extend(after);
+            }
+            (Some(AstNode::Wildcard), AstNode::Wildcard) => (),
+            (_, node) => {
+                out.push(node, span);
+                continue;
+            }
+        }
+        let last = out.spans.last_mut().unwrap();
+        *last = last.start.min(span.start)..last.end.max(span.end);
+    }
+    out
+}
+
+fn contains_repeat(node: &AstNode) -> bool {
+    match node {
+        AstNode::Repeat { .. } => true,
+        AstNode::Alternatives { choices } => choices
+            .iter()
+            .any(|choice| choice.nodes.iter().any(contains_repeat)),
+        _ => false,
+    }
+}
+
+#[derive(Default)]
+struct Translator {
+    unsupported: Vec<Unsupported>,
+}
+
+impl Translator {
+    fn unsupported(&mut self, span: &Span, message: impl Into<String>) {
+        // Parts of the regex are translated once for each position they can start at
+        let unsupported = Unsupported {
+            span: span.clone(),
+            message: message.into(),
+        };
+        if !self.unsupported.contains(&unsupported) {
+            self.unsupported.push(unsupported);
+        }
+    }
+
+    fn alternatives(&mut self, alternatives: &Alternatives, from: Position) -> Ends {
+        let mut ends: [Vec<Nodes>; 2] = [vec![], vec![]];
+        for sequence in alternatives {
+            let after = self.sequence(sequence, only(from, vec![]));
+            for (to, nodes) in after.into_iter().enumerate() {
+                ends[to].extend(nodes);
+            }
+        }
+        ends.map(choose)
+    }
+
+    /// Translate a sequence of pieces, carrying on from each of the ways through what came before
+    fn sequence(&mut self, pieces: &[Piece], before: Ends) -> Ends {
+        let mut current = before;
+        for piece in pieces {
+            current = self.then(current, |translator, from| translator.piece(piece, from));
+        }
+        current
+    }
+
+    /// Carry on from each of the ways through what came before, with the ways through what comes
+    /// next from where they end
+    fn then(&mut self, current: Ends, mut next: impl FnMut(&mut Self, Position) -> Ends) -> Ends {
+        let mut choices: [Vec<Nodes>; 2] = [vec![], vec![]];
+        for from in POSITIONS {
+            let Some(before) = &current[from as usize] else {
+                continue;
+            };
+            let after = next(self, from);
+            for to in POSITIONS {
+                if let Some(nodes) = &after[to as usize] {
+                    choices[to as usize].push(concat(before, nodes));
+                }
+            }
+        }
+        choices.map(choose)
+    }
+
+    fn piece(&mut self, piece: &Piece, from: Position) -> Ends {
+        match piece.max {
+            Some(max) => self.repeat(piece, piece.min, max, from),
+            None => {
+                let before = self.repeat(piece, piece.min, piece.min, from);
+                self.then(before, |translator, from| translator.star(piece, from))
+            }
+        }
+    }
+
+    /// One pass of the piece's item
+    fn item(&mut self, piece: &Piece, from: Position) -> Ends {
+        let span = &piece.item_span;
+        match &piece.item {
+            Item::Literal(string) if string == "/" => self.set(&vec![(SLASH, SLASH)], span, from),
+            Item::Literal(string) => only(
+                Position::Inside,
+                vec![(
+                    AstNode::LiteralString(string.clone().into_bytes()),
+                    span.clone(),
+                )],
+            ),
+            Item::Set(ranges) => self.set(ranges, span, from),
+            Item::Group(alternatives) => self.alternatives(alternatives, from),
+            Item::StartAnchor | Item::EndAnchor => {
+                self.unsupported(
+                    span,
+                    "`^` and `$` are only supported at the start and end of the regex",
+                );
+                only(from, vec![])
+            }
+        }
+    }
+
+    fn set(&mut self, ranges: &Ranges, span: &Span, from: Position) -> Ends {
+        let (component, slash) = split_slash(ranges);
+        let mut ends = [None, None];
+        // Components can't be empty, so a separator can't start one
+        if slash && from == Position::Inside {
+            ends[Position::Start as usize] = Some(vec![(AstNode::Separator, span.clone())]);
+        }
+        if !component.is_empty() {
+            ends[Position::Inside as usize] = Some(vec![(characters(&component), span.clone())]);
+        }
+        ends
+    }
+
+    /// Between `min` and `max` passes of the piece's item
+    fn repeat(&mut self, piece: &Piece, min: u32, max: u32, from: Position) -> Ends {
+        if max == 0 {
+            return only(from, vec![]);
+        }
+        let bodies = POSITIONS.map(|start| self.item(piece, start));
+        if max == 1 {
+            let ends = bodies[from as usize].clone();
+            return if min == 0 { optional(ends, from) } else { ends };
+        }
+        // A body that does the same wherever it starts, and always ends up in the same place, can
+        // be repeated by the pattern
+        if let (true, Some(to)) = (bodies[0] == bodies[1], single_end(&bodies[0])) {
+            let body = bodies[0][to as usize].clone().unwrap();
+            // Counters aren't reset, so repeats can't be nested
+            if !body.iter().any(|(node, _)| contains_repeat(node)) {
+                let node = AstNode::Repeat {
+                    // The matcher checks the counter after each pass, so there's always one
+                    min: min.max(1),
+                    max,
+                    pattern: pattern(body),
+                };
+                let ends = only(to, vec![(node, piece.span.clone())]);
+                return if min == 0 { optional(ends, from) } else { ends };
+            }
+        }
+        if max > MAX_UNROLLED {
+            self.unsupported(
+                &piece.span,
+                format!(
+                    "a repeat in a pattern can't match the same paths as this, so it can only be \
+                     repeated up to {} times",
+                    MAX_UNROLLED
+                ),
+            );
+            return only(from, vec![]);
+        }
+
+        let mut current = only(from, vec![]);
+        for passes in 0..max {
+            let mut next: [Vec<Nodes>; 2] = [vec![], vec![]];
+            for position in POSITIONS {
+                let Some(before) = &current[position as usize] else {
+                    continue;
+                };
+                if passes >= min {
+                    next[position as usize].push(before.clone());
+                }
+                for to in POSITIONS {
+                    if let Some(nodes) = &bodies[position as usize][to as usize] {
+                        next[to as usize].push(concat(before, nodes));
+                    }
+                }
+            }
+            current = next.map(choose);
+        }
+        current
+    }
+
+    /// Any number of passes of the piece's item, which only works for a few kinds of item
+    fn star(&mut self, piece: &Piece, from: Position) -> Ends {
+        use AstNode::{AnyCharacter, Recurse, Separator, Wildcard};
+        let span = &piece.span;
+        let nodes = |nodes: &[AstNode]| -> Nodes {
+            nodes
+                .iter()
+                .map(|node| (node.clone(), span.clone()))
+                .collect()
+        };
+        if let Some((component, slash)) = single_set(&piece.item).map(|set| split_slash(&set)) {
+            match (component == any_component_character(), slash, from) {
+                // Matching nothing at the start of a component doesn't start one
+                (true, false, Position::Start) => {
+                    return [Some(vec![]), Some(nodes(&[AnyCharacter, Wildcard]))]
+                }
+                (true, false, Position::Inside) => {
+                    return only(Position::Inside, nodes(&[Wildcard]))
+                }
+                (true, true, from) => return dot_star(from, span),
+                _ => (),
+            }
+        }
+        // Whole components, each followed by a separator
+        let whole_components = |ends: &Ends| match ends {
+            [Some(nodes), None] => {
+                same_nodes(nodes, &[AnyCharacter, Wildcard, Separator])
+                    || same_nodes(
+                        nodes,
+                        &[Recurse, Separator, AnyCharacter, Wildcard, Separator],
+                    )
+            }
+            _ => false,
+        };
+        if whole_components(&self.item(piece, Position::Start)) {
+            let directories = nodes(&[Recurse, Separator]);
+            match (from, self.item(piece, from)) {
+                (Position::Start, _) => return only(Position::Start, directories),
+                (Position::Inside, [Some(first), None]) => {
+                    return [Some(concat(&first, &directories)), Some(vec![])]
+                }
+                _ => (),
+            }
+        }
+        self.unsupported(
+            span,
+            "a glob can only repeat any character (`*`) or whole components (`**`) without a \
+             limit",
+        );
+        only(from, vec![])
+    }
+}
@@ -0,0 +1,403 @@
+use std::path::{Component, Path, PathBuf};
+
+use crate::{
+    compiler::compile,
+    generate::{GenerateOptions, Generator},
+    matcher::path_matches,
+    parser::{parse, AstNode, CharacterClass, Pattern},
+    regex::{from_regex, to_regex, Flavor, Unsupported},
+};
+
+fn translate(pattern: &str, flavor: Flavor) -> String {
+    to_regex(&parse(pattern), flavor).unwrap_or_else(|err| panic!("{}: {:?}", pattern, err))
+}
+
+fn classes(classes: Vec<CharacterClass>) -> Pattern {
+    let mut pattern = Pattern::default();
+    pattern.push(AstNode::Characters(classes), 0..1);
+    pattern
+}
+
+#[test]
+fn translations() {
+    for (pattern, rust, posix) in [
+        (
+            "src/**/*.rs",
+            r"^src/(?:[^/]+/)*[^/]*\.rs$",
+            r"^src/([^/]+/)*[^/]*\.rs$",
+        ),
+        (
+            "/usr/{bin,lib}/?",
+            r"^/usr/(?:bin|lib)/[^/]$",
+            r"^/usr/(bin|lib)/[^/]$",
+        ),
+        ("a/*/b", r"^a/(?:[^/]+/)?b$", r"^a/([^/]+/)?b$"),
+        (
+            "a**b",
+            r"^a(?:[^/]*/(?:[^/]+/)*)?b$",
+            r"^a([^/]*/([^/]+/)*)?b$",
+        ),
+        (
+            "<[a-c_]:2,4>.txt",
+            r"^[_a-c]{2,4}\.txt$",
+            r"^[_a-c]{2,4}\.txt$",
+        ),
+        ("<a:0,2>", r"^a{1,2}$", r"^a{1,2}$"),
+        ("<{,a}:0,5>", r"^(?:a(?:a?){0,4})?$", r"^(a(a?){0,4})?$"),
+        ("a+(b)", r"^a\+\(b\)$", r"^a\+\(b\)$"),
+    ] {
+        assert_eq!(translate(pattern, Flavor::Rust), rust, "{}", pattern);
+        // PCRE's `$` also matches before a trailing newline
+        let pcre = format!(r"{}\z", rust.strip_suffix('$').unwrap());
+        assert_eq!(translate(pattern, Flavor::Pcre), pcre, "{}", pattern);
+        assert_eq!(translate(pattern, Flavor::Posix), posix, "{}", pattern);
+    }
+}
+
+#[test]
+fn character_classes() {
+    let tricky = vec![
+        CharacterClass::Single(']'),
+        CharacterClass::Single('^'),
+        CharacterClass::Range('a', 'c'),
+        CharacterClass::Single('-'),
+    ];
+    for (classes, rust, posix) in [
+        (tricky, r"^[\-\]\^a-c]$", r"^[]a-c^-]$"),
+        (
+            vec![CharacterClass::Single('^'), CharacterClass::Single('-')],
+            r"^[\-\^]$",
+            r"^[-^]$",
+        ),
+        (
+            vec![CharacterClass::Range('[', '^')],
+            r"^[\[-\^]$",
+            r"^[]\[^]$",
+        ),
+        // Separators are left out, and so are backwards ranges
+        (
+            vec![
+                CharacterClass::Range('+', '0'),
+                CharacterClass::Range('z', 'x'),
+            ],
+            r"^[+-.0]$",
+            r"^[+-.0]$",
+        ),
+    ] {
+        let pattern = self::classes(classes);
+        assert_eq!(to_regex(&pattern, Flavor::Rust).unwrap(), rust);
+        assert_eq!(to_regex(&pattern, Flavor::Posix).unwrap(), posix);
+    }
+}
+
+#[test]
+fn regexes_match_the_same_paths() {
+    for pattern in [
+        "src/**/*.rs",
+        "**/*_test.go",
+        "a/*/b",
+        "a**b",
+        "a/{,x}/b",
+        "/usr/{bin,lib/*}/[a-f]?",
+        "./<[0-9]:2,3>/<x/:1,2>y",
+        "a/<*:1,2>/b",
+        "<{x/,y}:2,3>z",
+        "<{,a}:0,5>",
+        "<a/{,b}:3>",
+        "{a/,b}c",
+    ] {
+        let program = compile(&parse(pattern)).unwrap();
+        // None of these need anything that differs between the flavors, so they can all be
+        // checked with the same engine
+        let regexes = [Flavor::Rust, Flavor::Pcre, Flavor::Posix]
+            .map(|flavor| ::regex::Regex::new(&translate(pattern, flavor)).unwrap());
+        let mut generator = Generator::new(
+            &program,
+            GenerateOptions {
+                component_length: 0..=3,
+                seed: Some(7),
+                ..GenerateOptions::default()
+            },
+        );
+        for _ in 0..50 {
+            let paths = [generator.matching(), generator.non_matching()];
+            for path in paths.into_iter().flatten() {
+                let matches = path_matches(&path, &program).valid_as_complete_match;
+                for regex in &regexes {
+                    assert_eq!(
+                        regex.is_match(path.to_str().unwrap()),
+                        matches,
+                        "{} {} {}",
+                        pattern,
+                        regex,
+                        path.display()
+                    );
+                }
+            }
+        }
+        for path in ["a/b", "b", "ab", "src/a.rs", "x/y/z_test.go", "aaa"] {
+            let matches = path_matches(Path::new(path), &program).valid_as_complete_match;
+            assert_eq!(regexes[0].is_match(path), matches, "{} {}", pattern, path);
+        }
+    }
+}
+
+#[test]
+fn untranslatable_parts_are_reported() {
+    let unsupported = |pattern: &str, flavor| to_regex(&parse(pattern), flavor).unwrap_err();
+    assert_eq!(
+        unsupported("<<a:2>b:3>", Flavor::Rust),
+        [Unsupported {
+            span: 1..6,
+            message: "repeats inside repeats keep counting from one pass of the outer repeat to \
+                      the next, which a regex can't do"
+                .into()
+        }]
+    );
+    assert_eq!(
+        unsupported("x/<a:300>", Flavor::Posix),
+        [Unsupported {
+            span: 2..9,
+            message: "this flavor can only repeat something 255 times".into()
+        }]
+    );
+    assert_eq!(
+        to_regex(&classes(vec![CharacterClass::Single('/')]), Flavor::Rust).unwrap_err(),
+        [Unsupported {
+            span: 0..1,
+            message: "the pattern can't match any path".into()
+        }]
+    );
+    #[cfg(unix)]
+    {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+        let pattern = parse(OsStr::from_bytes(b"a/b\xff"));
+        assert_eq!(
+            to_regex(&pattern, Flavor::Rust).unwrap_err(),
+            [Unsupported {
+                span: 2..4,
+                message: "literals that aren't valid UTF-8 can't be in a regex".into()
+            }]
+        );
+    }
+}
+
+fn import(regex: &str) -> String {
+    from_regex(regex)
+        .unwrap_or_else(|err| panic!("{}: {:?}", regex, err))
+        .to_string()
+}
+
+#[test]
+fn imports() {
+    for (regex, pattern) in [
+        (r"^src/.*\.rs$", "src/**/*.rs"),
+        (r"\.(jpg|png)$", "**/*.{jpg,png}"),
+        (r"^(.*/)?test_[^/]*\.py$", "**/test_*.py"),
+        (r"^docs/[^/]+\.md$", "docs/?*.md"),
+        // Components can't be empty, so `[^/]*` between separators has to match something
+        (r"^a/[^/]*/b$", "a/?*/b"),
+        (r"^/etc/[^/]+$", "/etc/*"),
+        (r"^src/", "src/**/*"),
+        (r"^img[0-9]{2,4}\.png$", "img<[0-9]:2,4>.png"),
+        (r"^([^/]+/){2}x$", "<?*/:2>x"),
+        (r"^([^/]+/)*x$", "**/x"),
+        (r"^x(ab)?y{0,3}$", "x{,ab}{,<y:1,3>}"),
+        (r"^a.*b$", "a*{,/**/*}b"),
+        (r"^[]*{-]\.\.$", "[]*-*-{].."),
+        (r"^a/\./b$", "a/[.]/b"),
+    ] {
+        assert_eq!(import(regex), pattern, "{}", regex);
+    }
+}
+
+#[test]
+fn imported_patterns_match_the_same_paths() {
+    let components = [
+        "a",
+        "b",
+        "ab",
+        "x.rs",
+        ".rs",
+        "test_a.py",
+        "yy",
+        "node_modules",
+        "c.d",
+    ];
+    let mut paths: Vec<PathBuf> = vec![];
+    for a in components {
+        paths.push(a.into());
+        for b in components {
+            paths.push([a, b].iter().collect());
+            for c in ["a", "x.rs", "test_a.py", "node_modules"] {
+                paths.push([a, b, c].iter().collect());
+            }
+        }
+    }
+    for regex in [
+        r"^src/.*\.rs$",
+        r"\.rs$",
+        r"(.*/)?test_[^/]*\.py$",
+        r"(.*/)?node_modules/",
+        r"^a/[^/]*/b$",
+        r"^(a|b/){3}$",
+        r"^([^/]*/)*x\.rs$",
+        r"^(.*/)*(a|b)$",
+        r"a.*b",
+        r"^.*?[.][a-z]$",
+        r"^(a|ab|x\.)(rs|/a)?$",
+        r"^[^a/][^/]?(/[a-c]{1,2}){0,2}$",
+        r"^(a/|yy/)?.*\.(rs|py)$",
+        r"^a(b/|\.|/c)?.$",
+        r"^/a/.*",
+        r"^$|^a$",
+    ] {
+        let pattern = from_regex(regex).unwrap_or_else(|err| panic!("{}: {:?}", regex, err));
+        let programs = [
+            compile(&pattern).unwrap(),
+            compile(&parse(pattern.to_string())).unwrap(),
+        ];
+        let regex_match = ::regex::Regex::new(regex).unwrap();
+        let mut paths = paths.clone();
+        if regex.starts_with("^/") {
+            for path in &mut paths {
+                *path = Path::new("/").join(&*path);
+            }
+        }
+        for program in &programs {
+            let mut generator = Generator::new(
+                program,
+                GenerateOptions {
+                    component_length: 0..=3,
+                    seed: Some(7),
+                    ..GenerateOptions::default()
+                },
+            );
+            for _ in 0..50 {
+                paths.extend(
+                    [generator.matching(), generator.non_matching()]
+                        .into_iter()
+                        .flatten(),
+                );
+            }
+        }
+        for path in &paths {
+            // Only normalized UTF-8 paths with a component can be matched the same way
+            let Some(string) = path.to_str() else {
+                continue;
+            };
+            if !path
+                .components()
+                .any(|component| matches!(component, Component::Normal(_)))
+                || path
+                    .components()
+                    .any(|component| matches!(component, Component::CurDir | Component::ParentDir))
+            {
+                continue;
+            }
+            let expected = regex_match.is_match(string);
+            for program in &programs {
+                assert_eq!(
+                    path_matches(path, program).valid_as_complete_match,
+                    expected,
+                    "{} {} {}",
+                    regex,
+                    pattern,
+                    path.display()
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn unimportable_parts_are_reported() {
+    let unsupported = |regex: &str| {
+        from_regex(regex)
+            .unwrap_err()
+            .into_iter()
+            .map(|unsupported| (unsupported.span, unsupported.message))
+            .collect::<Vec<_>>()
+    };
+    let message = |message: &str| message.to_string();
+    assert_eq!(
+        unsupported(r"^a\d(?i)b$"),
+        [
+            (
+                2..4,
+                message(
+                    "`\\d`, `\\w` and `\\s` match different characters in different flavors, so \
+                     the class has to be written out, like `[0-9]`"
+                )
+            ),
+            (
+                4..8,
+                message("flags change how the regex matches, which can't be written as a glob")
+            ),
+        ]
+    );
+    assert_eq!(
+        unsupported(r"^[a-z]+$"),
+        [(
+            1..7,
+            message(
+                "a glob can only repeat any character (`*`) or whole components (`**`) without \
+                 a limit"
+            )
+        )]
+    );
+    assert_eq!(
+        unsupported(r"^(a|b/){20}$"),
+        [(
+            1..11,
+            message(
+                "a repeat in a pattern can't match the same paths as this, so it can only be \
+                 repeated up to 16 times"
+            )
+        )]
+    );
+    assert_eq!(
+        unsupported(r"^a|^/b"),
+        [(
+            0..6,
+            message(
+                "either all of the alternatives have to start with `^/`, or none of them, since \
+                 a pattern can't match both absolute and relative paths"
+            )
+        )]
+    );
+    let optional_root = message(
+        "a `/` at the start can only be the root if it's always there, since a pattern can't \
+         match both absolute and relative paths",
+    );
+    assert_eq!(unsupported(r"^/?a$"), [(1..3, optional_root.clone())]);
+    assert_eq!(unsupported(r"^x?/a$"), [(3..4, optional_root.clone())]);
+    assert_eq!(unsupported(r"^(/a|b)$"), [(1..7, optional_root)]);
+    assert_eq!(
+        unsupported(r"(a\1)|x(?=y)|\bz|[[:alpha:]]"),
+        [
+            (2..4, message("backreferences can't be written as a glob")),
+            (7..10, message("lookarounds can't be written as a glob")),
+            (13..15, message("only `^` and `$` are supported as anchors")),
+            (
+                18..27,
+                message("POSIX classes like `[:alpha:]` can't be written as a glob")
+            ),
+        ]
+    );
+    assert_eq!(
+        unsupported(r"a$b"),
+        [(
+            1..2,
+            message("`^` and `$` are only supported at the start and end of the regex")
+        )]
+    );
+    assert_eq!(
+        unsupported(r"^x/$"),
+        [(0..4, message("the regex can't match any path"))]
+    );
+    assert_eq!(
+        unsupported(r"(a"),
+        [(0..2, message("this group is never closed"))]
+    );
+}
@@ -0,0 +1,197 @@
+//! Checks that a program is well formed, so the matcher can run it without panicking or looping
+//! forever
+//!
+//! Compiled programs should always pass, so they are only checked in debug builds. Programs that
+//! come from anywhere else should be checked before they are run.
+
+use anyhow::bail;
+
+use crate::compiler::{CounterId, Instruction, Program, ProgramOffset};
+
+#[cfg(test)]
+mod tests;
+
+/// Check that every offset and counter in the program exists, that every instruction either
+/// moves on to another instruction or completes, and that there are no loops that can go around
+/// forever without consuming any of the path
+pub fn verify(program: &Program) -> anyhow::Result<()> {
+    let len = program.instructions.len();
+    if len == 0 {
+        bail!("the program has no instructions");
+    }
+    for (pc, instruction) in program.instructions.iter().enumerate() {
+        let (target, counter_id) = match instruction {
+            Instruction::Jump(target) | Instruction::Alternative(target) => (Some(target), None),
+            Instruction::Increment(counter_id) => (None, Some(counter_id)),
+            Instruction::BranchIfLessThan(target, counter_id, _) => {
+                (Some(target), Some(counter_id))
+            }
+            _ => (None, None),
+        };
+        if let Some(target) = target.filter(|target| target.0 >= len) {
+            bail!(
+                "{}: target {} is past the end of the program",
+                ProgramOffset(pc),
+                target
+            );
+        }
+        if let Some(counter_id) = counter_id.filter(|counter_id| counter_id.0 >= program.counters) {
+            bail!(
+                "{}: counter {} doesn't exist, there are only {}",
+                ProgramOffset(pc),
+                counter_id,
+                program.counters
+            );
+        }
+    }
+    if falls_through(&program.instructions[len - 1]) {
+        bail!(
+            "{}: the program can run past its end, it should finish with complete or a jump",
+            ProgramOffset(len - 1)
+        );
+    }
+
+    // A loop that doesn't consume anything can still finish, as long as it moves on to the next
+    // component each time around, which takes both a separator and something that loads a
+    // component. So look for loops without one or the other.
+    let without_separators = Graph::new(program, |instruction| {
+        matches!(instruction, Instruction::Separator)
+    });
+    let without_loads = Graph::new(program, |instruction| {
+        matches!(
+            instruction,
+            Instruction::AnyString | Instruction::LiteralString(_) | Instruction::SkipToLiteral(_)
+        )
+    });
+    for graph in [without_separators, without_loads] {
+        if let Some(pc) = graph.find_cycle() {
+            bail!(
+                "{}: the program can loop forever here without consuming anything",
+                ProgramOffset(pc)
+            );
+        }
+    }
+    Ok(())
+}
+
+fn falls_through(instruction: &Instruction) -> bool {
+    !matches!(instruction, Instruction::Jump(_) | Instruction::Complete)
+}
+
+/// Whether an instruction always consumes part of the path before moving on
+fn consumes(instruction: &Instruction) -> bool {
+    match instruction {
+        Instruction::Prefix(_)
+        | Instruction::RootDir
+        | Instruction::CurDir
+        | Instruction::ParentDir
+        | Instruction::AnyCharacter
+        | Instruction::Characters(_) => true,
+        Instruction::LiteralString(literal) | Instruction::SkipToLiteral(literal) => {
+            !literal.is_empty()
+        }
+        _ => false,
+    }
+}
+
+/// The ways a thread can move between instructions without consuming anything
+struct Graph {
+    edges: Vec<Vec<usize>>,
+}
+
+impl Graph {
+    /// Build the graph, leaving out the instructions that match `skip`
+    fn new(program: &Program, skip: impl Fn(&Instruction) -> bool) -> Graph {
+        let instructions = &program.instructions;
+        let mut edges = vec![vec![]; instructions.len()];
+        for (pc, instruction) in instructions.iter().enumerate() {
+            if consumes(instruction) || skip(instruction) {
+                continue;
+            }
+            if falls_through(instruction) {
+                edges[pc].push(pc + 1);
+            }
+            if let Instruction::Jump(target)
+            | Instruction::Alternative(target)
+            | Instruction::BranchIfLessThan(target, ..) = instruction
+            {
+                edges[pc].push(target.0);
+            }
+        }
+
+        // Branching back while a counter is low can only happen so many times, if the counter
+        // is incremented every time around
+        let mut graph = Graph { edges };
+        for (pc, instruction) in instructions.iter().enumerate() {
+            if let Instruction::BranchIfLessThan(target, counter_id, _) = instruction {
+                if !graph.reaches_avoiding(program, target.0, pc, *counter_id) {
+                    graph.edges[pc].retain(|next| *next != target.0);
+                }
+            }
+        }
+        graph
+    }
+
+    /// Whether `to` can be reached from `from` without incrementing `counter_id`
+    fn reaches_avoiding(
+        &self,
+        program: &Program,
+        from: usize,
+        to: usize,
+        counter_id: CounterId,
+    ) -> bool {
+        let mut seen = vec![false; self.edges.len()];
+        let mut stack = vec![from];
+        while let Some(pc) = stack.pop() {
+            if program.instructions[pc] == Instruction::Increment(counter_id) || seen[pc] {
+                continue;
+            }
+            if pc == to {
+                return true;
+            }
+            seen[pc] = true;
+            stack.extend(&self.edges[pc]);
+        }
+        false
+    }
+
+    /// An instruction on a cycle, if there is one
+    fn find_cycle(&self) -> Option<usize> {
+        #[derive(Clone, Copy, PartialEq, Eq)]
+        enum Visit {
+            New,
+            Active,
+            Done,
+        }
+        let mut visits = vec![Visit::New; self.edges.len()];
+        for root in 0..self.edges.len() {
+            if visits[root] != Visit::New {
+                continue;
+            }
+            // Each entry is an instruction and how many of its edges have been followed
+            let mut stack = vec![(root, 0)];
+            visits[root] = Visit::Active;
+            while let Some((pc, edge)) = stack.last_mut() {
+                let pc = *pc;
+                match self.edges[pc].get(*edge) {
+                    Some(&next) => {
+                        *edge += 1;
+                        match visits[next] {
+                            Visit::Active => return Some(next),
+                            Visit::New => {
+                                visits[next] = Visit::Active;
+                                stack.push((next, 0));
+                            }
+                            Visit::Done => (),
+                        }
+                    }
+                    None => {
+                        visits[pc] = Visit::Done;
+                        stack.pop();
+                    }
+                }
+            }
+        }
+        None
+    }
+}
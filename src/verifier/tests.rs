@@ -0,0 +1,144 @@
+use crate::{
+    compiler::{compile_with, CompileOptions, CounterId, Instruction, Program, ProgramOffset},
+    globset::GlobSet,
+    parser::parse,
+    verifier::verify,
+};
+
+fn program(instructions: Vec<Instruction>, counters: u16) -> Program {
+    Program {
+        instructions,
+        counters,
+        ..Program::default()
+    }
+}
+
+fn error(program: &Program) -> String {
+    verify(program).unwrap_err().to_string()
+}
+
+#[test]
+fn compiled_programs_are_valid() {
+    let patterns = [
+        "src/**/*.rs",
+        "**",
+        "**/**",
+        "a**b",
+        "/x/{a,{b,}}/../y",
+        "<*:2>",
+        "<{,a}:0,5>",
+        "<**/:1,3>x",
+        "<<{a,}:2>/:1,3>",
+        "C:/<[0-9]:0>",
+    ];
+    let mut programs = vec![];
+    for pattern in patterns {
+        for (case_insensitive, optimize) in [(false, false), (true, false), (false, true)] {
+            let options = CompileOptions {
+                case_insensitive,
+                optimize,
+            };
+            let program = compile_with(&parse(pattern), &options).unwrap();
+            verify(&program).unwrap_or_else(|err| panic!("{}: {}\n{}", pattern, err, program));
+            programs.push(program);
+        }
+    }
+    verify(GlobSet::new(&programs).unwrap().program()).unwrap();
+}
+
+#[test]
+fn offsets_and_counters_must_exist() {
+    assert_eq!(
+        error(&program(vec![], 0)),
+        "the program has no instructions"
+    );
+    assert_eq!(
+        error(&program(
+            vec![
+                Instruction::Jump(ProgramOffset::PLACEHOLDER),
+                Instruction::Complete
+            ],
+            0
+        )),
+        format!(
+            "[  0]: target {} is past the end of the program",
+            ProgramOffset::PLACEHOLDER
+        )
+    );
+    assert_eq!(
+        error(&program(
+            vec![Instruction::Increment(CounterId(0)), Instruction::Complete],
+            0
+        )),
+        "[  0]: counter #0 doesn't exist, there are only 0"
+    );
+}
+
+#[test]
+fn programs_must_not_run_off_the_end() {
+    assert_eq!(
+        error(&program(
+            vec![Instruction::LiteralString(b"a"[..].into())],
+            0
+        )),
+        "[  0]: the program can run past its end, it should finish with complete or a jump"
+    );
+}
+
+#[test]
+fn loops_must_consume_something() {
+    let loop_error = |pc: usize| {
+        format!(
+            "{}: the program can loop forever here without consuming anything",
+            ProgramOffset(pc)
+        )
+    };
+    // An alternative that comes straight back to itself
+    assert_eq!(
+        error(&program(
+            vec![
+                Instruction::Alternative(ProgramOffset(2)),
+                Instruction::Jump(ProgramOffset(0)),
+                Instruction::Complete,
+            ],
+            0
+        )),
+        loop_error(0)
+    );
+    // Using up a component over and over, without moving on to the next one
+    assert_eq!(
+        error(&program(
+            vec![
+                Instruction::Alternative(ProgramOffset(3)),
+                Instruction::AnyString,
+                Instruction::Jump(ProgramOffset(0)),
+                Instruction::Complete,
+            ],
+            0
+        )),
+        loop_error(0)
+    );
+    // Branching back on a counter that never goes up
+    let counter = CounterId(0);
+    assert_eq!(
+        error(&program(
+            vec![
+                Instruction::Separator,
+                Instruction::BranchIfLessThan(ProgramOffset(0), counter, 3),
+                Instruction::Complete,
+            ],
+            1
+        )),
+        loop_error(0)
+    );
+    // But a counter that does go up is fine
+    verify(&program(
+        vec![
+            Instruction::Increment(counter),
+            Instruction::BranchIfLessThan(ProgramOffset(0), counter, 3),
+            Instruction::Complete,
+        ],
+        1,
+    ))
+    .unwrap();
+}